@@ -25,6 +25,12 @@ mod imp {
 
         #[default(async_channel::bounded(1))]
         pub send_files_channel: AsyncChannel<Vec<String>>,
+        /// Endpoint id and files
+        #[default(async_channel::bounded(1))]
+        pub send_to_device_channel: AsyncChannel<(String, Vec<String>)>,
+        /// Exported along with `send-files`, its state holds the devices that
+        /// files can be sent to directly
+        pub devices_action: OnceCell<gio::SimpleAction>,
     }
 
     #[glib::object_subclass]
@@ -53,7 +59,7 @@ mod imp {
                 .set(window.downgrade())
                 .expect("Window already set.");
 
-            // Setup receiver
+            // Setup receivers
             let rx = self.send_files_channel.1.clone();
             glib::spawn_future_local(glib::clone!(
                 #[weak]
@@ -62,6 +68,14 @@ mod imp {
                     app.main_window().spawn_send_files_receiver(rx).await;
                 }
             ));
+            let rx = self.send_to_device_channel.1.clone();
+            glib::spawn_future_local(glib::clone!(
+                #[weak]
+                app,
+                async move {
+                    app.main_window().spawn_send_to_device_receiver(rx).await;
+                }
+            ));
 
             if !self.start_in_background.get() {
                 app.main_window().present();
@@ -143,12 +157,84 @@ mod imp {
                 ));
                 group.add_action(&send_files_action);
             }
+            {
+                // ('send-files-to-device', [<('endpoint id', ['~/file_1'])>], {})
+                let send_to_device_action = gio::SimpleAction::new(
+                    "send-files-to-device",
+                    Some(&<(String, Vec<String>)>::static_variant_type()),
+                );
+                send_to_device_action.connect_activate(clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |_, variant| {
+                        let Some((endpoint_id, files)) =
+                            variant.and_then(|it| it.get::<(String, Vec<String>)>())
+                        else {
+                            tracing::warn!("Invalid parameter for send-files-to-device");
+                            return;
+                        };
+                        glib::spawn_future_local(clone!(
+                            #[weak]
+                            this,
+                            async move {
+                                _ = this
+                                    .send_to_device_channel
+                                    .0
+                                    .send((endpoint_id, files))
+                                    .await
+                                    .inspect_err(|err| tracing::warn!("{err:#}"));
+                            }
+                        ));
+                    }
+                ));
+                group.add_action(&send_to_device_action);
+
+                // The state is an array of (endpoint id, name, is nearby), it's
+                // only meant to be read through `Describe`
+                let devices_action = gio::SimpleAction::new_stateful(
+                    "devices",
+                    None,
+                    &Vec::<(String, String, bool)>::new().to_variant(),
+                );
+                devices_action.set_enabled(false);
+                group.add_action(&devices_action);
+                _ = self.devices_action.set(devices_action);
+            }
 
             connection.export_action_group(&format!("{object_path}/Share"), &group)?;
 
             Ok(())
         }
 
+        /// Files given on the command line, or through "Open With", are sent
+        /// the same way as the ones from the file manager plugins.
+        fn open(&self, files: &[gio::File], _hint: &str) {
+            debug!(
+                count = files.len(),
+                "GtkApplication<PacketApplication>::open"
+            );
+
+            // Sets up the window if this is the first launch
+            self.obj().activate();
+
+            let paths = files
+                .iter()
+                .filter_map(|it| it.path())
+                .map(|it| it.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            if paths.is_empty() {
+                return;
+            }
+
+            let tx = self.send_files_channel.0.clone();
+            glib::spawn_future_local(async move {
+                _ = tx
+                    .send(paths)
+                    .await
+                    .inspect_err(|err| tracing::warn!("{err:#}"));
+            });
+        }
+
         fn handle_local_options(&self, options: &glib::VariantDict) -> glib::ExitCode {
             self.obj().handle_command_line(options);
             self.parent_handle_local_options(options)
@@ -175,6 +261,14 @@ impl PacketApplication {
         self.imp().window.get().unwrap().upgrade().unwrap()
     }
 
+    /// Publishes the devices that files can be sent to directly, as the
+    /// state of the exported `devices` action.
+    pub fn set_devices(&self, devices: Vec<(String, String, bool)>) {
+        if let Some(action) = self.imp().devices_action.get() {
+            action.set_state(&devices.to_variant());
+        }
+    }
+
     fn setup_gactions(&self) {
         // Quit
         let action_quit = gio::ActionEntry::builder("quit")
@@ -206,6 +300,8 @@ impl PacketApplication {
         self.set_accels_for_action("window.close", &["<Control>w"]);
         self.set_accels_for_action("win.preferences", &["<Control>comma"]);
         self.set_accels_for_action("win.help", &["F1"]);
+        self.set_accels_for_action("win.show-logs", &["<Control><Shift>l"]);
+        self.set_accels_for_action("win.screenshot", &["<Control><Shift>s"]);
     }
 
     fn setup_css(&self) {
@@ -248,10 +344,12 @@ impl PacketApplication {
                 // Translators: Replace "translator-credits" with your names, one name per line
                 "translator-credits",
             ))
+            // Shows up under the "Troubleshooting" section, where the user can
+            // copy or save the report for attaching to bug reports
+            .debug_info(self.main_window().troubleshooting_report())
+            .debug_info_filename("packet-debug-info.txt")
             .build();
 
-        // TODO: Add a troubleshoot section where the user can get a copy of the logs
-
         dialog.add_acknowledgement_section(
             Some(&gettext("Similar Projects")),
             &[
@@ -301,6 +399,7 @@ impl Default for PacketApplication {
     fn default() -> Self {
         glib::Object::builder()
             .property("application-id", APP_ID)
+            .property("flags", gio::ApplicationFlags::HANDLES_OPEN)
             .property("resource-base-path", "/io/github/nozwock/Packet/")
             .build()
     }
@@ -0,0 +1,283 @@
+use std::{
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use rqs_lib::channel::ChannelMessage;
+use tokio::sync::{broadcast, mpsc, watch};
+
+/// What a backend hands back once it's up and running.
+#[derive(Debug)]
+pub struct BackendChannels {
+    /// Outbound transfers are started by sending a `SendInfo` here.
+    pub file_sender: mpsc::Sender<rqs_lib::SendInfo>,
+    /// Fires whenever a nearby device is trying to share something over BLE.
+    pub ble_receiver: broadcast::Receiver<()>,
+}
+
+/// Everything the service task needs from a Quick Share implementation.
+///
+/// Consent and cancel actions are sent as `Message::Lib` over
+/// [`message_sender`](Self::message_sender), which is also where the transfer
+/// events come from. This mirrors how `rqs_lib` does it, and lets a scripted
+/// backend stand in for it in tests.
+pub trait QuickShareBackend: Send + 'static {
+    fn run(&mut self) -> impl Future<Output = anyhow::Result<BackendChannels>> + Send;
+    fn stop(&mut self) -> impl Future<Output = ()> + Send;
+
+    fn start_discovery(
+        &mut self,
+        discovery_tx: broadcast::Sender<rqs_lib::EndpointInfo>,
+    ) -> anyhow::Result<()>;
+    fn stop_discovery(&mut self);
+
+    fn set_visibility(&mut self, visibility: rqs_lib::Visibility);
+    fn set_download_path(&mut self, path: PathBuf);
+
+    fn message_sender(&self) -> broadcast::Sender<ChannelMessage>;
+    fn visibility_sender(&self) -> Arc<Mutex<watch::Sender<rqs_lib::Visibility>>>;
+    fn port_number(&self) -> Option<u32>;
+}
+
+impl QuickShareBackend for rqs_lib::RQS {
+    async fn run(&mut self) -> anyhow::Result<BackendChannels> {
+        let (file_sender, ble_receiver) = rqs_lib::RQS::run(self).await?;
+        Ok(BackendChannels {
+            file_sender,
+            ble_receiver,
+        })
+    }
+
+    async fn stop(&mut self) {
+        rqs_lib::RQS::stop(self).await
+    }
+
+    fn start_discovery(
+        &mut self,
+        discovery_tx: broadcast::Sender<rqs_lib::EndpointInfo>,
+    ) -> anyhow::Result<()> {
+        self.discovery(discovery_tx)
+    }
+
+    fn stop_discovery(&mut self) {
+        rqs_lib::RQS::stop_discovery(self)
+    }
+
+    fn set_visibility(&mut self, visibility: rqs_lib::Visibility) {
+        self.change_visibility(visibility)
+    }
+
+    fn set_download_path(&mut self, path: PathBuf) {
+        rqs_lib::RQS::set_download_path(self, Some(path))
+    }
+
+    fn message_sender(&self) -> broadcast::Sender<ChannelMessage> {
+        self.message_sender.clone()
+    }
+
+    fn visibility_sender(&self) -> Arc<Mutex<watch::Sender<rqs_lib::Visibility>>> {
+        self.visibility_sender.clone()
+    }
+
+    fn port_number(&self) -> Option<u32> {
+        self.port_number
+    }
+}
+
+/// A backend that plays back a fixed sequence of events, for driving the
+/// transfer flows without any networking.
+#[cfg(test)]
+pub mod scripted {
+    use std::mem;
+
+    use rqs_lib::channel::{Message, MessageClient, TransferAction, TransferKind};
+    use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    pub enum Step {
+        /// Broadcasts the message, as if it came from the other device.
+        Emit(Box<ChannelMessage>),
+        /// Waits until the UI sends this action for any transfer.
+        WaitForAction(TransferAction),
+        /// Waits until the UI asks for something to be sent.
+        WaitForSend,
+    }
+
+    /// Builds a client message for the transfer with the given id.
+    pub fn event(
+        id: &str,
+        kind: TransferKind,
+        state: rqs_lib::TransferState,
+        ack_bytes: u64,
+    ) -> ChannelMessage {
+        ChannelMessage {
+            id: id.to_string(),
+            msg: Message::Client(MessageClient {
+                kind,
+                state: Some(state),
+                metadata: Some(rqs_lib::hdl::info::TransferMetadata {
+                    id: id.to_string(),
+                    pin_code: Some("1234".into()),
+                    payload_kind: rqs_lib::hdl::info::TransferPayloadKind::Files,
+                    total_bytes: 100,
+                    ack_bytes,
+                    ..Default::default()
+                }),
+            }),
+        }
+    }
+
+    /// What the UI did while the script was playing.
+    #[derive(Debug, Default)]
+    pub struct Recorded {
+        pub actions: Vec<TransferAction>,
+        pub sends: Vec<rqs_lib::SendInfo>,
+        pub visibility: Vec<rqs_lib::Visibility>,
+        pub discovery_running: bool,
+        pub stopped: bool,
+    }
+
+    #[derive(Debug)]
+    pub struct ScriptedBackend {
+        steps: Vec<Step>,
+        run_error: Option<anyhow::Error>,
+        message_sender: broadcast::Sender<ChannelMessage>,
+        visibility_sender: Arc<Mutex<watch::Sender<rqs_lib::Visibility>>>,
+        ble_sender: broadcast::Sender<()>,
+        recorded: Arc<Mutex<Recorded>>,
+        script_handle: Option<JoinHandle<()>>,
+    }
+
+    impl ScriptedBackend {
+        pub fn new(steps: Vec<Step>) -> Self {
+            Self {
+                steps,
+                run_error: None,
+                message_sender: broadcast::channel(32).0,
+                visibility_sender: Arc::new(Mutex::new(
+                    watch::channel(rqs_lib::Visibility::Visible).0,
+                )),
+                ble_sender: broadcast::channel(1).0,
+                recorded: Default::default(),
+                script_handle: None,
+            }
+        }
+
+        /// Makes `run` fail with the given error instead of playing the script.
+        pub fn failing(err: anyhow::Error) -> Self {
+            let mut backend = Self::new(vec![]);
+            backend.run_error = Some(err);
+            backend
+        }
+
+        pub fn recorded(&self) -> Arc<Mutex<Recorded>> {
+            self.recorded.clone()
+        }
+
+        async fn play(
+            steps: Vec<Step>,
+            message_sender: broadcast::Sender<ChannelMessage>,
+            mut actions_rx: broadcast::Receiver<ChannelMessage>,
+            mut file_rx: mpsc::Receiver<rqs_lib::SendInfo>,
+            recorded: Arc<Mutex<Recorded>>,
+        ) {
+            for step in steps {
+                match step {
+                    Step::Emit(msg) => {
+                        _ = message_sender.send(*msg);
+                    }
+                    Step::WaitForAction(expected) => loop {
+                        let action = match actions_rx.recv().await {
+                            Ok(msg) => match msg.msg {
+                                Message::Lib { action } => action,
+                                // Our own events
+                                Message::Client(_) => continue,
+                            },
+                            Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => return,
+                        };
+
+                        let is_expected =
+                            mem::discriminant(&action) == mem::discriminant(&expected);
+                        recorded.lock().unwrap().actions.push(action);
+                        if is_expected {
+                            break;
+                        }
+                    },
+                    Step::WaitForSend => match file_rx.recv().await {
+                        Some(send_info) => recorded.lock().unwrap().sends.push(send_info),
+                        None => return,
+                    },
+                }
+            }
+        }
+    }
+
+    impl QuickShareBackend for ScriptedBackend {
+        async fn run(&mut self) -> anyhow::Result<BackendChannels> {
+            if let Some(err) = self.run_error.take() {
+                return Err(err);
+            }
+
+            let (file_sender, file_rx) = mpsc::channel(1);
+            // Subscribe right away, so that no action sent after `run` is missed
+            let actions_rx = self.message_sender.subscribe();
+            self.script_handle = Some(tokio::spawn(Self::play(
+                mem::take(&mut self.steps),
+                self.message_sender.clone(),
+                actions_rx,
+                file_rx,
+                self.recorded.clone(),
+            )));
+
+            Ok(BackendChannels {
+                file_sender,
+                ble_receiver: self.ble_sender.subscribe(),
+            })
+        }
+
+        async fn stop(&mut self) {
+            if let Some(handle) = self.script_handle.take() {
+                handle.abort();
+            }
+            self.recorded.lock().unwrap().stopped = true;
+        }
+
+        fn start_discovery(
+            &mut self,
+            _discovery_tx: broadcast::Sender<rqs_lib::EndpointInfo>,
+        ) -> anyhow::Result<()> {
+            self.recorded.lock().unwrap().discovery_running = true;
+            Ok(())
+        }
+
+        fn stop_discovery(&mut self) {
+            self.recorded.lock().unwrap().discovery_running = false;
+        }
+
+        fn set_visibility(&mut self, visibility: rqs_lib::Visibility) {
+            self.recorded.lock().unwrap().visibility.push(visibility);
+            self.visibility_sender
+                .lock()
+                .unwrap()
+                .send_replace(visibility);
+        }
+
+        fn set_download_path(&mut self, _path: PathBuf) {}
+
+        fn message_sender(&self) -> broadcast::Sender<ChannelMessage> {
+            self.message_sender.clone()
+        }
+
+        fn visibility_sender(&self) -> Arc<Mutex<watch::Sender<rqs_lib::Visibility>>> {
+            self.visibility_sender.clone()
+        }
+
+        fn port_number(&self) -> Option<u32> {
+            None
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::{cmp::Reverse, path::PathBuf};
+
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Only the most recently seen devices are remembered.
+const MAX_KNOWN_DEVICES: usize = 20;
+const STORE_FILE_NAME: &str = "deferred-sends.json";
+
+/// A device that was discovered at some point, to send to later on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownDevice {
+    pub id: String,
+    pub name: String,
+    /// Unix timestamp
+    pub last_seen: i64,
+}
+
+/// Files waiting on a device to show up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeferredSend {
+    pub id: String,
+    pub device_id: String,
+    pub device_name: String,
+    pub files: Vec<String>,
+    /// Unix timestamp
+    pub created_at: i64,
+    /// Unix timestamp
+    pub expires_at: i64,
+    /// Id of the watch folder the files came from, if any
+    #[serde(default)]
+    pub watch_folder: Option<String>,
+}
+
+impl DeferredSend {
+    pub fn new(device: &KnownDevice, files: Vec<String>, expires_in_hours: u32) -> Self {
+        let now = now();
+        Self {
+            id: glib::uuid_string_random().to_string(),
+            device_id: device.id.clone(),
+            device_name: device.name.clone(),
+            files,
+            created_at: now,
+            expires_at: now + expires_in_hours as i64 * 60 * 60,
+            watch_folder: None,
+        }
+    }
+
+    /// Only the endpoint id is trusted, since any device nearby could be
+    /// going by the same name.
+    pub fn is_for(&self, endpoint_info: &rqs_lib::EndpointInfo) -> bool {
+        endpoint_info.id == self.device_id
+    }
+
+    /// Endpoint ids aren't stable across restarts of the other device, so
+    /// this might be the device under a new id. It's up to the user to confirm
+    /// that before anything's sent to it.
+    pub fn is_maybe_for(&self, endpoint_info: &rqs_lib::EndpointInfo) -> bool {
+        !self.is_for(endpoint_info)
+            && endpoint_info
+                .name
+                .as_ref()
+                .is_some_and(|it| *it == self.device_name)
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// Known devices and deferred sends, persisted across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeferredSends {
+    #[serde(default)]
+    pub devices: Vec<KnownDevice>,
+    #[serde(default)]
+    pub sends: Vec<DeferredSend>,
+    /// The device files were last sent to from the recipients dialog
+    #[serde(default)]
+    pub last_used_device: Option<KnownDevice>,
+}
+
+impl DeferredSends {
+    pub fn path() -> PathBuf {
+        glib::user_data_dir().join("packet").join(STORE_FILE_NAME)
+    }
+
+    pub fn load() -> Self {
+        utils::load_json_or_default(&Self::path())
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        utils::save_json(&Self::path(), self)
+    }
+
+    /// Records the device as seen just now. Returns whether the store changed
+    /// in a way worth saving, i.e. not just the timestamp.
+    pub fn remember_device(&mut self, id: &str, name: &str) -> bool {
+        let now = now();
+
+        // A device with a new id is a new device, as far as can be told here
+        if let Some(device) = self.devices.iter_mut().find(|it| it.id == id) {
+            let is_changed = device.name != name;
+            device.name = name.to_string();
+            device.last_seen = now;
+
+            return is_changed;
+        }
+
+        self.devices.push(KnownDevice {
+            id: id.to_string(),
+            name: name.to_string(),
+            last_seen: now,
+        });
+        self.devices.sort_by_key(|it| Reverse(it.last_seen));
+        self.devices.truncate(MAX_KNOWN_DEVICES);
+
+        true
+    }
+
+    /// Moves the sends waiting on a device over to its new endpoint id, once
+    /// the user confirmed that it's the same device.
+    pub fn move_device(&mut self, old_id: &str, device: &KnownDevice) {
+        for send in self.sends.iter_mut().filter(|it| it.device_id == old_id) {
+            send.device_id = device.id.clone();
+            send.device_name = device.name.clone();
+        }
+        if let Some(last_used_device) = &mut self.last_used_device
+            && last_used_device.id == old_id
+        {
+            *last_used_device = device.clone();
+        }
+        self.devices.retain(|it| it.id != old_id);
+    }
+
+    pub fn add(&mut self, send: DeferredSend) {
+        self.sends.push(send);
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<DeferredSend> {
+        let pos = self.sends.iter().position(|it| it.id == id)?;
+        Some(self.sends.remove(pos))
+    }
+
+    /// A send of the watch folder that hasn't been started yet, for more of its
+    /// files to go along with.
+    pub fn pending_for_watch_folder(
+        &mut self,
+        watch_folder_id: &str,
+        active_id: Option<&str>,
+    ) -> Option<&mut DeferredSend> {
+        self.sends.iter_mut().find(|it| {
+            it.watch_folder.as_deref() == Some(watch_folder_id) && Some(it.id.as_str()) != active_id
+        })
+    }
+
+    /// Removes and returns the sends that are past their expiry.
+    pub fn take_expired(&mut self) -> Vec<DeferredSend> {
+        let now = now();
+        let (expired, sends) = std::mem::take(&mut self.sends)
+            .into_iter()
+            .partition(|it| it.is_expired(now));
+        self.sends = sends;
+
+        expired
+    }
+
+    pub fn devices_by_last_seen(&self) -> Vec<KnownDevice> {
+        let mut devices = self.devices.clone();
+        devices.sort_by_key(|it| Reverse(it.last_seen));
+        devices
+    }
+}
+
+pub fn now() -> i64 {
+    glib::DateTime::now_utc()
+        .map(|it| it.to_unix())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(id: &str, name: &str) -> rqs_lib::EndpointInfo {
+        rqs_lib::EndpointInfo {
+            id: id.into(),
+            name: Some(name.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sends_only_match_the_endpoint_id() {
+        let mut deferred_sends = DeferredSends::default();
+        deferred_sends.remember_device("a1", "Pixel");
+        let send = DeferredSend::new(&deferred_sends.devices[0], vec![], 1);
+
+        assert!(send.is_for(&endpoint("a1", "Renamed")));
+        assert!(!send.is_for(&endpoint("b2", "Pixel")));
+        assert!(send.is_maybe_for(&endpoint("b2", "Pixel")));
+        assert!(!send.is_maybe_for(&endpoint("a1", "Pixel")));
+        assert!(!send.is_maybe_for(&endpoint("c3", "Galaxy")));
+    }
+
+    #[test]
+    fn devices_sharing_a_name_stay_apart() {
+        let mut deferred_sends = DeferredSends::default();
+        assert!(deferred_sends.remember_device("a1", "Pixel"));
+        assert!(deferred_sends.remember_device("b2", "Pixel"));
+        assert!(!deferred_sends.remember_device("a1", "Pixel"));
+
+        let mut ids = deferred_sends
+            .devices
+            .iter()
+            .map(|it| it.id.as_str())
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, ["a1", "b2"]);
+    }
+
+    #[test]
+    fn confirmed_device_takes_over_sends() {
+        let mut deferred_sends = DeferredSends::default();
+        deferred_sends.remember_device("a1", "Pixel");
+        deferred_sends.remember_device("b2", "Pixel");
+        let old_device = deferred_sends
+            .devices
+            .iter()
+            .find(|it| it.id == "a1")
+            .cloned()
+            .unwrap();
+        deferred_sends.add(DeferredSend::new(&old_device, vec![], 1));
+        deferred_sends.last_used_device = Some(old_device);
+
+        let new_device = KnownDevice {
+            id: "b2".into(),
+            name: "Pixel".into(),
+            last_seen: now(),
+        };
+        deferred_sends.move_device("a1", &new_device);
+
+        assert!(deferred_sends.sends[0].is_for(&endpoint("b2", "Pixel")));
+        assert_eq!(deferred_sends.last_used_device, Some(new_device));
+        assert_eq!(deferred_sends.devices.len(), 1);
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use formatx::formatx;
+use gettextrs::gettext;
+use gtk::gio;
+use gtk::prelude::*;
+
+use crate::monitors;
+
+/// The mDNS service type Quick Share devices advertise themselves under.
+const QUICK_SHARE_SERVICE_TYPE: &str = "_FC9F5ED42C8A._tcp.local.";
+const MDNS_BROWSE_TIMEOUT: Duration = Duration::from_secs(5);
+const PORT_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+const FIREWALLD_BUS_NAME: &str = "org.fedoraproject.FirewallD1";
+const FIREWALLD_OBJECT_PATH: &str = "/org/fedoraproject/FirewallD1";
+const UFW_CONFIG_PATH: &str = "/etc/ufw/ufw.conf";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Bluetooth,
+    Network,
+    MdnsAdvertisement,
+    PortReachable,
+    Firewall,
+}
+
+impl Check {
+    pub const ALL: [Check; 5] = [
+        Check::Bluetooth,
+        Check::Network,
+        Check::MdnsAdvertisement,
+        Check::PortReachable,
+        Check::Firewall,
+    ];
+
+    pub fn title(&self) -> String {
+        match self {
+            Check::Bluetooth => gettext("Bluetooth"),
+            Check::Network => gettext("Network"),
+            Check::MdnsAdvertisement => gettext("Visibility on the Network"),
+            Check::PortReachable => gettext("Incoming Connections"),
+            Check::Firewall => gettext("Firewall"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Passed,
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub status: CheckStatus,
+    pub summary: String,
+    /// Suggested fix, only set for checks that didn't pass.
+    pub fix: Option<String>,
+}
+
+impl CheckResult {
+    fn passed(summary: String) -> Self {
+        Self {
+            status: CheckStatus::Passed,
+            summary,
+            fix: None,
+        }
+    }
+
+    fn warning(summary: String, fix: String) -> Self {
+        Self {
+            status: CheckStatus::Warning,
+            summary,
+            fix: Some(fix),
+        }
+    }
+
+    fn failed(summary: String, fix: String) -> Self {
+        Self {
+            status: CheckStatus::Failed,
+            summary,
+            fix: Some(fix),
+        }
+    }
+
+    /// For when a check couldn't run to completion at all.
+    pub fn incomplete() -> Self {
+        Self::warning(
+            gettext("The check couldn't be completed"),
+            gettext("Run the checks again, or look at the logs for more details"),
+        )
+    }
+}
+
+/// Outcome of looking for our own advertisement on the network.
+#[derive(Debug, Clone)]
+pub struct MdnsCheckResult {
+    pub result: CheckResult,
+    /// The port other devices are being told to connect to, if our
+    /// advertisement was found.
+    pub advertised_port: Option<u32>,
+}
+
+pub async fn check_bluetooth(conn: Option<zbus::Connection>) -> CheckResult {
+    let Some(conn) = conn else {
+        return CheckResult::failed(
+            gettext("Couldn't connect to the system bus"),
+            gettext("Make sure Packet is allowed to talk to the Bluetooth service"),
+        );
+    };
+
+    match monitors::is_bluetooth_powered(&conn).await {
+        Ok(true) => CheckResult::passed(gettext("Bluetooth is turned on")),
+        Ok(false) => CheckResult::warning(
+            gettext("Bluetooth is turned off"),
+            gettext(
+                "Turn on Bluetooth, nearby devices use it to let each other know they're sharing",
+            ),
+        ),
+        Err(err) => {
+            tracing::warn!(err = format!("{err:#}"), "Bluetooth check failed");
+            CheckResult::failed(
+                gettext("No Bluetooth adapter found"),
+                gettext(
+                    "Make sure a Bluetooth adapter is connected and the Bluetooth service is running",
+                ),
+            )
+        }
+    }
+}
+
+pub fn check_network(monitor: &gio::NetworkMonitor) -> CheckResult {
+    if !monitor.is_network_available() {
+        return CheckResult::failed(
+            gettext("Not connected to a network"),
+            gettext("Connect to the same Wi-Fi network as the other device"),
+        );
+    }
+
+    match monitor.connectivity() {
+        // Local connectivity is all that's needed to share with nearby devices
+        gio::NetworkConnectivity::Local
+        | gio::NetworkConnectivity::Limited
+        | gio::NetworkConnectivity::Portal => {
+            CheckResult::passed(gettext("Connected to a local network"))
+        }
+        _ => CheckResult::passed(gettext("Connected to a network")),
+    }
+}
+
+/// Browses for Quick Share services the same way a phone would, and looks for
+/// the one that's pointing back at this machine.
+///
+/// This blocks for up to [`MDNS_BROWSE_TIMEOUT`].
+pub fn check_mdns_advertisement(is_visible: bool, port: Option<u32>) -> MdnsCheckResult {
+    if !is_visible {
+        return MdnsCheckResult {
+            result: CheckResult::warning(
+                gettext("This device is hidden"),
+                gettext("Turn on visibility so that nearby devices can find this device"),
+            ),
+            advertised_port: None,
+        };
+    }
+
+    let found = || -> anyhow::Result<Option<u32>> {
+        let local_addrs = local_addresses()?;
+
+        let daemon = mdns_sd::ServiceDaemon::new()?;
+        let receiver = daemon.browse(QUICK_SHARE_SERVICE_TYPE)?;
+
+        let deadline = Instant::now() + MDNS_BROWSE_TIMEOUT;
+        let mut advertised_port = None;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            match receiver.recv_timeout(remaining) {
+                Ok(mdns_sd::ServiceEvent::ServiceResolved(info)) => {
+                    tracing::debug!(fullname = info.get_fullname(), "Resolved service");
+
+                    let is_ours = info
+                        .get_addresses()
+                        .iter()
+                        .any(|addr| local_addrs.contains(&IpAddr::from(*addr)))
+                        && port.is_none_or(|port| port == info.get_port() as u32);
+                    if is_ours {
+                        advertised_port = Some(info.get_port() as u32);
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+
+        _ = daemon.shutdown();
+
+        Ok(advertised_port)
+    }();
+
+    match found {
+        Ok(Some(advertised_port)) => MdnsCheckResult {
+            result: CheckResult::passed(gettext("This device is being advertised")),
+            advertised_port: Some(advertised_port),
+        },
+        Ok(None) => MdnsCheckResult {
+            result: CheckResult::failed(
+                gettext("This device couldn't be found on the network"),
+                gettext(
+                    "Make sure the network allows devices to discover each other, public and guest networks often don't",
+                ),
+            ),
+            advertised_port: None,
+        },
+        Err(err) => {
+            tracing::warn!(err = format!("{err:#}"), "mDNS advertisement check failed");
+            MdnsCheckResult {
+                result: CheckResult::failed(
+                    gettext("Couldn't look for devices on the network"),
+                    gettext("Make sure no other application is blocking the mDNS port 5353"),
+                ),
+                advertised_port: None,
+            }
+        }
+    }
+}
+
+/// Tries connecting to the service port through each of the non-loopback
+/// interfaces, which is roughly what another device on the network would do.
+///
+/// Must be run within the Tokio runtime.
+pub async fn check_port_reachable(port: Option<u32>) -> CheckResult {
+    let Some(port) = port.and_then(|it| u16::try_from(it).ok()) else {
+        return CheckResult::warning(
+            gettext("Couldn't find the port Packet is listening on"),
+            gettext("Set a static port in Preferences and run the checks again"),
+        );
+    };
+
+    // IPv6 link-local addresses can't be connected to without a scope id, and
+    // the other devices connect over IPv4 anyways
+    let local_addrs = match local_addresses() {
+        Ok(local_addrs) => local_addrs
+            .into_iter()
+            .filter(IpAddr::is_ipv4)
+            .collect::<Vec<_>>(),
+        Err(err) => {
+            tracing::warn!(err = format!("{err:#}"), "Couldn't list network interfaces");
+            return CheckResult::incomplete();
+        }
+    };
+    if local_addrs.is_empty() {
+        return CheckResult::failed(
+            gettext("No network interface to connect through"),
+            gettext("Connect to the same Wi-Fi network as the other device"),
+        );
+    }
+
+    let mut reachable = 0;
+    for addr in &local_addrs {
+        match tokio::time::timeout(
+            PORT_CONNECT_TIMEOUT,
+            tokio::net::TcpStream::connect((*addr, port)),
+        )
+        .await
+        {
+            Ok(Ok(_)) => reachable += 1,
+            Ok(Err(err)) => {
+                tracing::debug!(%addr, port, %err, "Couldn't connect to the service port")
+            }
+            Err(_) => tracing::debug!(%addr, port, "Timed out connecting to the service port"),
+        }
+    }
+
+    if reachable == 0 {
+        CheckResult::failed(
+            formatx!(gettext("Nothing is accepting connections on port {}"), port)
+                .unwrap_or_else(|_| "badly formatted locale string".into()),
+            gettext("Restart Packet, or choose a different static port in Preferences"),
+        )
+    } else if reachable < local_addrs.len() {
+        CheckResult::passed(
+            formatx!(
+                gettext("Port {} is reachable through some of the network interfaces"),
+                port
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+        )
+    } else {
+        CheckResult::passed(
+            formatx!(gettext("Port {} is accepting connections"), port)
+                .unwrap_or_else(|_| "badly formatted locale string".into()),
+        )
+    }
+}
+
+/// Looks for firewalld over D-Bus and falls back to ufw's config file, since
+/// those are what most distros ship with.
+pub async fn check_firewall(conn: Option<zbus::Connection>, port: Option<u32>) -> CheckResult {
+    if let Some(conn) = conn {
+        match query_firewalld(&conn, port).await {
+            Ok(Some(result)) => return result,
+            Ok(None) => {}
+            Err(err) => {
+                tracing::warn!(err = format!("{err:#}"), "Couldn't query firewalld");
+                return CheckResult::warning(
+                    gettext("Couldn't check the firewalld rules"),
+                    allow_through_firewall_hint(port),
+                );
+            }
+        }
+    }
+
+    let is_ufw_enabled = fs_err::read_to_string(UFW_CONFIG_PATH)
+        .map(|it| {
+            it.lines()
+                .any(|line| line.trim().eq_ignore_ascii_case("ENABLED=yes"))
+        })
+        .unwrap_or_default();
+    if is_ufw_enabled {
+        return CheckResult::warning(
+            gettext("ufw is enabled and might be blocking other devices"),
+            allow_through_firewall_hint(port),
+        );
+    }
+
+    CheckResult::passed(gettext("No firewall detected"))
+}
+
+/// Returns `None` if firewalld isn't running.
+async fn query_firewalld(
+    conn: &zbus::Connection,
+    port: Option<u32>,
+) -> anyhow::Result<Option<CheckResult>> {
+    let dbus_proxy = zbus::fdo::DBusProxy::new(conn).await?;
+    if !dbus_proxy
+        .name_has_owner(FIREWALLD_BUS_NAME.try_into()?)
+        .await?
+    {
+        return Ok(None);
+    }
+
+    let proxy = zbus::Proxy::new(
+        conn,
+        FIREWALLD_BUS_NAME,
+        FIREWALLD_OBJECT_PATH,
+        FIREWALLD_BUS_NAME,
+    )
+    .await?;
+    let zone: String = proxy.call("getDefaultZone", &()).await?;
+
+    let zone_proxy = zbus::Proxy::new(
+        conn,
+        FIREWALLD_BUS_NAME,
+        FIREWALLD_OBJECT_PATH,
+        "org.fedoraproject.FirewallD1.zone",
+    )
+    .await?;
+    let is_mdns_allowed: bool = zone_proxy
+        .call("queryService", &(zone.as_str(), "mdns"))
+        .await?;
+
+    let Some(port) = port else {
+        return Ok(Some(CheckResult::warning(
+            formatx!(
+                gettext(
+                    "firewalld is running and Packet is using a random port in the \"{}\" zone"
+                ),
+                zone.as_str()
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+            allow_through_firewall_hint(None),
+        )));
+    };
+
+    let is_port_allowed: bool = zone_proxy
+        .call("queryPort", &(zone.as_str(), port.to_string(), "tcp"))
+        .await?;
+
+    let result = match (is_mdns_allowed, is_port_allowed) {
+        (true, true) => CheckResult::passed(
+            formatx!(
+                gettext("firewalld allows Packet in the \"{}\" zone"),
+                zone.as_str()
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+        ),
+        _ => CheckResult::failed(
+            formatx!(
+                gettext("firewalld is blocking Packet in the \"{}\" zone"),
+                zone.as_str()
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+            allow_through_firewall_hint(Some(port)),
+        ),
+    };
+
+    Ok(Some(result))
+}
+
+fn allow_through_firewall_hint(port: Option<u32>) -> String {
+    match port {
+        Some(port) => formatx!(
+            gettext("Allow mDNS (UDP port 5353) and TCP port {} through the firewall"),
+            port
+        )
+        .unwrap_or_else(|_| "badly formatted locale string".into()),
+        None => gettext(
+            "Set a static port in Preferences, then allow it along with mDNS (UDP port 5353) through the firewall",
+        ),
+    }
+}
+
+/// Addresses of all the non-loopback network interfaces.
+fn local_addresses() -> anyhow::Result<HashSet<IpAddr>> {
+    Ok(if_addrs::get_if_addrs()
+        .map_err(|err| anyhow!(err).context("Failed to list network interfaces"))?
+        .into_iter()
+        .filter(|it| !it.is_loopback())
+        .map(|it| it.ip())
+        .collect())
+}
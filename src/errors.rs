@@ -0,0 +1,265 @@
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use gettextrs::gettext;
+use gtk::glib;
+
+/// Known reasons for the service failing to start, used to show something
+/// more helpful than the raw error chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartupErrorKind {
+    PortInUse,
+    /// Binding the listening socket was refused, either since it's a
+    /// privileged port or by a sandbox or security policy.
+    ListenNotPermitted,
+    AddressNotAvailable,
+    BluetoothUnavailable,
+    DownloadFolderNotWritable,
+    MdnsConflict,
+    Unknown,
+}
+
+/// What the user can do about a [`StartupErrorKind`], besides retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartupErrorAction {
+    ChangePort,
+    PickFolder,
+    OpenDiagnostics,
+}
+
+impl StartupErrorKind {
+    /// Walks the error chain looking for a known cause.
+    pub fn classify(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if cause.downcast_ref::<DownloadFolderError>().is_some() {
+                return Self::DownloadFolderNotWritable;
+            }
+
+            // rqs_lib uses BlueZ both directly and through btleplug. These only
+            // match if the versions in Cargo.toml are the same as rqs_lib's,
+            // which `shares_bluetooth_crates_with_rqs_lib` makes sure of
+            if cause.downcast_ref::<bluer::Error>().is_some()
+                || cause.downcast_ref::<btleplug::Error>().is_some()
+            {
+                return Self::BluetoothUnavailable;
+            }
+
+            if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+                match io_err.kind() {
+                    ErrorKind::AddrInUse => return Self::PortInUse,
+                    ErrorKind::AddrNotAvailable => return Self::AddressNotAvailable,
+                    // Only thing that's touched with permissions at startup,
+                    // other than the download folder, is the listening socket
+                    ErrorKind::PermissionDenied => return Self::ListenNotPermitted,
+                    _ => {}
+                }
+            }
+        }
+
+        // Not all errors make it through rqs_lib with their source intact, so
+        // fallback to matching against the messages
+        let chain = format!("{err:#}").to_lowercase();
+        if chain.contains("address already in use") {
+            Self::PortInUse
+        } else if chain.contains("cannot assign requested address")
+            || chain.contains("address not available")
+        {
+            Self::AddressNotAvailable
+        } else if chain.contains("mdns") || chain.contains("multicast") {
+            Self::MdnsConflict
+        } else if chain.contains("bluetooth") || chain.contains("bluez") {
+            Self::BluetoothUnavailable
+        } else {
+            Self::Unknown
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match self {
+            StartupErrorKind::PortInUse => gettext("Port Unavailable"),
+            StartupErrorKind::ListenNotPermitted => gettext("Not Allowed to Listen"),
+            StartupErrorKind::AddressNotAvailable => gettext("No Network Address"),
+            StartupErrorKind::BluetoothUnavailable => gettext("Bluetooth Unavailable"),
+            StartupErrorKind::DownloadFolderNotWritable => gettext("Can't Save Files"),
+            StartupErrorKind::MdnsConflict => gettext("Network Discovery Unavailable"),
+            // Translators: This is the title of the status page where some error occurred while trying to setup Packet
+            StartupErrorKind::Unknown => gettext("Couldn't Set Up"),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            StartupErrorKind::PortInUse => gettext(
+                "The static port is already in use by another application, or is reserved. Choose a different port above 1024.",
+            ),
+            StartupErrorKind::ListenNotPermitted => gettext(
+                "Packet isn't allowed to listen for connections. Ports below 1024 need extra privileges, so choose a higher static port if one's set. Otherwise, a sandbox or security policy might be blocking it.",
+            ),
+            StartupErrorKind::AddressNotAvailable => gettext(
+                "Packet couldn't listen on this device's network address. Make sure you're connected to a network.",
+            ),
+            StartupErrorKind::BluetoothUnavailable => gettext(
+                "Packet couldn't use the Bluetooth adapter. Make sure Bluetooth is turned on and the Bluetooth service is running.",
+            ),
+            StartupErrorKind::DownloadFolderNotWritable => gettext(
+                "Packet doesn't have permission to save files to the download folder. Choose a different folder.",
+            ),
+            StartupErrorKind::MdnsConflict => gettext(
+                "Packet couldn't advertise itself on the network. Another application might be using the mDNS port.",
+            ),
+            StartupErrorKind::Unknown => gettext("An error occurred while trying to setup Packet"),
+        }
+    }
+
+    pub fn action(&self) -> Option<StartupErrorAction> {
+        match self {
+            StartupErrorKind::PortInUse => Some(StartupErrorAction::ChangePort),
+            StartupErrorKind::DownloadFolderNotWritable => Some(StartupErrorAction::PickFolder),
+            StartupErrorKind::ListenNotPermitted
+            | StartupErrorKind::AddressNotAvailable
+            | StartupErrorKind::BluetoothUnavailable
+            | StartupErrorKind::MdnsConflict => Some(StartupErrorAction::OpenDiagnostics),
+            StartupErrorKind::Unknown => None,
+        }
+    }
+}
+
+impl StartupErrorAction {
+    pub fn label(&self) -> String {
+        match self {
+            StartupErrorAction::ChangePort => gettext("Change Port"),
+            StartupErrorAction::PickFolder => gettext("Pick Folder"),
+            StartupErrorAction::OpenDiagnostics => gettext("Open Diagnostics"),
+        }
+    }
+}
+
+/// The download folder failed the pre-flight check that's done before
+/// starting the service.
+#[derive(Debug)]
+pub struct DownloadFolderError {
+    pub path: PathBuf,
+    pub source: io::Error,
+}
+
+impl fmt::Display for DownloadFolderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Download folder {:?} isn't writable", self.path)
+    }
+}
+
+impl std::error::Error for DownloadFolderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Makes sure files can actually be created in the download folder, since
+/// otherwise we'd only find out midway through the first transfer.
+pub fn ensure_download_folder_writable(path: &Path) -> Result<(), DownloadFolderError> {
+    let map_err = |source| DownloadFolderError {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    fs_err::create_dir_all(path).map_err(map_err)?;
+
+    let probe_path = path.join(format!(".packet-{}", glib::uuid_string_random()));
+    fs_err::File::create(&probe_path).map_err(map_err)?;
+    _ = fs_err::remove_file(&probe_path);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Context, anyhow};
+
+    use super::*;
+
+    fn io_error(kind: ErrorKind) -> anyhow::Error {
+        anyhow::Error::new(io::Error::from(kind)).context("Failed to start the service")
+    }
+
+    #[test]
+    fn classifies_by_cause() {
+        assert_eq!(
+            StartupErrorKind::classify(&io_error(ErrorKind::AddrInUse)),
+            StartupErrorKind::PortInUse
+        );
+        assert_eq!(
+            StartupErrorKind::classify(&io_error(ErrorKind::AddrNotAvailable)),
+            StartupErrorKind::AddressNotAvailable
+        );
+        assert_eq!(
+            StartupErrorKind::classify(&io_error(ErrorKind::PermissionDenied)),
+            StartupErrorKind::ListenNotPermitted
+        );
+        assert_eq!(
+            StartupErrorKind::classify(&io_error(ErrorKind::Other)),
+            StartupErrorKind::Unknown
+        );
+
+        // Not taken for the listening socket, even though its source is a
+        // permission error
+        let err = anyhow::Error::new(DownloadFolderError {
+            path: "/downloads".into(),
+            source: io::Error::from(ErrorKind::PermissionDenied),
+        });
+        assert_eq!(
+            StartupErrorKind::classify(&err),
+            StartupErrorKind::DownloadFolderNotWritable
+        );
+
+        let err = Err::<(), _>(btleplug::Error::DeviceNotFound)
+            .context("Failed to start BLE")
+            .unwrap_err();
+        assert_eq!(
+            StartupErrorKind::classify(&err),
+            StartupErrorKind::BluetoothUnavailable
+        );
+    }
+
+    #[test]
+    fn classifies_by_message() {
+        let classify = |message: &str| {
+            StartupErrorKind::classify(&anyhow!("{message}").context("Failed to start the service"))
+        };
+
+        assert_eq!(
+            classify("Address already in use (os error 98)"),
+            StartupErrorKind::PortInUse
+        );
+        assert_eq!(
+            classify("Cannot assign requested address (os error 99)"),
+            StartupErrorKind::AddressNotAvailable
+        );
+        assert_eq!(
+            classify("Failed to create mDNS daemon"),
+            StartupErrorKind::MdnsConflict
+        );
+        assert_eq!(
+            classify("org.bluez.Error.NotReady: Resource Not Ready"),
+            StartupErrorKind::BluetoothUnavailable
+        );
+        assert_eq!(classify("Something else"), StartupErrorKind::Unknown);
+    }
+
+    /// rqs_lib's Bluetooth errors can only be downcast to if there's a single
+    /// version of each of the crates.
+    #[test]
+    fn shares_bluetooth_crates_with_rqs_lib() {
+        let lock_file =
+            std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.lock")).unwrap();
+        for name in ["bluer", "btleplug"] {
+            assert_eq!(
+                lock_file.matches(&format!("name = \"{name}\"\n")).count(),
+                1,
+                "{name} isn't the same version as rqs_lib's"
+            );
+        }
+    }
+}
@@ -0,0 +1,521 @@
+//! Transfer and discovery events as JSON, one object per line, served over a
+//! Unix socket so that other tools can follow along with what Packet is doing.
+//!
+//! See the "Monitoring Transfers" section of the README for the schema. Any
+//! change to the existing fields must bump [`SCHEMA_VERSION`], adding events or
+//! fields doesn't.
+
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, bail};
+use gtk::glib;
+use rqs_lib::{
+    TransferState as RqsState,
+    channel::{ChannelMessage, TransferKind},
+    hdl::info::{TransferMetadata, TransferPayload, TransferPayloadKind},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{UnixListener, UnixStream},
+    sync::broadcast::{self, error::RecvError},
+};
+
+use crate::config::{APP_ID, VERSION};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Where the running instance listens, inside the runtime folder that's
+/// shared with the host when running as a Flatpak.
+pub fn socket_path() -> PathBuf {
+    glib::user_runtime_dir()
+        .join("app")
+        .join(APP_ID)
+        .join("events.sock")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl Direction {
+    pub fn from_kind(kind: &TransferKind) -> Self {
+        match kind {
+            TransferKind::Inbound => Direction::Inbound,
+            TransferKind::Outbound => Direction::Outbound,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadKind {
+    Files,
+    Text,
+    Url,
+    Wifi,
+}
+
+impl PayloadKind {
+    pub fn from_metadata(metadata: Option<&TransferMetadata>) -> Self {
+        match metadata.map(|it| &it.payload_kind) {
+            Some(TransferPayloadKind::Text) => PayloadKind::Text,
+            Some(TransferPayloadKind::Url) => PayloadKind::Url,
+            Some(TransferPayloadKind::WiFi) => PayloadKind::Wifi,
+            Some(TransferPayloadKind::Files) | None => PayloadKind::Files,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayloadKind::Files => "files",
+            PayloadKind::Text => "text",
+            PayloadKind::Url => "url",
+            PayloadKind::Wifi => "wifi",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Sent first to every client.
+    Hello {
+        app_version: String,
+    },
+    /// Some events were skipped since the client wasn't keeping up.
+    Lagged {
+        skipped: u64,
+    },
+    DeviceDiscovered {
+        id: String,
+        name: Option<String>,
+    },
+    DeviceLost {
+        id: String,
+        name: Option<String>,
+    },
+    /// For inbound transfers it's us that needs to consent, for outbound
+    /// ones the other device.
+    ConsentRequested {
+        id: String,
+        direction: Direction,
+        device: Option<String>,
+        pin_code: Option<String>,
+        payload_kind: PayloadKind,
+        files: Vec<String>,
+        total_bytes: u64,
+    },
+    Progress {
+        id: String,
+        direction: Direction,
+        device: Option<String>,
+        ack_bytes: u64,
+        total_bytes: u64,
+    },
+    Finished {
+        id: String,
+        direction: Direction,
+        device: Option<String>,
+    },
+    Failed {
+        id: String,
+        direction: Direction,
+        device: Option<String>,
+        /// `rejected` or `disconnected`
+        reason: &'static str,
+    },
+    Cancelled {
+        id: String,
+        direction: Direction,
+        device: Option<String>,
+    },
+}
+
+impl Event {
+    /// `None` for the states that are only part of setting up a connection.
+    pub fn from_channel_message(channel_message: &ChannelMessage) -> Option<Self> {
+        let client_msg = channel_message.msg.as_client()?;
+        let id = channel_message.id.clone();
+        let direction = Direction::from_kind(&client_msg.kind);
+        let metadata = client_msg.metadata.as_ref();
+        let device = metadata
+            .and_then(|it| it.source.as_ref())
+            .map(|it| it.name.clone());
+
+        let event = match client_msg.state.as_ref()? {
+            RqsState::WaitingForUserConsent | RqsState::SentIntroduction => {
+                Event::ConsentRequested {
+                    id,
+                    direction,
+                    device,
+                    pin_code: metadata.and_then(|it| it.pin_code.clone()),
+                    payload_kind: PayloadKind::from_metadata(metadata),
+                    files: metadata
+                        .and_then(|it| match &it.payload {
+                            Some(TransferPayload::Files(files)) => Some(files.clone()),
+                            _ => None,
+                        })
+                        .unwrap_or_default(),
+                    total_bytes: metadata.map(|it| it.total_bytes).unwrap_or_default(),
+                }
+            }
+            RqsState::ReceivingFiles | RqsState::SendingFiles => Event::Progress {
+                id,
+                direction,
+                device,
+                ack_bytes: metadata.map(|it| it.ack_bytes).unwrap_or_default(),
+                total_bytes: metadata.map(|it| it.total_bytes).unwrap_or_default(),
+            },
+            RqsState::Finished => Event::Finished {
+                id,
+                direction,
+                device,
+            },
+            RqsState::Rejected => Event::Failed {
+                id,
+                direction,
+                device,
+                reason: "rejected",
+            },
+            RqsState::Disconnected => Event::Failed {
+                id,
+                direction,
+                device,
+                reason: "disconnected",
+            },
+            RqsState::Cancelled => Event::Cancelled {
+                id,
+                direction,
+                device,
+            },
+            _ => return None,
+        };
+
+        Some(event)
+    }
+
+    pub fn from_endpoint(endpoint_info: &rqs_lib::EndpointInfo) -> Self {
+        let id = endpoint_info.id.clone();
+        let name = endpoint_info.name.clone();
+        if endpoint_info.present == Some(false) {
+            Event::DeviceLost { id, name }
+        } else {
+            Event::DeviceDiscovered { id, name }
+        }
+    }
+}
+
+/// What's actually written out for each event.
+#[derive(Debug, Serialize)]
+struct Envelope<'a> {
+    version: u32,
+    /// Milliseconds since the Unix epoch
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+fn to_line(event: &Event) -> Option<Arc<str>> {
+    let envelope = Envelope {
+        version: SCHEMA_VERSION,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_millis() as u64)
+            .unwrap_or_default(),
+        event,
+    };
+
+    serde_json::to_string(&envelope)
+        .inspect_err(|err| tracing::warn!(?event, "Couldn't serialize event: {err:#}"))
+        .ok()
+        .map(|it| format!("{it}\n").into())
+}
+
+/// Hands events out to everyone connected to the socket.
+#[derive(Debug, Clone)]
+pub struct EventStream {
+    lines_tx: broadcast::Sender<Arc<str>>,
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        Self {
+            lines_tx: broadcast::channel(256).0,
+        }
+    }
+}
+
+impl EventStream {
+    pub fn publish(&self, event: Event) {
+        // Not worth serializing for no one
+        if self.lines_tx.receiver_count() == 0 {
+            return;
+        }
+
+        if let Some(line) = to_line(&event) {
+            _ = self.lines_tx.send(line);
+        }
+    }
+
+    pub fn publish_message(&self, channel_message: &ChannelMessage) {
+        if let Some(event) = Event::from_channel_message(channel_message) {
+            self.publish(event);
+        }
+    }
+
+    /// Accepts clients until the task is aborted. Must be run on the Tokio
+    /// runtime.
+    pub async fn serve(self) -> anyhow::Result<()> {
+        let path = socket_path();
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+
+        let listener = bind(&path).await?;
+        tracing::info!(?path, "Serving transfer events");
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(Self::write_events(stream, self.lines_tx.subscribe()));
+        }
+    }
+
+    async fn write_events(mut stream: UnixStream, mut lines_rx: broadcast::Receiver<Arc<str>>) {
+        tracing::debug!("Event stream client connected");
+
+        let mut next = to_line(&Event::Hello {
+            app_version: VERSION.to_string(),
+        });
+        loop {
+            if let Some(line) = next.take()
+                && stream.write_all(line.as_bytes()).await.is_err()
+            {
+                break;
+            }
+
+            next = match lines_rx.recv().await {
+                Ok(line) => Some(line),
+                Err(RecvError::Lagged(skipped)) => to_line(&Event::Lagged { skipped }),
+                Err(RecvError::Closed) => break,
+            };
+        }
+
+        tracing::debug!("Event stream client disconnected");
+    }
+}
+
+/// Binds to the socket, replacing it if it was left behind by an instance
+/// that didn't get to clean up, but not if something's still listening on it.
+async fn bind(path: &Path) -> anyhow::Result<UnixListener> {
+    match UnixStream::connect(path).await {
+        Ok(_) => bail!("Something's already listening on {}", path.display()),
+        // Nothing's listening on it
+        Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+            tracing::debug!(?path, "Removing stale socket");
+            fs_err::remove_file(path)?;
+        }
+        Err(_) => {}
+    }
+
+    UnixListener::bind(path).with_context(|| format!("Couldn't listen on {}", path.display()))
+}
+
+/// Runs `packet monitor` with the arguments that come after `monitor`, which
+/// prints the events of the running instance until it quits.
+pub fn run_monitor(args: impl IntoIterator<Item = String>) -> glib::ExitCode {
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!(
+                    "Usage: packet monitor [--json]\n\n\
+                     Prints transfer events from the running instance of Packet, as JSON\n\
+                     lines with --json. See the README for the schema."
+                );
+                return glib::ExitCode::SUCCESS;
+            }
+            _ => {
+                eprintln!("packet monitor: Unknown argument {arg:?}");
+                return glib::ExitCode::from(2);
+            }
+        }
+    }
+
+    let path = socket_path();
+    let stream = match std::os::unix::net::UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(err) => {
+            eprintln!(
+                "packet monitor: Couldn't connect to {}, is Packet running? {err}",
+                path.display()
+            );
+            return glib::ExitCode::FAILURE;
+        }
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+
+        let line = if json { line } else { describe_line(&line) };
+        // Stdout was closed, e.g. by `head`
+        if writeln!(stdout, "{line}")
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    glib::ExitCode::SUCCESS
+}
+
+/// Turns an event into `<event> key=value...`, for reading along.
+fn describe_line(line: &str) -> String {
+    let Ok(serde_json::Value::Object(fields)) = serde_json::from_str(line) else {
+        return line.to_string();
+    };
+
+    let mut description = fields
+        .get("event")
+        .and_then(|it| it.as_str())
+        .unwrap_or("unknown")
+        .to_string();
+    for (key, value) in &fields {
+        if matches!(key.as_str(), "event" | "version" | "timestamp") || value.is_null() {
+            continue;
+        }
+        match value {
+            serde_json::Value::String(value) => description.push_str(&format!(" {key}={value:?}")),
+            value => description.push_str(&format!(" {key}={value}")),
+        }
+    }
+
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use rqs_lib::channel::{Message, MessageClient};
+
+    use super::*;
+
+    fn message(kind: TransferKind, state: RqsState, ack_bytes: u64) -> ChannelMessage {
+        ChannelMessage {
+            id: "transfer".into(),
+            msg: Message::Client(MessageClient {
+                kind,
+                state: Some(state),
+                metadata: Some(rqs_lib::hdl::info::TransferMetadata {
+                    id: "transfer".into(),
+                    payload: Some(TransferPayload::Files(vec!["a.jpg".into()])),
+                    total_bytes: 100,
+                    ack_bytes,
+                    ..Default::default()
+                }),
+            }),
+        }
+    }
+
+    fn json(event: &Event) -> serde_json::Value {
+        serde_json::from_str(&to_line(event).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn serializes_progress() {
+        let event = Event::from_channel_message(&message(
+            TransferKind::Inbound,
+            RqsState::ReceivingFiles,
+            40,
+        ))
+        .unwrap();
+        let value = json(&event);
+
+        assert_eq!(value["version"], SCHEMA_VERSION);
+        assert_eq!(value["event"], "progress");
+        assert_eq!(value["id"], "transfer");
+        assert_eq!(value["direction"], "inbound");
+        assert_eq!(value["ack_bytes"], 40);
+        assert_eq!(value["total_bytes"], 100);
+    }
+
+    #[test]
+    fn maps_states_to_events() {
+        let event_name = |kind, state| {
+            Event::from_channel_message(&message(kind, state, 0))
+                .map(|it| json(&it)["event"].clone())
+        };
+
+        assert_eq!(
+            event_name(TransferKind::Inbound, RqsState::WaitingForUserConsent).unwrap(),
+            "consent_requested"
+        );
+        assert_eq!(
+            event_name(TransferKind::Outbound, RqsState::Finished).unwrap(),
+            "finished"
+        );
+        assert_eq!(
+            event_name(TransferKind::Outbound, RqsState::Rejected).unwrap(),
+            "failed"
+        );
+        assert_eq!(
+            event_name(TransferKind::Inbound, RqsState::Cancelled).unwrap(),
+            "cancelled"
+        );
+        assert!(event_name(TransferKind::Inbound, RqsState::SentUkeyServerInit).is_none());
+    }
+
+    #[tokio::test]
+    async fn only_replaces_stale_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.sock");
+
+        let listener = bind(&path).await.unwrap();
+        assert!(bind(&path).await.is_err());
+        assert!(UnixStream::connect(&path).await.is_ok());
+
+        // Left behind, as if the instance had crashed
+        drop(listener);
+        assert!(path.exists());
+        let _listener = bind(&path).await.unwrap();
+        assert!(UnixStream::connect(&path).await.is_ok());
+    }
+
+    #[test]
+    fn describes_lines() {
+        let line = to_line(&Event::Failed {
+            id: "transfer".into(),
+            direction: Direction::Outbound,
+            device: None,
+            reason: "rejected",
+        })
+        .unwrap();
+
+        let description = describe_line(&line);
+
+        assert!(description.starts_with("failed "));
+        assert!(description.contains(r#"direction="outbound""#));
+        assert!(description.contains(r#"reason="rejected""#));
+        assert!(!description.contains("timestamp"));
+        assert!(!description.contains("device"));
+    }
+}
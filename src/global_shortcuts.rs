@@ -0,0 +1,119 @@
+use anyhow::Context;
+use ashpd::{
+    WindowIdentifier,
+    desktop::global_shortcuts::{GlobalShortcuts, NewShortcut},
+};
+use futures_lite::StreamExt;
+use gettextrs::gettext;
+
+/// Shortcuts that work system-wide, including while Packet is in the
+/// background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalShortcut {
+    ToggleVisibility,
+    SendClipboard,
+    OpenSendPage,
+    SendScreenshot,
+}
+
+impl GlobalShortcut {
+    pub const ALL: [Self; 4] = [
+        Self::ToggleVisibility,
+        Self::SendClipboard,
+        Self::OpenSendPage,
+        Self::SendScreenshot,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::ToggleVisibility => "toggle-visibility",
+            Self::SendClipboard => "send-clipboard",
+            Self::OpenSendPage => "open-send-page",
+            Self::SendScreenshot => "send-screenshot",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|it| it.id() == id)
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Self::ToggleVisibility => gettext("Toggle Visibility"),
+            Self::SendClipboard => gettext("Send Clipboard Contents"),
+            Self::OpenSendPage => gettext("Open Send Page"),
+            Self::SendScreenshot => gettext("Send a Screenshot"),
+        }
+    }
+
+    /// Key holding the preferred trigger, in the format of the XDG shortcuts
+    /// spec, e.g. `LOGO+ALT+v`.
+    pub fn settings_key(&self) -> &'static str {
+        match self {
+            Self::ToggleVisibility => "global-shortcut-toggle-visibility",
+            Self::SendClipboard => "global-shortcut-send-clipboard",
+            Self::OpenSendPage => "global-shortcut-open-send-page",
+            Self::SendScreenshot => "global-shortcut-send-screenshot",
+        }
+    }
+}
+
+/// Binds the shortcuts through the portal, and calls `on_activated` whenever
+/// one of them is triggered. Runs until `stop_rx` is closed, at which point the
+/// session is closed as well.
+///
+/// Preferred triggers are only a hint, the desktop can let the user pick
+/// different ones, or none at all.
+pub async fn run(
+    shortcuts: Vec<(GlobalShortcut, String)>,
+    identifier: Option<WindowIdentifier>,
+    stop_rx: async_channel::Receiver<()>,
+    on_activated: impl Fn(GlobalShortcut),
+) -> anyhow::Result<()> {
+    let proxy = GlobalShortcuts::new().await?;
+    let session = proxy.create_session().await?;
+
+    let new_shortcuts = shortcuts
+        .iter()
+        .map(|(shortcut, trigger)| {
+            NewShortcut::new(shortcut.id(), shortcut.description())
+                .preferred_trigger((!trigger.is_empty()).then_some(trigger.as_str()))
+        })
+        .collect::<Vec<_>>();
+    let bound = proxy
+        .bind_shortcuts(&session, &new_shortcuts, identifier.as_ref())
+        .await?
+        .response()
+        .context("Failed to bind global shortcuts")?;
+    tracing::info!(
+        shortcuts = ?bound
+            .shortcuts()
+            .iter()
+            .map(|it| (it.id(), it.trigger_description()))
+            .collect::<Vec<_>>(),
+        "Bound global shortcuts"
+    );
+
+    let mut activated_stream = proxy.receive_activated().await?;
+    loop {
+        let activated = futures_lite::future::or(async { activated_stream.next().await }, async {
+            // Closed by the sender being dropped
+            _ = stop_rx.recv().await;
+            None
+        })
+        .await;
+        let Some(activated) = activated else {
+            break;
+        };
+
+        tracing::info!(id = activated.shortcut_id(), "Global shortcut activated");
+        if let Some(shortcut) = GlobalShortcut::from_id(activated.shortcut_id()) {
+            on_activated(shortcut);
+        }
+    }
+
+    tracing::info!("Unbinding global shortcuts");
+    session.close().await?;
+
+    Ok(())
+}
@@ -0,0 +1,355 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event_stream::{Direction, PayloadKind},
+    tokio_runtime, utils,
+};
+
+const STORE_FILE_NAME: &str = "hooks.json";
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+/// A command that's run after a transfer finishes. Edited by hand, see the
+/// "Post-Transfer Hooks" section of the README.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hook {
+    /// Shown in the logs, the command is used if it's not set.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Run with `sh -c`, with the file paths as its arguments.
+    pub command: String,
+    /// Runs for both if not set.
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    /// Names of the devices to run for, any device if empty.
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Only runs for these file extensions if set, and is only passed the
+    /// files that have them.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Any kind if empty.
+    #[serde(default)]
+    pub payload_kinds: Vec<PayloadKind>,
+    /// In seconds
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+impl Hook {
+    fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.command)
+    }
+
+    /// The files to run the hook with, or `None` if it shouldn't run for
+    /// the transfer.
+    fn matching_files(&self, transfer: &FinishedTransfer) -> Option<Vec<PathBuf>> {
+        if self.direction.is_some_and(|it| it != transfer.direction)
+            || (!self.devices.is_empty() && !self.devices.contains(&transfer.device_name))
+            || (!self.payload_kinds.is_empty()
+                && !self.payload_kinds.contains(&transfer.payload_kind))
+        {
+            return None;
+        }
+
+        if self.extensions.is_empty() {
+            return Some(transfer.files.clone());
+        }
+
+        let files = transfer
+            .files
+            .iter()
+            .filter(|path| {
+                path.extension()
+                    .map(|it| it.to_string_lossy().to_lowercase())
+                    .is_some_and(|extension| {
+                        self.extensions
+                            .iter()
+                            .any(|it| it.trim_start_matches('.').to_lowercase() == extension)
+                    })
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        (!files.is_empty()).then_some(files)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Hooks {
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+}
+
+impl Hooks {
+    pub fn path() -> PathBuf {
+        glib::user_config_dir().join("packet").join(STORE_FILE_NAME)
+    }
+
+    pub fn load() -> Self {
+        utils::load_json_or_default(&Self::path())
+    }
+}
+
+/// What the hooks get to know about a transfer that finished.
+#[derive(Debug, Clone)]
+pub struct FinishedTransfer {
+    pub id: String,
+    pub direction: Direction,
+    pub device_name: String,
+    pub payload_kind: PayloadKind,
+    pub files: Vec<PathBuf>,
+    /// The text, link or Wi-Fi details that were shared
+    pub text: Option<String>,
+}
+
+impl FinishedTransfer {
+    fn env(&self, files: &[PathBuf]) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("PACKET_TRANSFER_ID", self.id.clone()),
+            ("PACKET_DIRECTION", self.direction.as_str().to_string()),
+            ("PACKET_DEVICE_NAME", self.device_name.clone()),
+            (
+                "PACKET_PAYLOAD_KIND",
+                self.payload_kind.as_str().to_string(),
+            ),
+            (
+                "PACKET_FILES",
+                files
+                    .iter()
+                    .map(|it| it.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            ("PACKET_FILE_COUNT", files.len().to_string()),
+        ];
+        if let Some(text) = &self.text {
+            env.push(("PACKET_TEXT", text.clone()));
+        }
+
+        env
+    }
+}
+
+/// Runs the hooks that match the transfer one after the other, on the Tokio
+/// runtime.
+pub fn run_post_transfer_hooks(transfer: FinishedTransfer) {
+    tokio_runtime().spawn(async move {
+        let hooks = match tokio::task::spawn_blocking(Hooks::load).await {
+            Ok(it) => it.hooks,
+            Err(err) => {
+                tracing::warn!("Couldn't load hooks: {err:#}");
+                return;
+            }
+        };
+
+        for hook in hooks {
+            let Some(files) = hook.matching_files(&transfer) else {
+                continue;
+            };
+
+            if let Err(err) = run_hook(&hook, &transfer, &files).await {
+                tracing::warn!(
+                    hook = hook.display_name(),
+                    id = transfer.id,
+                    "Hook failed: {err:#}"
+                );
+            }
+        }
+    });
+}
+
+async fn run_hook(
+    hook: &Hook,
+    transfer: &FinishedTransfer,
+    files: &[PathBuf],
+) -> anyhow::Result<()> {
+    tracing::info!(
+        hook = hook.display_name(),
+        id = transfer.id,
+        file_count = files.len(),
+        "Running post-transfer hook"
+    );
+
+    let child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&hook.command)
+        // `$0`, followed by the files as `$@`
+        .arg("packet-hook")
+        .args(files)
+        .envs(transfer.env(files))
+        .current_dir(
+            files
+                .first()
+                .and_then(|it| it.parent())
+                .filter(|it| it.is_dir())
+                .unwrap_or(Path::new("/")),
+        )
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // So that whatever the command starts can be stopped along with it
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+        .context("Couldn't start the command")?;
+    let pgid = child.id().context("Command exited right away")?;
+
+    let output =
+        match tokio::time::timeout(Duration::from_secs(hook.timeout), child.wait_with_output())
+            .await
+        {
+            Ok(output) => output?,
+            Err(_) => {
+                // SAFETY: Only sends a signal, and the group is the command's own
+                unsafe { libc::killpg(pgid as libc::pid_t, libc::SIGKILL) };
+                bail!("Timed out after {}s", hook.timeout);
+            }
+        };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        tracing::info!(
+            hook = hook.display_name(),
+            stdout = stdout.trim_end(),
+            stderr = stderr.trim_end(),
+            "Post-transfer hook finished"
+        );
+    } else {
+        tracing::warn!(
+            hook = hook.display_name(),
+            status = %output.status,
+            stdout = stdout.trim_end(),
+            stderr = stderr.trim_end(),
+            "Post-transfer hook exited with an error"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer() -> FinishedTransfer {
+        FinishedTransfer {
+            id: "transfer".into(),
+            direction: Direction::Inbound,
+            device_name: "Pixel".into(),
+            payload_kind: PayloadKind::Files,
+            files: vec!["/tmp/a.ZIP".into(), "/tmp/b.jpg".into()],
+            text: None,
+        }
+    }
+
+    fn hook(json: &str) -> Hook {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn filters_by_transfer() {
+        let transfer = transfer();
+
+        assert_eq!(
+            hook(r#"{ "command": "true" }"#).matching_files(&transfer),
+            Some(transfer.files.clone())
+        );
+        assert_eq!(
+            hook(r#"{ "command": "true", "direction": "outbound" }"#).matching_files(&transfer),
+            None
+        );
+        assert_eq!(
+            hook(r#"{ "command": "true", "devices": ["Tablet"] }"#).matching_files(&transfer),
+            None
+        );
+        assert_eq!(
+            hook(r#"{ "command": "true", "payload_kinds": ["text", "url"] }"#)
+                .matching_files(&transfer),
+            None
+        );
+    }
+
+    #[test]
+    fn filters_by_extension() {
+        let transfer = transfer();
+
+        assert_eq!(
+            hook(r#"{ "command": "true", "extensions": [".zip"] }"#).matching_files(&transfer),
+            Some(vec!["/tmp/a.ZIP".into()])
+        );
+        assert_eq!(
+            hook(r#"{ "command": "true", "extensions": ["png"] }"#).matching_files(&transfer),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn passes_the_transfer_to_the_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let hook = Hook {
+            command: format!(
+                r#"printf '%s|%s|%s' "$PACKET_DEVICE_NAME" "$PACKET_FILE_COUNT" "$1" > {:?}"#,
+                out
+            ),
+            ..hook(r#"{ "command": "" }"#)
+        };
+        let transfer = transfer();
+
+        run_hook(&hook, &transfer, &transfer.files).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(out).unwrap(), "Pixel|2|/tmp/a.ZIP");
+    }
+
+    #[tokio::test]
+    async fn stops_commands_that_time_out() {
+        let hook = Hook {
+            timeout: 1,
+            ..hook(r#"{ "command": "sleep 10" }"#)
+        };
+
+        assert!(run_hook(&hook, &transfer(), &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stops_everything_a_command_started() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        let hook = Hook {
+            timeout: 1,
+            command: format!("sleep 30 & echo $! > {pid_file:?}; wait"),
+            ..hook(r#"{ "command": "" }"#)
+        };
+
+        assert!(run_hook(&hook, &transfer(), &[]).await.is_err());
+
+        let pid = std::fs::read_to_string(pid_file).unwrap();
+        let is_running = || {
+            // Zombies are as good as gone
+            std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).is_ok_and(|it| {
+                !it.rsplit_once(") ")
+                    .is_some_and(|(_, it)| it.starts_with('Z'))
+            })
+        };
+        for _ in 0..50 {
+            if !is_running() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Background process of the command is still running");
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+
+use formatx::formatx;
+use gettextrs::{gettext, ngettext};
+use gtk::prelude::*;
+
+/// The inhibit reason is only updated once the progress moves by this many
+/// percent, so that the session isn't asked to re-inhibit on every chunk.
+const PROGRESS_STEP: u64 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TransferProgress {
+    device_name: String,
+    is_inbound: bool,
+    /// Rounded down to [`PROGRESS_STEP`]
+    percent: u64,
+}
+
+/// Keeps the session from suspending or going idle while files are being sent
+/// or received.
+///
+/// Any transfer in `SendingFiles`/`ReceivingFiles` takes the inhibit, and it's
+/// released once every such transfer has reached a final state.
+#[derive(Debug, Default)]
+pub struct TransferInhibitor {
+    transfers: RefCell<HashMap<String, TransferProgress>>,
+    cookie: Cell<Option<u32>>,
+    reason: RefCell<String>,
+}
+
+impl TransferInhibitor {
+    /// Updates the inhibit as per the transfer event. Returns whether the
+    /// [`Self::description`] changed.
+    ///
+    /// `device_name` is the other side of the transfer, since outbound events
+    /// don't carry it.
+    pub fn handle_event(
+        &self,
+        app: &gtk::Application,
+        window: &gtk::Window,
+        channel_message: &rqs_lib::channel::ChannelMessage,
+        device_name: String,
+    ) -> bool {
+        let Some(client_msg) = channel_message.msg.as_client() else {
+            return false;
+        };
+
+        use rqs_lib::TransferState;
+        match client_msg.state {
+            Some(TransferState::SendingFiles | TransferState::ReceivingFiles) => {
+                let percent = client_msg
+                    .metadata
+                    .as_ref()
+                    .filter(|it| it.total_bytes > 0)
+                    .map(|it| it.ack_bytes * 100 / it.total_bytes)
+                    .unwrap_or_default();
+                self.transfers.borrow_mut().insert(
+                    channel_message.id.clone(),
+                    TransferProgress {
+                        device_name,
+                        is_inbound: matches!(
+                            client_msg.kind,
+                            rqs_lib::channel::TransferKind::Inbound
+                        ),
+                        percent: percent - percent % PROGRESS_STEP,
+                    },
+                );
+            }
+            Some(
+                TransferState::Disconnected
+                | TransferState::Rejected
+                | TransferState::Cancelled
+                | TransferState::Finished,
+            ) => {
+                self.transfers.borrow_mut().remove(&channel_message.id);
+            }
+            _ => return false,
+        };
+
+        self.update(app, window)
+    }
+
+    /// Releases the inhibit regardless of the transfers, e.g. when the service
+    /// goes away along with them.
+    pub fn release(&self, app: &gtk::Application) {
+        self.transfers.borrow_mut().clear();
+        if let Some(cookie) = self.cookie.take() {
+            tracing::info!("Releasing suspend inhibit");
+            app.uninhibit(cookie);
+        }
+        self.reason.borrow_mut().clear();
+    }
+
+    fn update(&self, app: &gtk::Application, window: &gtk::Window) -> bool {
+        let Some(reason) = self.description() else {
+            let is_changed = !self.reason.borrow().is_empty();
+            self.release(app);
+            return is_changed;
+        };
+        if *self.reason.borrow() == reason {
+            return false;
+        }
+
+        // Take the new one before letting go of the old one, so that there's no
+        // gap in between
+        let cookie = app.inhibit(
+            Some(window),
+            gtk::ApplicationInhibitFlags::SUSPEND | gtk::ApplicationInhibitFlags::IDLE,
+            Some(&reason),
+        );
+        if cookie == 0 {
+            tracing::warn!(reason, "Failed to inhibit suspend");
+        } else {
+            tracing::debug!(reason, "Inhibiting suspend");
+        }
+
+        if let Some(old_cookie) = self.cookie.replace((cookie != 0).then_some(cookie)) {
+            app.uninhibit(old_cookie);
+        }
+        self.reason.replace(reason);
+
+        true
+    }
+
+    /// What's being transferred, if anything, e.g. "Sending files to Pixel 9
+    /// (40%)".
+    pub fn description(&self) -> Option<String> {
+        let transfers = self.transfers.borrow();
+
+        let reason = match transfers.len() {
+            0 => return None,
+            1 => {
+                let progress = transfers.values().next().unwrap();
+                if progress.is_inbound {
+                    formatx!(
+                        gettext(
+                            // Translators: e.g. "Receiving files from Pixel 9 (40%)"
+                            "Receiving files from {} ({}%)"
+                        ),
+                        &progress.device_name,
+                        progress.percent
+                    )
+                } else {
+                    formatx!(
+                        gettext(
+                            // Translators: e.g. "Sending files to Pixel 9 (40%)"
+                            "Sending files to {} ({}%)"
+                        ),
+                        &progress.device_name,
+                        progress.percent
+                    )
+                }
+            }
+            count => formatx!(
+                ngettext(
+                    "{} transfer in progress",
+                    "{} transfers in progress",
+                    count as u32
+                ),
+                count
+            ),
+        }
+        .unwrap_or_else(|_| "badly formatted locale string".into());
+
+        Some(reason)
+    }
+}
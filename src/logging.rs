@@ -0,0 +1,292 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    io::Write as _,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use gtk::glib;
+use tracing::{
+    Level,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+/// Number of log records kept in memory for the log viewer and exports.
+const LOG_BUFFER_CAPACITY: usize = 5000;
+
+/// The log file is rotated once it grows past this size.
+const LOG_FILE_MAX_SIZE: u64 = 2 * 1024 * 1024;
+/// Number of rotated log files kept around, excluding the current one.
+const LOG_FILE_ROTATE_COUNT: usize = 3;
+const LOG_FILE_NAME: &str = "packet.log";
+
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub timestamp: String,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:>5} {}: {}",
+            self.timestamp, self.level, self.target, self.message
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct RecordVisitor {
+    message: String,
+    fields: String,
+}
+
+impl RecordVisitor {
+    fn finish(self) -> String {
+        if self.fields.is_empty() {
+            self.message
+        } else if self.message.is_empty() {
+            self.fields.trim_start().to_string()
+        } else {
+            format!("{}{}", self.message, self.fields)
+        }
+    }
+}
+
+impl Visit for RecordVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            _ = write!(self.message, "{value:?}");
+        } else {
+            _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RotatingLogFile {
+    dir: PathBuf,
+    file: std::fs::File,
+    size: u64,
+}
+
+impl RotatingLogFile {
+    fn open(dir: &Path) -> std::io::Result<Self> {
+        fs_err::create_dir_all(dir)?;
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE_NAME))?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size + line.len() as u64 > LOG_FILE_MAX_SIZE {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{line}")?;
+        self.size += line.len() as u64 + 1;
+
+        Ok(())
+    }
+
+    /// `packet.log` -> `packet.log.1` -> ... -> `packet.log.N`, the oldest one
+    /// gets dropped.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let rotated_path = |n: usize| self.dir.join(format!("{LOG_FILE_NAME}.{n}"));
+
+        _ = std::fs::remove_file(rotated_path(LOG_FILE_ROTATE_COUNT));
+        for n in (1..LOG_FILE_ROTATE_COUNT).rev() {
+            _ = std::fs::rename(rotated_path(n), rotated_path(n + 1));
+        }
+        std::fs::rename(self.dir.join(LOG_FILE_NAME), rotated_path(1))?;
+
+        *self = Self::open(&self.dir)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LogStore {
+    records: Mutex<VecDeque<LogRecord>>,
+    file: Mutex<Option<RotatingLogFile>>,
+}
+
+impl LogStore {
+    fn push(&self, record: LogRecord) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            // Can't log the failure here without ending up back in here
+            _ = file.write_line(&record.to_string());
+        }
+
+        let mut records = self.records.lock().unwrap();
+        if records.len() == LOG_BUFFER_CAPACITY {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Returns the buffered records that are at least as severe as `max_level`.
+    pub fn records(&self, max_level: Level) -> Vec<LogRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|it| it.level <= max_level)
+            .cloned()
+            .collect()
+    }
+
+    pub fn set_file_logging(&self, enabled: bool) -> std::io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        if enabled {
+            if file.is_none() {
+                *file = Some(RotatingLogFile::open(&log_dir())?);
+            }
+        } else {
+            *file = None;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn log_store() -> &'static LogStore {
+    static LOG_STORE: OnceLock<LogStore> = OnceLock::new();
+    LOG_STORE.get_or_init(Default::default)
+}
+
+/// `$XDG_STATE_HOME/packet/logs`
+pub fn log_dir() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(glib::user_cache_dir)
+        .join("packet")
+        .join("logs")
+}
+
+/// A `tracing` layer that feeds every event into the global [`LogStore`].
+#[derive(Debug, Default)]
+pub struct LogCaptureLayer;
+
+impl<S: tracing::Subscriber> Layer<S> for LogCaptureLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = RecordVisitor::default();
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        log_store().push(LogRecord {
+            timestamp: glib::DateTime::now_local()
+                .and_then(|it| it.format("%F %T"))
+                .map(|it| it.to_string())
+                .unwrap_or_default(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.finish(),
+        });
+    }
+}
+
+/// Strips personally identifying bits, such as the user's home path and the
+/// device names, out of text that's meant to be shared in bug reports.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    home: Option<String>,
+    device_names: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(device_names: impl IntoIterator<Item = String>) -> Self {
+        let mut device_names = device_names
+            .into_iter()
+            .filter(|it| !it.trim().is_empty())
+            .collect::<Vec<_>>();
+        // Longest first, in case one of the names contains another
+        device_names.sort_by_key(|it| std::cmp::Reverse(it.len()));
+        device_names.dedup();
+
+        Self {
+            home: dirs::home_dir()
+                .map(|it| it.to_string_lossy().trim_end_matches('/').to_string())
+                .filter(|it| !it.is_empty()),
+            device_names,
+        }
+    }
+
+    pub fn redact(&self, s: &str) -> String {
+        let mut s = s.to_string();
+        if let Some(home) = &self.home {
+            s = replace_path(&s, home, "~");
+        }
+        for device_name in &self.device_names {
+            s = s.replace(device_name.as_str(), "<device name>");
+        }
+
+        s
+    }
+}
+
+/// Replaces `path` only where it's the whole path or a parent of it, so that
+/// e.g. `/home/al` doesn't turn `/home/alice` into `~ice`.
+fn replace_path(s: &str, path: &str, with: &str) -> String {
+    let mut replaced = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(index) = rest.find(path) {
+        replaced.push_str(&rest[..index]);
+        rest = &rest[index + path.len()..];
+
+        let continues_name = rest
+            .chars()
+            .next()
+            .is_some_and(|it| it.is_alphanumeric() || matches!(it, '-' | '_' | '.'));
+        replaced.push_str(if continues_name { path } else { with });
+    }
+    replaced.push_str(rest);
+
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_only_the_home_path() {
+        let redactor = Redactor {
+            home: Some("/home/al".into()),
+            device_names: vec!["Pixel".into()],
+        };
+
+        assert_eq!(
+            redactor.redact("/home/al/Downloads/a.jpg from Pixel"),
+            "~/Downloads/a.jpg from <device name>"
+        );
+        assert_eq!(redactor.redact("home=\"/home/al\""), "home=\"~\"");
+        assert_eq!(redactor.redact("/home/al"), "~");
+        assert_eq!(
+            redactor.redact("/home/alice/a.jpg /home/al.bak /home/al"),
+            "/home/alice/a.jpg /home/al.bak ~"
+        );
+    }
+}
@@ -1,11 +1,30 @@
 mod application;
+mod backend;
 #[rustfmt::skip]
 mod config;
 mod constants;
+mod deferred;
+mod diagnostics;
+mod errors;
+mod event_stream;
+mod global_shortcuts;
+mod hooks;
+mod inhibit;
+mod logging;
 mod monitors;
 mod objects;
+mod partial_files;
 mod plugins;
+mod receive;
+mod received_files;
+mod secret;
+mod service;
+mod supervisor;
+mod transfer_flow;
+mod tray;
 mod utils;
+mod watch_folders;
+mod webhooks;
 mod widgets;
 mod window;
 
@@ -13,18 +32,47 @@ use gettextrs::{LocaleCategory, gettext};
 use gtk::{gio, glib};
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::EnvFilter;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
 
 use self::application::PacketApplication;
 use self::config::{GETTEXT_PACKAGE, LOCALEDIR, RESOURCES_FILE};
 
 fn main() -> glib::ExitCode {
+    let args = std::env::args().collect::<Vec<_>>();
+    let command = args
+        .get(1)
+        .map(String::as_str)
+        .filter(|it| ["receive", "monitor"].contains(it));
+
     // Initialize logger
-    tracing_subscriber::fmt()
-        .with_line_number(true)
-        .with_env_filter(
-            EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
-                .from_env_lossy(),
+    // Logs are also captured in-memory so that they can be viewed and exported
+    // from within the app for troubleshooting. That's always done at the debug
+    // level for our own crates, regardless of what's printed.
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_line_number(true)
+                // Keeps stdout for what the commands print
+                .with_writer(if command.is_some() {
+                    BoxMakeWriter::new(std::io::stderr)
+                } else {
+                    BoxMakeWriter::new(std::io::stdout)
+                })
+                .with_filter(
+                    EnvFilter::builder()
+                        .with_default_directive(LevelFilter::INFO.into())
+                        .from_env_lossy(),
+                ),
+        )
+        .with(
+            logging::LogCaptureLayer.with_filter(
+                Targets::new()
+                    .with_default(LevelFilter::INFO)
+                    .with_target("packet", LevelFilter::DEBUG)
+                    .with_target("rqs_lib", LevelFilter::DEBUG),
+            ),
         )
         .init();
 
@@ -33,6 +81,12 @@ fn main() -> glib::ExitCode {
     gettextrs::bindtextdomain(GETTEXT_PACKAGE, LOCALEDIR).expect("Unable to bind the text domain");
     gettextrs::textdomain(GETTEXT_PACKAGE).expect("Unable to switch to the text domain");
 
+    match command {
+        Some("receive") => return receive::main(args.iter().skip(2).cloned()),
+        Some("monitor") => return event_stream::run_monitor(args.iter().skip(2).cloned()),
+        _ => {}
+    }
+
     glib::set_application_name(&gettext("Packet"));
 
     let res = gio::Resource::load(RESOURCES_FILE).expect("Could not load gresource file");
@@ -1,5 +1,7 @@
 mod receive_transfer;
+pub mod send_queue;
 pub mod send_transfer;
 
 pub use receive_transfer::*;
+pub use send_queue::*;
 pub use send_transfer::*;
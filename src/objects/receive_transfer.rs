@@ -12,6 +12,16 @@ pub enum UserAction {
     TransferCancel,
 }
 
+impl From<UserAction> for rqs_lib::channel::TransferAction {
+    fn from(value: UserAction) -> Self {
+        match value {
+            UserAction::ConsentAccept => Self::ConsentAccept,
+            UserAction::ConsentDecline => Self::ConsentDecline,
+            UserAction::TransferCancel => Self::TransferCancel,
+        }
+    }
+}
+
 pub mod imp {
     use std::{cell::RefCell, rc::Rc};
 
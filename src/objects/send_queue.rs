@@ -0,0 +1,453 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use gtk::{gio, glib, prelude::*, subclass::prelude::*};
+
+use super::{SendRequestState, TransferState};
+
+/// How many times a send that failed due to a disconnect is retried, before
+/// it's left as failed.
+pub const MAX_RETRY_ATTEMPTS: u32 = 3;
+
+/// Delay before the given retry attempt, starting from 1.
+pub fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(5 << attempt.saturating_sub(1).min(4))
+}
+
+/// How far an outbound transfer got, going by the events `rqs_lib` sent for
+/// it.
+///
+/// The sending side doesn't get told about declines or cancels by the
+/// recipient, it only sees `Disconnected`, so this is what's left to go on when
+/// telling those apart from the connection dropping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutboundProgress {
+    introduced: bool,
+    sending: bool,
+    disconnected: bool,
+    rejected: bool,
+    ack_bytes: u64,
+    total_bytes: u64,
+}
+
+impl OutboundProgress {
+    pub fn update(&mut self, client_msg: &rqs_lib::channel::MessageClient) {
+        use rqs_lib::TransferState as RqsState;
+
+        match client_msg.state {
+            Some(RqsState::SentIntroduction) => self.introduced = true,
+            Some(RqsState::SendingFiles) => self.sending = true,
+            Some(RqsState::Disconnected) => self.disconnected = true,
+            Some(RqsState::Rejected) => self.rejected = true,
+            _ => {}
+        }
+        if let Some(metadata) = &client_msg.metadata {
+            self.ack_bytes = metadata.ack_bytes;
+            self.total_bytes = metadata.total_bytes;
+        }
+    }
+
+    /// Whether the recipient turned the transfer down, i.e. it was
+    /// disconnected while waiting on them, before anything was sent.
+    pub fn is_declined(&self) -> bool {
+        self.rejected
+            || (self.disconnected && self.introduced && !self.sending && self.ack_bytes == 0)
+    }
+
+    /// Whether the transfer got disconnected on its own, either while sending
+    /// or before the recipient was ever asked, and so is worth retrying.
+    pub fn is_connection_lost(&self) -> bool {
+        if !self.disconnected {
+            return false;
+        }
+
+        if self.sending {
+            self.ack_bytes < self.total_bytes
+        } else {
+            !self.introduced
+        }
+    }
+}
+
+/// How a send ended up, for the summary of a send to multiple recipients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    Sent,
+    Declined,
+    Failed,
+    Cancelled,
+}
+
+impl SendOutcome {
+    /// `None` while the send is still queued, ongoing or waiting on a retry.
+    pub fn of(item: &SendRequestState) -> Option<Self> {
+        match item.transfer_state() {
+            TransferState::Done => Some(Self::Sent),
+            TransferState::Failed => {
+                if item.imp().outbound_progress.get().is_declined() {
+                    Some(Self::Declined)
+                } else {
+                    Some(Self::Failed)
+                }
+            }
+            TransferState::AwaitingConsentOrIdle => Some(Self::Cancelled),
+            TransferState::Queued
+            | TransferState::Retrying
+            | TransferState::RequestedForConsent
+            | TransferState::OngoingTransfer => None,
+        }
+    }
+}
+
+/// Outbound transfers across all the recipients.
+///
+/// Only one transfer can happen at a time, so sends wait in here until the
+/// active one is settled. `model` has every send of the current recipients
+/// session, with the pending ones always at the end and in the order they'll
+/// be sent in.
+#[derive(Debug)]
+pub struct SendQueue {
+    pub model: gio::ListStore,
+    pending: RefCell<VecDeque<SendRequestState>>,
+    active: RefCell<Option<SendRequestState>>,
+    retries: RefCell<HashMap<SendRequestState, RetryState>>,
+    /// Recipients that were sent to together, to summarize once all are done
+    batches: RefCell<Vec<Vec<SendRequestState>>>,
+    pub(crate) state_handlers: RefCell<HashMap<SendRequestState, glib::SignalHandlerId>>,
+}
+
+#[derive(Debug, Default)]
+struct RetryState {
+    attempts: Cell<u32>,
+    source_id: RefCell<Option<glib::SourceId>>,
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        Self {
+            model: gio::ListStore::new::<SendRequestState>(),
+            pending: Default::default(),
+            active: Default::default(),
+            retries: Default::default(),
+            batches: Default::default(),
+            state_handlers: Default::default(),
+        }
+    }
+}
+
+impl SendQueue {
+    pub fn is_active(&self, item: &SendRequestState) -> bool {
+        self.active.borrow().as_ref() == Some(item)
+    }
+
+    pub fn is_pending(&self, item: &SendRequestState) -> bool {
+        self.pending.borrow().contains(item)
+    }
+
+    /// Whether nothing is being sent, nor waiting to be.
+    pub fn is_idle(&self) -> bool {
+        self.active.borrow().is_none() && self.pending.borrow().is_empty()
+    }
+
+    /// Whether the item is in the queue at all, regardless of its state.
+    pub fn contains(&self, item: &SendRequestState) -> bool {
+        self.model.find(item).is_some()
+    }
+
+    /// Adds the item to the end of the queue, moving it there if it was
+    /// already in the queue.
+    pub fn push(&self, item: &SendRequestState) {
+        self.pending.borrow_mut().retain(|it| it != item);
+        if let Some(pos) = self.model.find(item) {
+            self.model.remove(pos);
+        }
+
+        self.pending.borrow_mut().push_back(item.clone());
+        self.model.append(item);
+        item.set_transfer_state(TransferState::Queued);
+    }
+
+    /// Takes the next pending item and marks it as the active one, if nothing
+    /// else is active.
+    pub fn start_next(&self) -> Option<SendRequestState> {
+        if self.active.borrow().is_some() {
+            return None;
+        }
+
+        let item = self.pending.borrow_mut().pop_front()?;
+        self.active.replace(Some(item.clone()));
+
+        Some(item)
+    }
+
+    /// Clears the active item, returns whether it was the given one.
+    pub fn finish_active(&self, item: &SendRequestState) -> bool {
+        if self.is_active(item) {
+            self.active.replace(None);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes the item from the pending ones, and stops any scheduled retry.
+    /// Returns whether the item was waiting on anything.
+    pub fn remove_pending(&self, item: &SendRequestState) -> bool {
+        let was_pending = {
+            let mut pending = self.pending.borrow_mut();
+            let len = pending.len();
+            pending.retain(|it| it != item);
+            pending.len() != len
+        };
+        let was_retrying = self.cancel_retry(item);
+
+        was_pending || was_retrying
+    }
+
+    /// Moves a pending item up (negative offset) or down the queue.
+    pub fn move_pending(&self, item: &SendRequestState, offset: i32) {
+        let (new_idx, pending_len) = {
+            let mut pending = self.pending.borrow_mut();
+            let Some(idx) = pending.iter().position(|it| it == item) else {
+                return;
+            };
+            let new_idx = (idx as i64 + offset as i64).clamp(0, pending.len() as i64 - 1) as usize;
+            if new_idx == idx {
+                return;
+            }
+
+            let item = pending.remove(idx).unwrap();
+            pending.insert(new_idx, item);
+
+            (new_idx, pending.len())
+        };
+
+        // Pending items are always at the end of the model
+        let first_pending_pos = self.model.n_items() - pending_len as u32;
+        if let Some(pos) = self.model.find(item) {
+            self.model.remove(pos);
+            self.model.insert(first_pending_pos + new_idx as u32, item);
+        }
+    }
+
+    pub fn can_move(&self, item: &SendRequestState, offset: i32) -> bool {
+        let pending = self.pending.borrow();
+        pending
+            .iter()
+            .position(|it| it == item)
+            .map(|idx| {
+                let new_idx = idx as i64 + offset as i64;
+                new_idx >= 0 && new_idx < pending.len() as i64
+            })
+            .unwrap_or_default()
+    }
+
+    /// Number of retries that have been scheduled for the item so far.
+    pub fn retry_attempts(&self, item: &SendRequestState) -> u32 {
+        self.retries
+            .borrow()
+            .get(item)
+            .map(|it| it.attempts.get())
+            .unwrap_or_default()
+    }
+
+    /// Bumps the retry attempt count and keeps the source that'll requeue the
+    /// item, so that it can be cancelled.
+    pub fn set_retry(&self, item: &SendRequestState, source_id: glib::SourceId) -> u32 {
+        let mut retries = self.retries.borrow_mut();
+        let retry = retries.entry(item.clone()).or_default();
+        if let Some(old_source_id) = retry.source_id.replace(Some(source_id)) {
+            old_source_id.remove();
+        }
+        retry.attempts.set(retry.attempts.get() + 1);
+
+        retry.attempts.get()
+    }
+
+    /// To be called from the retry source once it fires.
+    pub fn take_retry_source(&self, item: &SendRequestState) {
+        if let Some(retry) = self.retries.borrow().get(item) {
+            retry.source_id.take();
+        }
+    }
+
+    fn cancel_retry(&self, item: &SendRequestState) -> bool {
+        self.retries
+            .borrow()
+            .get(item)
+            .and_then(|it| it.source_id.take())
+            .map(|it| it.remove())
+            .is_some()
+    }
+
+    /// Forgets about previous retries, e.g. once the send went through or the
+    /// user started it again themselves.
+    pub fn reset_retries(&self, item: &SendRequestState) {
+        self.cancel_retry(item);
+        self.retries.borrow_mut().remove(item);
+    }
+
+    pub fn add_batch(&self, items: Vec<SendRequestState>) {
+        self.batches.borrow_mut().push(items);
+    }
+
+    /// Removes and returns the batches where every send has ended up
+    /// somewhere.
+    pub fn take_finished_batches(&self) -> Vec<Vec<SendRequestState>> {
+        let (finished, ongoing) = self
+            .batches
+            .take()
+            .into_iter()
+            .partition::<Vec<_>, _>(|batch| {
+                batch
+                    .iter()
+                    .all(|it| !self.is_pending(it) && SendOutcome::of(it).is_some())
+            });
+        self.batches.replace(ongoing);
+
+        finished
+    }
+
+    /// Removes the items that are done with, either sent, failed or cancelled.
+    pub fn clear_finished(&self) {
+        let finished = self
+            .model
+            .iter::<SendRequestState>()
+            .filter_map(|it| it.ok())
+            .filter(|it| {
+                !self.is_active(it)
+                    && !self.is_pending(it)
+                    && matches!(
+                        it.transfer_state(),
+                        TransferState::AwaitingConsentOrIdle
+                            | TransferState::Failed
+                            | TransferState::Done
+                    )
+            })
+            .collect::<Vec<_>>();
+
+        for item in finished {
+            self.forget(&item);
+        }
+    }
+
+    /// Removes the item from the queue entirely.
+    pub fn forget(&self, item: &SendRequestState) {
+        self.remove_pending(item);
+        self.retries.borrow_mut().remove(item);
+        self.finish_active(item);
+        self.batches.borrow_mut().retain_mut(|batch| {
+            batch.retain(|it| it != item);
+            !batch.is_empty()
+        });
+        if let Some(handler_id) = self.state_handlers.borrow_mut().remove(item) {
+            item.disconnect(handler_id);
+        }
+        if let Some(pos) = self.model.find(item) {
+            self.model.remove(pos);
+        }
+    }
+
+    pub fn clear(&self) {
+        let items = self
+            .model
+            .iter::<SendRequestState>()
+            .filter_map(|it| it.ok())
+            .collect::<Vec<_>>();
+        for item in items {
+            self.forget(&item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rqs_lib::{TransferState as RqsState, channel::TransferKind};
+
+    use super::*;
+    use crate::backend::scripted::event;
+
+    fn progress(states: &[(RqsState, u64)]) -> OutboundProgress {
+        let mut progress = OutboundProgress::default();
+        for (state, ack_bytes) in states {
+            let msg = event(
+                "outbound",
+                TransferKind::Outbound,
+                state.clone(),
+                *ack_bytes,
+            );
+            progress.update(msg.msg.as_client().unwrap());
+        }
+        progress
+    }
+
+    #[test]
+    fn retries_lost_connections() {
+        // Dropped midway through sending
+        assert!(
+            progress(&[
+                (RqsState::SentIntroduction, 0),
+                (RqsState::SendingFiles, 40),
+                (RqsState::Disconnected, 40),
+            ])
+            .is_connection_lost()
+        );
+        // Couldn't connect in the first place
+        assert!(progress(&[(RqsState::Disconnected, 0)]).is_connection_lost());
+        assert!(
+            progress(&[
+                (RqsState::SentUkeyClientInit, 0),
+                (RqsState::Disconnected, 0),
+            ])
+            .is_connection_lost()
+        );
+    }
+
+    #[test]
+    fn tells_declines_apart() {
+        let declined = progress(&[(RqsState::SentIntroduction, 0), (RqsState::Disconnected, 0)]);
+        assert!(declined.is_declined());
+        assert!(progress(&[(RqsState::Rejected, 0)]).is_declined());
+
+        assert!(!progress(&[(RqsState::Disconnected, 0)]).is_declined());
+        assert!(
+            !progress(&[
+                (RqsState::SentIntroduction, 0),
+                (RqsState::SendingFiles, 40),
+                (RqsState::Disconnected, 40),
+            ])
+            .is_declined()
+        );
+    }
+
+    #[test]
+    fn doesnt_retry_declines() {
+        // What a decline or a cancel before accepting looks like from here
+        assert!(
+            !progress(&[
+                (RqsState::SentUkeyClientInit, 0),
+                (RqsState::SentIntroduction, 0),
+                (RqsState::Disconnected, 0),
+            ])
+            .is_connection_lost()
+        );
+        assert!(
+            !progress(&[
+                (RqsState::SentIntroduction, 0),
+                (RqsState::SendingFiles, 100),
+                (RqsState::Disconnected, 100),
+            ])
+            .is_connection_lost()
+        );
+        assert!(
+            !progress(&[
+                (RqsState::SentIntroduction, 0),
+                (RqsState::SendingFiles, 40),
+            ])
+            .is_connection_lost()
+        );
+    }
+}
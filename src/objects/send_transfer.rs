@@ -7,6 +7,7 @@ use rqs_lib::hdl::{
     info::{TransferPayload, TransferPayloadKind},
 };
 
+use super::OutboundProgress;
 use crate::{impl_deref_for_newtype, utils};
 
 #[derive(Debug, Clone, Default, glib::Boxed)]
@@ -107,6 +108,8 @@ impl ChannelMessage {
 #[boxed_type(name = "TransferStateBoxed")]
 pub enum TransferState {
     Queued,
+    /// Waiting to be queued again after getting disconnected.
+    Retrying,
     #[default]
     AwaitingConsentOrIdle,
     RequestedForConsent,
@@ -115,8 +118,41 @@ pub enum TransferState {
     Done,
 }
 
+impl TransferState {
+    /// Maps the state of an outbound transfer, as reported by `rqs_lib`, to
+    /// the state of the recipient card. `None` if the card shouldn't change.
+    pub fn from_outbound(state: &rqs_lib::TransferState) -> Option<Self> {
+        use rqs_lib::TransferState as RqsState;
+
+        match state {
+            RqsState::SentUkeyClientInit
+            | RqsState::SentUkeyClientFinish
+            | RqsState::SentIntroduction => Some(Self::RequestedForConsent),
+            RqsState::SendingFiles => Some(Self::OngoingTransfer),
+            // Outbound(Reject) is not handled on lib side
+            // rqs_lib::hdl::outbound: Cannot process: consent denied: Reject
+            RqsState::Disconnected | RqsState::Rejected => Some(Self::Failed),
+            RqsState::Cancelled => Some(Self::AwaitingConsentOrIdle),
+            RqsState::Finished => Some(Self::Done),
+            RqsState::Initial
+            | RqsState::ReceivedConnectionRequest
+            | RqsState::SentUkeyServerInit
+            | RqsState::SentPairedKeyEncryption
+            | RqsState::ReceivedUkeyClientFinish
+            | RqsState::SentConnectionResponse
+            | RqsState::SentPairedKeyResult
+            | RqsState::ReceivedPairedKeyResult
+            | RqsState::WaitingForUserConsent
+            | RqsState::ReceivingFiles => None,
+        }
+    }
+}
+
 pub mod imp {
-    use std::{cell::RefCell, rc::Rc};
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
 
     use gtk::glib::Properties;
 
@@ -127,11 +163,16 @@ pub mod imp {
     pub struct SendTransferState {
         pub eta: Rc<RefCell<utils::DataTransferEta>>,
         pub files: Rc<RefCell<Vec<String>>>,
+        /// Of the current or last transfer
+        pub outbound_progress: Cell<OutboundProgress>,
 
         #[property(get, set)]
         transfer_state: RefCell<TransferState>,
         #[property(get, set)]
         device_name: RefCell<String>,
+        /// Picked for sending to multiple recipients at once
+        #[property(get, set)]
+        selected: Cell<bool>,
 
         // For modifying widget by listening for events
         #[property(get, set)]
@@ -158,6 +199,40 @@ impl SendRequestState {
     pub fn new() -> Self {
         Default::default()
     }
+    /// What the service needs to send the files to this recipient.
+    pub fn send_info(&self) -> rqs_lib::SendInfo {
+        let endpoint_info = self.endpoint_info();
+
+        rqs_lib::SendInfo {
+            id: endpoint_info.id.clone(),
+            name: endpoint_info
+                .name
+                .clone()
+                .unwrap_or(gettext("Unknown device")),
+            addr: format!(
+                "{}:{}",
+                endpoint_info.ip.clone().unwrap_or_default(),
+                endpoint_info.port.clone().unwrap_or_default()
+            ),
+            ob: rqs_lib::OutboundPayload::Files(self.imp().files.borrow().clone()),
+        }
+    }
+
+    /// Follows an event of the outbound transfer to this recipient.
+    pub fn update_from_outbound(&self, client_msg: &rqs_lib::channel::MessageClient) {
+        let mut progress = self.imp().outbound_progress.get();
+        progress.update(client_msg);
+        self.imp().outbound_progress.set(progress);
+
+        if let Some(transfer_state) = client_msg
+            .state
+            .as_ref()
+            .and_then(TransferState::from_outbound)
+        {
+            self.set_transfer_state(transfer_state);
+        }
+    }
+
     pub fn copy(&self) -> Self {
         let obj = Self::new();
         obj.set_endpoint_info(self.endpoint_info());
@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+
+use crate::received_files::ReceivedFiles;
+
+/// Appended to the names of files that are kept after a receive that didn't
+/// finish.
+pub const PARTIAL_SUFFIX: &str = "partial";
+
+/// What happened to the files of a receive that didn't finish.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub removed: Vec<PathBuf>,
+    /// Where the files that were left behind are, whether on purpose or since
+    /// they couldn't be removed.
+    pub kept: Vec<PathBuf>,
+}
+
+/// Removes the files the transfer wrote, or renames them to mark them as
+/// partial if `keep` is set. Files that were already in the download folder
+/// are never touched. Blocks, so it should be run off the main thread.
+pub fn clean_up(files: &ReceivedFiles, keep: bool) -> CleanupReport {
+    let mut report = CleanupReport::default();
+
+    for path in files.written() {
+        if keep {
+            let partial_path = partial_path_for(&path);
+            match fs_err::rename(&path, &partial_path) {
+                Ok(_) => {
+                    tracing::info!(?partial_path, "Kept partially received file");
+                    report.kept.push(partial_path);
+                }
+                Err(err) => {
+                    tracing::warn!("Couldn't mark partially received file: {err:#}");
+                    report.kept.push(path);
+                }
+            }
+        } else {
+            match fs_err::remove_file(&path) {
+                Ok(_) => {
+                    tracing::info!(?path, "Removed partially received file");
+                    report.removed.push(path);
+                }
+                Err(err) => {
+                    tracing::warn!("Couldn't remove partially received file: {err:#}");
+                    report.kept.push(path);
+                }
+            }
+        }
+    }
+
+    report
+}
+
+/// `video.mp4` becomes `video.mp4.partial`, or `video.mp4.2.partial` and so
+/// on if that's taken.
+fn partial_path_for(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    (1..)
+        .map(|n| {
+            if n == 1 {
+                path.with_file_name(format!("{file_name}.{PARTIAL_SUFFIX}"))
+            } else {
+                path.with_file_name(format!("{file_name}.{n}.{PARTIAL_SUFFIX}"))
+            }
+        })
+        .find(|it| it.symlink_metadata().is_err())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|it| it.to_string()).collect()
+    }
+
+    #[test]
+    fn leaves_existing_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("photo.jpg"), "mine").unwrap();
+
+        let files = ReceivedFiles::new(
+            dir.path(),
+            &names(&["photo.jpg", "new.jpg", "../escape.jpg"]),
+        );
+        // Saved under another name, since the original one's taken
+        std::fs::write(dir.path().join("photo (1).jpg"), "partial").unwrap();
+        std::fs::write(dir.path().join("new.jpg"), "partial").unwrap();
+        std::fs::write(dir.path().join("escape.jpg"), "partial").unwrap();
+
+        assert_eq!(
+            clean_up(&files, false),
+            CleanupReport {
+                removed: vec![
+                    dir.path().join("escape.jpg"),
+                    dir.path().join("new.jpg"),
+                    dir.path().join("photo (1).jpg"),
+                ],
+                kept: vec![],
+            }
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("photo.jpg")).unwrap(),
+            "mine"
+        );
+    }
+
+    #[test]
+    fn leaves_modified_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("report.pdf"), "mine").unwrap();
+
+        let files = ReceivedFiles::new(dir.path(), &names(&["report.pdf"]));
+        // Written to by something else while the transfer's going on
+        std::fs::write(dir.path().join("report.pdf"), "still mine").unwrap();
+
+        assert_eq!(clean_up(&files, false), CleanupReport::default());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("report.pdf")).unwrap(),
+            "still mine"
+        );
+    }
+
+    #[test]
+    fn marks_kept_files_as_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("video.mp4.partial"), "earlier").unwrap();
+
+        let files = ReceivedFiles::new(dir.path(), &names(&["video.mp4", "unwritten.mp4"]));
+        std::fs::write(dir.path().join("video.mp4"), "partial").unwrap();
+
+        assert_eq!(
+            clean_up(&files, true),
+            CleanupReport {
+                removed: vec![],
+                kept: vec![dir.path().join("video.mp4.2.partial")],
+            }
+        );
+        assert!(!dir.path().join("video.mp4").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("video.mp4.partial")).unwrap(),
+            "earlier"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 
@@ -7,12 +7,155 @@ use crate::{
     utils::{is_file_same, xdg_data_dirs},
 };
 
+/// Helper that the action based plugins call with the selected files, which
+/// passes them onto the `send-files` action over D-Bus.
+const SEND_FILES_SCRIPT: &str = "plugins/packet_send_files.sh";
+/// Every plugin file carries the version of Packet it was bundled with, in a
+/// comment starting with this.
+const VERSION_MARKER: &str = "packet-plugin-version:";
+/// Written by the Nautilus extension next to itself once it's loaded.
+const NAUTILUS_LOADED_STAMP: &str = ".packet_nautilus.loaded";
+
+/// Something a plugin needs on the host to work, besides the file manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    NautilusPython,
+    PythonDbus,
+    Gdbus,
+}
+
+impl Dependency {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::NautilusPython => "nautilus-python",
+            Self::PythonDbus => "python-dbus",
+            Self::Gdbus => "gdbus",
+        }
+    }
+
+    /// Blocks, since it might have to run Python.
+    fn is_available(&self) -> bool {
+        match self {
+            Self::NautilusPython => {
+                const LIB_DIRS: &[&str] = &[
+                    "/usr/lib",
+                    "/usr/lib64",
+                    "/usr/lib/x86_64-linux-gnu",
+                    "/usr/lib/aarch64-linux-gnu",
+                    "/usr/local/lib",
+                ];
+                LIB_DIRS.iter().any(|lib_dir| {
+                    ["nautilus/extensions-4", "nautilus/extensions-3.0"]
+                        .iter()
+                        .any(|it| {
+                            Path::new(lib_dir)
+                                .join(it)
+                                .join("libnautilus-python.so")
+                                .exists()
+                        })
+                })
+            }
+            Self::PythonDbus => std::process::Command::new("python3")
+                .args(["-c", "import dbus"])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|it| it.success()),
+            Self::Gdbus => gtk::glib::find_program_in_path("gdbus").is_some(),
+        }
+    }
+
+    /// The command to install it with, going by the distribution Packet is
+    /// running on.
+    pub fn install_hint(&self) -> Option<String> {
+        let ids = [gtk::glib::os_info("ID"), gtk::glib::os_info("ID_LIKE")]
+            .into_iter()
+            .flatten()
+            .flat_map(|it| {
+                it.split_whitespace()
+                    .map(|it| it.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let is_distro = |names: &[&str]| ids.iter().any(|id| names.contains(&id.as_str()));
+
+        let (command, package) = if is_distro(&["debian", "ubuntu"]) {
+            (
+                "sudo apt install",
+                match self {
+                    Self::NautilusPython => "python3-nautilus",
+                    Self::PythonDbus => "python3-dbus",
+                    Self::Gdbus => "libglib2.0-bin",
+                },
+            )
+        } else if is_distro(&["fedora", "rhel"]) {
+            (
+                "sudo dnf install",
+                match self {
+                    Self::NautilusPython => "nautilus-python",
+                    Self::PythonDbus => "python3-dbus",
+                    Self::Gdbus => "glib2",
+                },
+            )
+        } else if is_distro(&["arch"]) {
+            (
+                "sudo pacman -S",
+                match self {
+                    Self::NautilusPython => "nautilus-python",
+                    Self::PythonDbus => "python-dbus",
+                    Self::Gdbus => "glib2",
+                },
+            )
+        } else if is_distro(&["suse", "opensuse"]) {
+            (
+                "sudo zypper install",
+                match self {
+                    Self::NautilusPython => "python3-nautilus",
+                    Self::PythonDbus => "python3-dbus-python",
+                    Self::Gdbus => "glib2-tools",
+                },
+            )
+        } else {
+            return None;
+        };
+
+        Some(format!("{command} {package}"))
+    }
+}
+
+/// How the installed plugin compares to the one bundled with Packet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PluginStatus {
+    pub is_installed: bool,
+    pub installed_version: Option<String>,
+    pub bundled_version: Option<String>,
+    /// Installed files are missing or don't match the bundled ones
+    pub is_stale: bool,
+    /// `None` if they couldn't be checked, like under Flatpak where the host
+    /// isn't visible
+    pub missing_dependencies: Option<Vec<Dependency>>,
+    /// Whether the file manager has loaded the installed version, for the
+    /// plugins that let Packet know
+    pub is_loaded: Option<bool>,
+}
+
+impl PluginStatus {
+    pub fn is_outdated(&self) -> bool {
+        self.is_installed && self.installed_version != self.bundled_version
+    }
+}
+
 pub trait Plugin {
     /// Installs and updates the plugin.
     ///
     /// Run it under a separate thread if you don't want it to block.
     fn install_plugin(&self) -> anyhow::Result<()>;
     fn uninstall_plugin(&self) -> anyhow::Result<()>;
+    /// Checks the installed plugin against the bundled one, along with its
+    /// dependencies.
+    ///
+    /// Run it under a separate thread if you don't want it to block.
+    fn plugin_status(&self) -> PluginStatus;
 }
 
 pub trait FileBasedPlugin: Plugin {
@@ -20,6 +163,46 @@ pub trait FileBasedPlugin: Plugin {
     fn install_dir(&self) -> Option<PathBuf>;
     /// It's the path to show to the user for troubleshooting purposes.
     fn help_install_dir() -> &'static str;
+    fn dependencies(&self) -> &[Dependency] {
+        &[]
+    }
+    /// Files that the plugin creates next to itself, to be removed along with
+    /// it.
+    fn generated_files(&self) -> &[&str] {
+        &[]
+    }
+    /// Whether the file manager has loaded the installed plugin, if it can be
+    /// told.
+    fn is_plugin_loaded(&self, _install_dir: &Path) -> Option<bool> {
+        None
+    }
+}
+
+/// Reads the version out of a plugin file, see [`VERSION_MARKER`].
+fn plugin_file_version(path: &Path) -> Option<String> {
+    let contents = fs_err::read_to_string(path).ok()?;
+    let (_, rest) = contents.split_once(VERSION_MARKER)?;
+    let version = rest
+        .trim_start()
+        .chars()
+        .take_while(|it| !it.is_whitespace())
+        .collect::<String>();
+
+    (!version.is_empty()).then_some(version)
+}
+
+fn missing_dependencies(dependencies: &[Dependency]) -> Option<Vec<Dependency>> {
+    if Path::new("/.flatpak-info").exists() {
+        return None;
+    }
+
+    Some(
+        dependencies
+            .iter()
+            .copied()
+            .filter(|it| !it.is_available())
+            .collect(),
+    )
 }
 impl<T: FileBasedPlugin> Plugin for T {
     fn install_plugin(&self) -> anyhow::Result<()> {
@@ -47,6 +230,9 @@ impl<T: FileBasedPlugin> Plugin for T {
             "Installing plugin"
         );
 
+        // Not every file manager creates its directory for these beforehand
+        fs_err::create_dir_all(&install_dir)?;
+
         for (src_path, dest_path) in self
             .plugin_files()
             .into_iter()
@@ -95,6 +281,7 @@ impl<T: FileBasedPlugin> Plugin for T {
             .plugin_files()
             .into_iter()
             .filter_map(|it| it.file_name().map(|name| install_dir.join(name)))
+            .chain(self.generated_files().iter().map(|it| install_dir.join(it)))
             .filter(|it| it.is_file())
         {
             tracing::debug!(?file_path, "Removing plugin file");
@@ -103,6 +290,45 @@ impl<T: FileBasedPlugin> Plugin for T {
 
         Ok(())
     }
+
+    fn plugin_status(&self) -> PluginStatus {
+        let mut status = PluginStatus {
+            bundled_version: self
+                .plugin_files()
+                .first()
+                .and_then(|it| plugin_file_version(it)),
+            missing_dependencies: missing_dependencies(self.dependencies()),
+            ..Default::default()
+        };
+
+        let Some(install_dir) = self.install_dir() else {
+            return status;
+        };
+        let installed_files = self
+            .plugin_files()
+            .iter()
+            .filter_map(|src_path| {
+                src_path
+                    .file_name()
+                    .map(|name| (src_path, install_dir.join(name)))
+            })
+            .collect::<Vec<_>>();
+
+        status.is_installed = installed_files.iter().any(|(_, dest)| dest.is_file());
+        if !status.is_installed {
+            return status;
+        }
+
+        status.installed_version = installed_files
+            .first()
+            .and_then(|(_, dest)| plugin_file_version(dest));
+        status.is_stale = installed_files
+            .iter()
+            .any(|(src, dest)| !is_file_same(src, dest).unwrap_or_default());
+        status.is_loaded = self.is_plugin_loaded(&install_dir);
+
+        status
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -137,6 +363,38 @@ impl FileBasedPlugin for NautilusPlugin {
     fn help_install_dir() -> &'static str {
         "~/.local/share/nautilus-python/extensions"
     }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &[Dependency::NautilusPython, Dependency::PythonDbus]
+    }
+
+    fn generated_files(&self) -> &[&str] {
+        &[NAUTILUS_LOADED_STAMP]
+    }
+
+    /// The extension leaves a stamp with its version whenever Nautilus loads
+    /// it, which would be older than the extension if Nautilus hasn't been
+    /// restarted since it was installed.
+    fn is_plugin_loaded(&self, install_dir: &Path) -> Option<bool> {
+        let extension_path = install_dir.join(self.files.first()?.file_name()?);
+        let stamp_path = install_dir.join(NAUTILUS_LOADED_STAMP);
+
+        let modified = |path: &Path| fs_err::metadata(path).and_then(|it| it.modified()).ok();
+        let (Some(extension_modified), Some(stamp_modified)) =
+            (modified(&extension_path), modified(&stamp_path))
+        else {
+            return Some(false);
+        };
+
+        Some(
+            stamp_modified >= extension_modified
+                && fs_err::read_to_string(&stamp_path)
+                    .ok()
+                    .as_deref()
+                    .map(str::trim)
+                    == plugin_file_version(&extension_path).as_deref(),
+        )
+    }
 }
 
 impl Default for NautilusPlugin {
@@ -152,3 +410,368 @@ impl NautilusPlugin {
         Self::default()
     }
 }
+
+/// File managers that Packet can add a "Send with Packet" entry to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileManager {
+    Nautilus,
+    Dolphin,
+    Nemo,
+    Thunar,
+}
+
+impl FileManager {
+    pub const ALL: [Self; 4] = [Self::Nautilus, Self::Dolphin, Self::Nemo, Self::Thunar];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Nautilus => "Nautilus",
+            Self::Dolphin => "Dolphin",
+            Self::Nemo => "Nemo",
+            Self::Thunar => "Thunar",
+        }
+    }
+
+    pub fn settings_key(&self) -> &'static str {
+        match self {
+            Self::Nautilus => "enable-nautilus-plugin",
+            Self::Dolphin => "enable-dolphin-plugin",
+            Self::Nemo => "enable-nemo-plugin",
+            Self::Thunar => "enable-thunar-plugin",
+        }
+    }
+
+    pub fn plugin(&self) -> Box<dyn Plugin + Send> {
+        match self {
+            Self::Nautilus => Box::new(NautilusPlugin::new()),
+            Self::Dolphin => Box::new(DolphinPlugin::new()),
+            Self::Nemo => Box::new(NemoPlugin::new()),
+            Self::Thunar => Box::new(ThunarPlugin::new()),
+        }
+    }
+
+    /// It's the path to show to the user for troubleshooting purposes.
+    pub fn help_install_dir(&self) -> &'static str {
+        match self {
+            Self::Nautilus => NautilusPlugin::help_install_dir(),
+            Self::Dolphin => DolphinPlugin::help_install_dir(),
+            Self::Nemo => NemoPlugin::help_install_dir(),
+            Self::Thunar => ThunarPlugin::help_install_dir(),
+        }
+    }
+
+    /// Whether the file manager seems to be around, going by its executable or
+    /// the configuration it leaves in the home directory.
+    ///
+    /// Under Flatpak only the latter is visible, and only for the directories
+    /// that Packet has access to.
+    pub fn is_detected(&self) -> bool {
+        let (program, home_paths): (&str, &[&str]) = match self {
+            Self::Nautilus => ("nautilus", &[".local/share/nautilus-python"]),
+            Self::Dolphin => ("dolphin", &[".config/dolphinrc"]),
+            Self::Nemo => ("nemo", &[".config/nemo", ".local/share/nemo"]),
+            Self::Thunar => ("thunar", &[".config/Thunar"]),
+        };
+
+        gtk::glib::find_program_in_path(program).is_some()
+            || dirs::home_dir()
+                .is_some_and(|home| home_paths.iter().any(|it| home.join(it).exists()))
+    }
+}
+
+/// The first of the directories under the user's home that exists, or the
+/// first one otherwise, for it to be created.
+///
+/// Under Flatpak, `XDG_DATA_HOME` and such point inside of the sandbox, so the
+/// home directory is used as is.
+fn home_install_dir(candidates: &[&str]) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    let candidates = candidates
+        .iter()
+        .map(|it| home.join(it))
+        .collect::<Vec<_>>();
+    candidates
+        .iter()
+        .find(|it| it.is_dir())
+        .or(candidates.first())
+        .cloned()
+}
+
+fn pkgdata_plugin_file(path: impl AsRef<Path>) -> PathBuf {
+    PathBuf::from(PKGDATADIR).join(path)
+}
+
+/// A KDE service menu, which shows up in Dolphin and other KIO based file
+/// managers.
+#[derive(Debug, Clone)]
+pub struct DolphinPlugin {
+    files: Vec<PathBuf>,
+}
+
+impl FileBasedPlugin for DolphinPlugin {
+    fn plugin_files(&self) -> &[PathBuf] {
+        self.files.as_slice()
+    }
+
+    fn install_dir(&self) -> Option<PathBuf> {
+        // https://develop.kde.org/docs/apps/dolphin/service-menus/
+        home_install_dir(&[".local/share/kio/servicemenus"])
+    }
+
+    fn help_install_dir() -> &'static str {
+        "~/.local/share/kio/servicemenus"
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &[Dependency::Gdbus]
+    }
+}
+
+impl Default for DolphinPlugin {
+    fn default() -> Self {
+        Self {
+            files: vec![
+                pkgdata_plugin_file("plugins/packet_dolphin.desktop"),
+                pkgdata_plugin_file(SEND_FILES_SCRIPT),
+            ],
+        }
+    }
+}
+
+impl DolphinPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NemoPlugin {
+    files: Vec<PathBuf>,
+}
+
+impl FileBasedPlugin for NemoPlugin {
+    fn plugin_files(&self) -> &[PathBuf] {
+        self.files.as_slice()
+    }
+
+    fn install_dir(&self) -> Option<PathBuf> {
+        home_install_dir(&[".local/share/nemo/actions"])
+    }
+
+    fn help_install_dir() -> &'static str {
+        "~/.local/share/nemo/actions"
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        &[Dependency::Gdbus]
+    }
+}
+
+impl Default for NemoPlugin {
+    fn default() -> Self {
+        Self {
+            files: vec![
+                pkgdata_plugin_file("plugins/packet.nemo_action"),
+                pkgdata_plugin_file(SEND_FILES_SCRIPT),
+            ],
+        }
+    }
+}
+
+impl NemoPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// A Thunar custom action.
+///
+/// Thunar keeps all of the custom actions in a single `uca.xml`, so unlike the
+/// other plugins, the action is merged into that file instead of being copied
+/// over.
+#[derive(Debug, Clone)]
+pub struct ThunarPlugin {
+    action_file: PathBuf,
+    script_file: PathBuf,
+}
+
+impl ThunarPlugin {
+    /// Identifies the action among the user's own ones in `uca.xml`.
+    const UNIQUE_ID: &str = "<unique-id>packet-send-files</unique-id>";
+    const EMPTY_ACTIONS: &str =
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<actions>\n</actions>\n";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn help_install_dir() -> &'static str {
+        "~/.config/Thunar"
+    }
+
+    fn install_dir(&self) -> Option<PathBuf> {
+        home_install_dir(&[".config/Thunar"])
+    }
+
+    /// Returns the actions with Packet's one taken out of them.
+    fn remove_action(actions: &str) -> String {
+        let Some(unique_id_idx) = actions.find(Self::UNIQUE_ID) else {
+            return actions.to_string();
+        };
+        let start = actions[..unique_id_idx].rfind("<action>");
+        let end = actions[unique_id_idx..]
+            .find("</action>")
+            .map(|it| unique_id_idx + it + "</action>".len());
+        let (Some(start), Some(end)) = (start, end) else {
+            return actions.to_string();
+        };
+
+        // Along with the indentation and newline around it
+        let start = actions[..start].trim_end_matches([' ', '\t']).len();
+        let end = if actions[end..].starts_with('\n') {
+            end + 1
+        } else {
+            end
+        };
+
+        format!("{}{}", &actions[..start], &actions[end..])
+    }
+}
+
+impl Default for ThunarPlugin {
+    fn default() -> Self {
+        Self {
+            action_file: pkgdata_plugin_file("plugins/packet_thunar.xml"),
+            script_file: pkgdata_plugin_file(SEND_FILES_SCRIPT),
+        }
+    }
+}
+
+impl Plugin for ThunarPlugin {
+    fn install_plugin(&self) -> anyhow::Result<()> {
+        let install_dir = self
+            .install_dir()
+            .context("Couldn't find the Thunar configuration directory")?;
+        let script_name = self
+            .script_file
+            .file_name()
+            .context("Plugin script has no file name")?;
+
+        tracing::debug!(?install_dir, "Installing Thunar plugin");
+
+        fs_err::create_dir_all(&install_dir)?;
+        let dest_script = install_dir.join(script_name);
+        if !(dest_script.exists() && is_file_same(&self.script_file, &dest_script)?) {
+            fs_err::copy(&self.script_file, &dest_script)?;
+        }
+
+        let action = fs_err::read_to_string(&self.action_file)?;
+        let uca_path = install_dir.join("uca.xml");
+        let actions = match fs_err::read_to_string(&uca_path) {
+            Ok(it) => it,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Self::EMPTY_ACTIONS.to_string()
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut actions = Self::remove_action(&actions);
+        let insert_idx = actions
+            .rfind("</actions>")
+            .context("Thunar's uca.xml has no actions element")?;
+        actions.insert_str(insert_idx, &action);
+
+        fs_err::write(&uca_path, actions)?;
+
+        Ok(())
+    }
+
+    fn uninstall_plugin(&self) -> anyhow::Result<()> {
+        let install_dir = self
+            .install_dir()
+            .context("Couldn't find the Thunar configuration directory")?;
+
+        tracing::debug!(?install_dir, "Uninstalling Thunar plugin");
+
+        let uca_path = install_dir.join("uca.xml");
+        if uca_path.is_file() {
+            let actions = fs_err::read_to_string(&uca_path)?;
+            fs_err::write(&uca_path, Self::remove_action(&actions))?;
+        }
+
+        if let Some(script_name) = self.script_file.file_name() {
+            let script_path = install_dir.join(script_name);
+            if script_path.is_file() {
+                fs_err::remove_file(script_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Thunar rewrites `uca.xml` in its own way whenever the actions are
+    /// edited, so only the script is versioned and checked for changes.
+    fn plugin_status(&self) -> PluginStatus {
+        let mut status = PluginStatus {
+            bundled_version: plugin_file_version(&self.script_file),
+            missing_dependencies: missing_dependencies(&[Dependency::Gdbus]),
+            ..Default::default()
+        };
+
+        let Some(install_dir) = self.install_dir() else {
+            return status;
+        };
+        status.is_installed = fs_err::read_to_string(install_dir.join("uca.xml"))
+            .is_ok_and(|it| it.contains(Self::UNIQUE_ID));
+        if !status.is_installed {
+            return status;
+        }
+
+        let installed_script = self
+            .script_file
+            .file_name()
+            .map(|it| install_dir.join(it))
+            .unwrap_or_default();
+        status.installed_version = plugin_file_version(&installed_script);
+        status.is_stale = !is_file_same(&self.script_file, &installed_script).unwrap_or_default();
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_file_version_from_comment() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packet_send_files.sh");
+
+        fs_err::write(&path, "#!/bin/sh\n# packet-plugin-version: 0.4.0\nexit 0\n").unwrap();
+        assert_eq!(plugin_file_version(&path).as_deref(), Some("0.4.0"));
+
+        fs_err::write(&path, "#!/bin/sh\nexit 0\n").unwrap();
+        assert_eq!(plugin_file_version(&path), None);
+    }
+
+    const ACTION: &str = "\t<action>\n\t\t<unique-id>packet-send-files</unique-id>\n\t</action>\n";
+    const USER_ACTION: &str = "\t<action>\n\t\t<unique-id>1700000000-1</unique-id>\n\t</action>\n";
+
+    #[test]
+    fn thunar_remove_action_keeps_user_actions() {
+        let actions = format!("<actions>\n{USER_ACTION}{ACTION}{USER_ACTION}</actions>\n");
+
+        assert_eq!(
+            ThunarPlugin::remove_action(&actions),
+            format!("<actions>\n{USER_ACTION}{USER_ACTION}</actions>\n")
+        );
+    }
+
+    #[test]
+    fn thunar_remove_action_without_packet_action() {
+        let actions = format!("<actions>\n{USER_ACTION}</actions>\n");
+
+        assert_eq!(ThunarPlugin::remove_action(&actions), actions);
+    }
+}
@@ -0,0 +1,732 @@
+//! `packet receive`, for receiving files without anyone around to accept them,
+//! such as on test machines.
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Context, anyhow, bail};
+use gtk::{gio, glib, prelude::*};
+use rqs_lib::{
+    TransferState as RqsState,
+    channel::{ChannelMessage, TransferAction, TransferKind},
+    hdl::info::{TransferPayload, TransferPayloadKind},
+};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    backend::{BackendChannels, QuickShareBackend},
+    config::APP_ID,
+    errors,
+    received_files::{self, ReceivedFiles},
+    service::{ServiceCommand, ServiceEvent, ServiceHandle},
+    tokio_runtime,
+};
+
+const USAGE: &str = "\
+Usage: packet receive --accept-from <device> --out <dir> [options]
+
+Receives files without asking, and prints what was received once done.
+
+Options:
+  --accept-from <device>  Accept transfers from the device with this name,
+                          can be given more than once
+  --out <dir>             Folder to save the received files to
+  --count <n>             Number of transfers to wait for (default: 1)
+  --timeout <seconds>     Give up after this long, 0 to wait forever (default: 0)
+  --name <name>           Name to show up as, instead of the one set in Packet
+  --json                  Print the summary as JSON
+  -h, --help              Show this help
+
+Exit status:
+  0    All transfers finished
+  1    The service couldn't be started, or stopped unexpectedly
+  2    Invalid arguments
+  3    Timed out before enough transfers were done
+  4    A transfer was rejected, cancelled or interrupted
+  130  Interrupted
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiveOptions {
+    /// Names of the devices to accept transfers from.
+    pub accept_from: Vec<String>,
+    pub out: PathBuf,
+    pub count: usize,
+    pub timeout: Option<Duration>,
+    pub device_name: Option<String>,
+    pub json: bool,
+}
+
+/// Parses the arguments that come after `receive`. `None` if only the help
+/// was asked for.
+pub fn parse_args(
+    args: impl IntoIterator<Item = String>,
+) -> anyhow::Result<Option<ReceiveOptions>> {
+    let mut accept_from = vec![];
+    let mut out = None;
+    let mut count = 1;
+    let mut timeout = None;
+    let mut device_name = None;
+    let mut json = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .with_context(|| format!("{flag} needs a value"))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--accept-from" => accept_from.push(value()?),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--count" => {
+                count = value()?
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|it| *it > 0)
+                    .context("--count must be a positive number")?;
+            }
+            "--timeout" => {
+                let seconds = value()?
+                    .parse::<u64>()
+                    .context("--timeout must be a number of seconds")?;
+                timeout = (seconds > 0).then(|| Duration::from_secs(seconds));
+            }
+            "--name" => device_name = Some(value()?),
+            "--json" => json = true,
+            _ => bail!("Unknown argument {flag:?}"),
+        }
+    }
+
+    if accept_from.is_empty() {
+        bail!("--accept-from is required");
+    }
+
+    Ok(Some(ReceiveOptions {
+        accept_from,
+        out: out.context("--out is required")?,
+        count,
+        timeout,
+        device_name,
+        json,
+    }))
+}
+
+/// Runs `packet receive` with the arguments that come after `receive`.
+pub fn main(args: impl IntoIterator<Item = String>) -> glib::ExitCode {
+    let options = match parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return glib::ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("packet receive: {err:#}\n\n{USAGE}");
+            return glib::ExitCode::from(2);
+        }
+    };
+
+    let summary = tokio_runtime().block_on(async {
+        let device_name = options.device_name.clone().unwrap_or_else(device_name);
+        tracing::info!(
+            ?device_name,
+            out = ?options.out,
+            accept_from = ?options.accept_from,
+            count = options.count,
+            timeout = ?options.timeout,
+            "Receiving from the command line"
+        );
+
+        if let Err(err) = errors::ensure_download_folder_writable(&options.out) {
+            return Summary::error(err.into());
+        }
+
+        let rqs = rqs_lib::RQS::new(
+            rqs_lib::Visibility::Visible,
+            None,
+            Some(options.out.clone()),
+            Some(device_name),
+        );
+        let interrupted = async {
+            if let Err(err) = tokio::signal::ctrl_c().await {
+                tracing::warn!("Couldn't listen for Ctrl+C: {err:#}");
+                std::future::pending::<()>().await;
+            }
+        };
+
+        receive(rqs, &options, interrupted).await
+    });
+
+    summary.print(options.json);
+
+    glib::ExitCode::from(summary.status.exit_code())
+}
+
+/// The name set in Packet, if it's installed.
+fn device_name() -> String {
+    gio::SettingsSchemaSource::default()
+        .and_then(|source| source.lookup(APP_ID, true))
+        .map(|_| gio::Settings::new(APP_ID).string("device-name").to_string())
+        .filter(|it| !it.is_empty())
+        .unwrap_or_else(whoami::devicename)
+}
+
+/// Keeps accepting transfers until enough of them are done, or until it's time
+/// to give up.
+async fn receive<B: QuickShareBackend>(
+    mut backend: B,
+    options: &ReceiveOptions,
+    interrupted: impl Future<Output = ()>,
+) -> Summary {
+    // Subscribed before `run`, so that no consent request is missed
+    let mut messages_rx = backend.message_sender().subscribe();
+    let file_sender = match backend.run().await {
+        Ok(BackendChannels { file_sender, .. }) => file_sender,
+        Err(err) => {
+            backend.stop().await;
+            return Summary::error(err.context("Failed to start the Quick Share service"));
+        }
+    };
+
+    let (events_tx, events_rx) = async_channel::unbounded();
+    let service = ServiceHandle::spawn(
+        backend,
+        Some(file_sender),
+        broadcast::channel(1).0,
+        events_tx,
+    );
+
+    let mut session = Session::new(options);
+    let result = {
+        let timed_out = async {
+            match options.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(timed_out);
+        tokio::pin!(interrupted);
+
+        loop {
+            if session.is_done() {
+                break Ok(session.status());
+            }
+
+            tokio::select! {
+                msg = messages_rx.recv() => match msg {
+                    Ok(channel_message) => {
+                        if let Some((id, action)) = session.handle_message(&channel_message) {
+                            service.send(ServiceCommand::TransferAction { id, action });
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "Missed some transfer events");
+                    }
+                    Err(RecvError::Closed) => {
+                        break Err(anyhow!("Quick Share service stopped unexpectedly"));
+                    }
+                },
+                Ok(ServiceEvent::CommandFailed { command, err }) = events_rx.recv() => {
+                    tracing::warn!(command, "{err:#}");
+                }
+                _ = &mut timed_out => break Ok(Status::TimedOut),
+                _ = &mut interrupted => break Ok(Status::Interrupted),
+            }
+        }
+    };
+
+    // Whatever is still going won't be finished anymore
+    for transfer in &session.transfers {
+        if transfer.state == TransferOutcome::Incomplete {
+            service.send(ServiceCommand::TransferAction {
+                id: transfer.id.clone(),
+                action: TransferAction::TransferCancel,
+            });
+        }
+    }
+    service.stop().await;
+
+    let mut summary = match result {
+        Ok(status) => Summary {
+            status,
+            error: None,
+            transfers: session.transfers,
+        },
+        Err(err) => Summary {
+            transfers: session.transfers,
+            ..Summary::error(err)
+        },
+    };
+    for transfer in &mut summary.transfers {
+        transfer.fill_file_sizes();
+    }
+
+    summary
+}
+
+/// Decides what to do with each incoming transfer, and keeps track of the
+/// ones that were accepted.
+#[derive(Debug)]
+struct Session<'a> {
+    options: &'a ReceiveOptions,
+    transfers: Vec<TransferSummary>,
+}
+
+impl<'a> Session<'a> {
+    fn new(options: &'a ReceiveOptions) -> Self {
+        Self {
+            options,
+            transfers: vec![],
+        }
+    }
+
+    /// Returns what should be done about the transfer, if anything.
+    fn handle_message(
+        &mut self,
+        channel_message: &ChannelMessage,
+    ) -> Option<(String, TransferAction)> {
+        let client_msg = channel_message.msg.as_client()?;
+        if !matches!(client_msg.kind, TransferKind::Inbound) {
+            return None;
+        }
+
+        let id = &channel_message.id;
+        let state = client_msg.state.clone().unwrap_or(RqsState::Initial);
+        let metadata = client_msg.metadata.as_ref();
+        let files = metadata
+            .and_then(|it| match &it.payload {
+                Some(TransferPayload::Files(files)) => Some(files.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        if matches!(state, RqsState::WaitingForUserConsent) {
+            let device = metadata
+                .and_then(|it| it.source.as_ref())
+                .map(|it| it.name.clone())
+                .unwrap_or_default();
+            let is_files =
+                metadata.is_none_or(|it| matches!(it.payload_kind, TransferPayloadKind::Files));
+
+            return Some((
+                id.clone(),
+                self.consent_requested(id, &device, is_files, files),
+            ));
+        }
+
+        self.update(
+            id,
+            &state,
+            files,
+            metadata.map(|it| (it.ack_bytes, it.total_bytes)),
+        );
+
+        None
+    }
+
+    fn consent_requested(
+        &mut self,
+        id: &str,
+        device: &str,
+        is_files: bool,
+        files: Vec<String>,
+    ) -> TransferAction {
+        let is_accepted = is_files
+            && self.options.accept_from.iter().any(|it| it == device)
+            && self.transfers.len() < self.options.count;
+        if !is_accepted {
+            tracing::info!(
+                id,
+                device,
+                is_files,
+                "Declining transfer that wasn't asked for"
+            );
+            return TransferAction::ConsentDecline;
+        }
+
+        tracing::info!(id, device, ?files, "Accepting transfer");
+        self.transfers.push(TransferSummary {
+            id: id.to_string(),
+            device: device.to_string(),
+            state: TransferOutcome::Incomplete,
+            received_files: ReceivedFiles::new(&self.options.out, &files),
+            files: files
+                .into_iter()
+                .map(|name| ReceivedFile {
+                    name,
+                    path: None,
+                    size: None,
+                })
+                .collect(),
+            total_bytes: 0,
+            received_bytes: 0,
+        });
+
+        TransferAction::ConsentAccept
+    }
+
+    fn update(
+        &mut self,
+        id: &str,
+        state: &RqsState,
+        files: Vec<String>,
+        bytes: Option<(u64, u64)>,
+    ) {
+        let Some(transfer) = self.transfers.iter_mut().find(|it| it.id == id) else {
+            return;
+        };
+        if transfer.state != TransferOutcome::Incomplete {
+            return;
+        }
+
+        if let Some((ack_bytes, total_bytes)) = bytes {
+            transfer.received_bytes = ack_bytes;
+            transfer.total_bytes = total_bytes;
+        }
+        if transfer.files.is_empty() {
+            transfer.received_files.set_names(&files);
+            transfer.files = files
+                .into_iter()
+                .map(|name| ReceivedFile {
+                    name,
+                    path: None,
+                    size: None,
+                })
+                .collect();
+        }
+
+        transfer.state = match state {
+            RqsState::Finished => TransferOutcome::Finished,
+            RqsState::Rejected => TransferOutcome::Rejected,
+            RqsState::Cancelled => TransferOutcome::Cancelled,
+            RqsState::Disconnected => TransferOutcome::Disconnected,
+            _ => TransferOutcome::Incomplete,
+        };
+        if transfer.state != TransferOutcome::Incomplete {
+            tracing::info!(id, state = ?transfer.state, "Transfer is done");
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.transfers
+            .iter()
+            .filter(|it| it.state != TransferOutcome::Incomplete)
+            .count()
+            >= self.options.count
+    }
+
+    fn status(&self) -> Status {
+        if self
+            .transfers
+            .iter()
+            .all(|it| it.state == TransferOutcome::Finished)
+        {
+            Status::Finished
+        } else {
+            Status::Failed
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    /// All the transfers finished.
+    Finished,
+    /// Some transfer didn't finish.
+    Failed,
+    TimedOut,
+    Interrupted,
+    /// Something went wrong with the service itself.
+    Error,
+}
+
+impl Status {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Status::Finished => 0,
+            Status::Error => 1,
+            Status::TimedOut => 3,
+            Status::Failed => 4,
+            Status::Interrupted => 130,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TransferOutcome {
+    Finished,
+    Rejected,
+    Cancelled,
+    Disconnected,
+    /// Still going when we stopped waiting.
+    Incomplete,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ReceivedFile {
+    name: String,
+    path: Option<PathBuf>,
+    /// Size on disk, if the file is there.
+    size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TransferSummary {
+    id: String,
+    device: String,
+    state: TransferOutcome,
+    files: Vec<ReceivedFile>,
+    total_bytes: u64,
+    received_bytes: u64,
+    /// The output folder from when the transfer was accepted
+    #[serde(skip)]
+    received_files: ReceivedFiles,
+}
+
+impl TransferSummary {
+    /// Fills in where the files were saved, which isn't necessarily under
+    /// their own names if the output folder had files named the same.
+    fn fill_file_sizes(&mut self) {
+        let mut destinations = self.received_files.destinations();
+        for file in &mut self.files {
+            let name = received_files::file_name(&file.name);
+            let path = destinations
+                .iter()
+                .position(|(it, _)| *it == name)
+                .and_then(|it| destinations.remove(it).1);
+            file.size = path
+                .as_ref()
+                .and_then(|it| it.metadata().ok())
+                .map(|it| it.len());
+            file.path = path.filter(|_| file.size.is_some());
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    transfers: Vec<TransferSummary>,
+}
+
+impl Summary {
+    fn error(err: anyhow::Error) -> Self {
+        Self {
+            status: Status::Error,
+            error: Some(format!("{err:#}")),
+            transfers: vec![],
+        }
+    }
+
+    fn print(&self, json: bool) {
+        if json {
+            match serde_json::to_string_pretty(self) {
+                Ok(json) => println!("{json}"),
+                Err(err) => tracing::error!("Couldn't serialize the summary: {err:#}"),
+            }
+            return;
+        }
+
+        for transfer in &self.transfers {
+            println!(
+                "{:?} from {:?}: {} of {} bytes",
+                transfer.state, transfer.device, transfer.received_bytes, transfer.total_bytes
+            );
+            for file in &transfer.files {
+                match (&file.path, file.size) {
+                    (Some(path), Some(size)) => println!("  {} ({size} bytes)", path.display()),
+                    _ => println!("  {} (missing)", file.name),
+                }
+            }
+        }
+
+        match (&self.error, self.status) {
+            (Some(err), _) => eprintln!("packet receive: {err}"),
+            (None, Status::TimedOut) => eprintln!("packet receive: timed out"),
+            (None, Status::Interrupted) => eprintln!("packet receive: interrupted"),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|it| it.to_string()).collect()
+    }
+
+    fn options(count: usize) -> ReceiveOptions {
+        ReceiveOptions {
+            accept_from: vec!["Pixel".into()],
+            out: "/tmp".into(),
+            count,
+            timeout: None,
+            device_name: None,
+            json: true,
+        }
+    }
+
+    #[test]
+    fn parses_all_options() {
+        let options = parse_args(args(&[
+            "--accept-from",
+            "Pixel",
+            "--accept-from=Tablet",
+            "--out",
+            "/tmp/out",
+            "--count=2",
+            "--timeout",
+            "120",
+            "--json",
+        ]))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(options.accept_from, ["Pixel", "Tablet"]);
+        assert_eq!(options.out, PathBuf::from("/tmp/out"));
+        assert_eq!(options.count, 2);
+        assert_eq!(options.timeout, Some(Duration::from_secs(120)));
+        assert!(options.json);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse_args(args(&["--out", "/tmp"])).is_err());
+        assert!(parse_args(args(&["--accept-from", "Pixel"])).is_err());
+        assert!(
+            parse_args(args(&[
+                "--accept-from",
+                "Pixel",
+                "--out",
+                "/tmp",
+                "--count",
+                "0"
+            ]))
+            .is_err()
+        );
+        assert!(parse_args(args(&["--accept-from"])).is_err());
+        assert!(
+            parse_args(args(&[
+                "--accept-from",
+                "Pixel",
+                "--out",
+                "/tmp",
+                "--bogus"
+            ]))
+            .is_err()
+        );
+        assert_eq!(parse_args(args(&["--help"])).unwrap(), None);
+    }
+
+    #[test]
+    fn accepts_only_matching_devices() {
+        let options = options(1);
+        let mut session = Session::new(&options);
+
+        assert!(matches!(
+            session.consent_requested("1", "Someone else", true, vec![]),
+            TransferAction::ConsentDecline
+        ));
+        assert!(matches!(
+            session.consent_requested("2", "Pixel", false, vec![]),
+            TransferAction::ConsentDecline
+        ));
+        assert!(matches!(
+            session.consent_requested("3", "Pixel", true, vec!["a.jpg".into()]),
+            TransferAction::ConsentAccept
+        ));
+        // Only as many as were asked for
+        assert!(matches!(
+            session.consent_requested("4", "Pixel", true, vec![]),
+            TransferAction::ConsentDecline
+        ));
+        assert_eq!(session.transfers.len(), 1);
+    }
+
+    #[test]
+    fn done_once_enough_transfers_are_over() {
+        let options = options(2);
+        let mut session = Session::new(&options);
+
+        session.consent_requested("1", "Pixel", true, vec!["a.jpg".into()]);
+        session.consent_requested("2", "Pixel", true, vec!["b.jpg".into()]);
+        session.update("1", &RqsState::ReceivingFiles, vec![], Some((50, 100)));
+        assert!(!session.is_done());
+
+        session.update("1", &RqsState::Finished, vec![], Some((100, 100)));
+        assert!(!session.is_done());
+
+        session.update("2", &RqsState::Disconnected, vec![], Some((10, 100)));
+        // Later events don't change how it ended
+        session.update("2", &RqsState::Finished, vec![], Some((100, 100)));
+        assert!(session.is_done());
+        assert_eq!(session.status(), Status::Failed);
+        assert_eq!(session.transfers[0].received_bytes, 100);
+        assert_eq!(session.transfers[1].state, TransferOutcome::Disconnected);
+    }
+
+    #[test]
+    fn summary_has_where_files_were_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("photo.jpg"), "mine").unwrap();
+        let options = ReceiveOptions {
+            out: dir.path().into(),
+            ..options(1)
+        };
+        let mut session = Session::new(&options);
+
+        session.consent_requested("1", "Pixel", true, vec!["photo.jpg".into()]);
+        std::fs::write(dir.path().join("photo (1).jpg"), "received").unwrap();
+        session.update("1", &RqsState::Finished, vec![], Some((8, 8)));
+
+        let transfer = &mut session.transfers[0];
+        transfer.fill_file_sizes();
+        assert_eq!(
+            transfer.files[0].path,
+            Some(dir.path().join("photo (1).jpg"))
+        );
+        assert_eq!(transfer.files[0].size, Some(8));
+    }
+
+    #[test]
+    fn summary_has_sizes_of_files_known_after_consent() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ReceiveOptions {
+            out: dir.path().into(),
+            ..options(1)
+        };
+        let mut session = Session::new(&options);
+
+        session.consent_requested("1", "Pixel", true, vec![]);
+        std::fs::write(dir.path().join("b.jpg"), "bb").unwrap();
+        std::fs::write(dir.path().join("a.jpg"), "a").unwrap();
+        session.update(
+            "1",
+            &RqsState::Finished,
+            vec!["b.jpg".into(), "a.jpg".into()],
+            Some((3, 3)),
+        );
+
+        let transfer = &mut session.transfers[0];
+        transfer.fill_file_sizes();
+        assert_eq!(transfer.files[0].path, Some(dir.path().join("b.jpg")));
+        assert_eq!(transfer.files[0].size, Some(2));
+        assert_eq!(transfer.files[1].path, Some(dir.path().join("a.jpg")));
+        assert_eq!(transfer.files[1].size, Some(1));
+    }
+}
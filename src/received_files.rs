@@ -0,0 +1,237 @@
+//! Finding the files a receive actually wrote.
+//!
+//! `rqs_lib` only reports the names of the incoming files, not where it saved
+//! them. A name that's already taken in the download folder gets a number
+//! added to it, so going by the names alone points at some other file. Instead,
+//! the folder is looked at once the transfer is accepted, and compared with
+//! what's in it later on. Only files that weren't there before count, so that
+//! a file someone else happened to write to meanwhile is never taken for one
+//! of the transfer's.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// The download folder as it was when a transfer was accepted, along with the
+/// names of the files the transfer is going to write.
+#[derive(Debug, Clone, Default)]
+pub struct ReceivedFiles {
+    folder: PathBuf,
+    names: Vec<String>,
+    before: HashSet<OsString>,
+}
+
+impl ReceivedFiles {
+    /// Meant to be called as the transfer is accepted, before anything's
+    /// written.
+    pub fn new(folder: &Path, names: &[String]) -> Self {
+        Self {
+            folder: folder.to_path_buf(),
+            names: names.iter().map(|it| file_name(it)).collect(),
+            before: file_names(folder),
+        }
+    }
+
+    /// For when the names only become known after the transfer's accepted.
+    pub fn set_names(&mut self, names: &[String]) {
+        self.names = names.iter().map(|it| file_name(it)).collect();
+    }
+
+    /// Files that were created since, and are named after one of the
+    /// transfer's files.
+    pub fn written(&self) -> Vec<PathBuf> {
+        let mut written = file_names(&self.folder)
+            .into_iter()
+            .filter(|name| !self.before.contains(name))
+            .filter(|name| {
+                let name = name.to_string_lossy();
+                self.names.iter().any(|it| is_saved_as(it, &name))
+            })
+            .map(|name| self.folder.join(name))
+            .collect::<Vec<_>>();
+        written.sort();
+
+        written
+    }
+
+    /// Where each of the transfer's files ended up, in the order of the
+    /// names. `None` for files that weren't written.
+    pub fn destinations(&self) -> Vec<(String, Option<PathBuf>)> {
+        let mut written = self.written();
+
+        // Exact names first, so that a renamed copy isn't taken for the file
+        // that kept its name
+        let mut destinations = self
+            .names
+            .iter()
+            .map(|name| {
+                let pos = written
+                    .iter()
+                    .position(|it| it.file_name().is_some_and(|it| it == name.as_str()));
+                (name.clone(), pos.map(|it| written.remove(it)))
+            })
+            .collect::<Vec<_>>();
+        for (name, path) in destinations.iter_mut().filter(|(_, it)| it.is_none()) {
+            let pos = written.iter().position(|it| {
+                it.file_name()
+                    .is_some_and(|it| is_saved_as(name, &it.to_string_lossy()))
+            });
+            *path = pos.map(|it| written.remove(it));
+        }
+
+        destinations
+    }
+}
+
+/// The name a file's saved under in the folder, going by `name`. Never lets
+/// it point outside of the folder.
+pub fn file_name(name: &str) -> String {
+    Path::new(name)
+        .file_name()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn file_names(folder: &Path) -> HashSet<OsString> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return HashSet::new();
+    };
+
+    entries
+        .filter_map(|it| it.ok())
+        .filter(|it| it.metadata().is_ok_and(|it| it.is_file()))
+        .map(|it| it.file_name())
+        .collect()
+}
+
+/// Whether a file named `name` could've been saved as `saved_name`, i.e. as is
+/// or with a number added to make the name unique, e.g. `photo (1).jpg` or
+/// `photo_2.jpg` for `photo.jpg`.
+fn is_saved_as(name: &str, saved_name: &str) -> bool {
+    if name.is_empty() {
+        return false;
+    }
+    if name == saved_name {
+        return true;
+    }
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name, None),
+    };
+    let Some(rest) = saved_name.strip_prefix(stem) else {
+        return false;
+    };
+    let suffix = match extension {
+        Some(extension) => rest
+            .strip_suffix(extension)
+            .and_then(|it| it.strip_suffix('.')),
+        None => Some(rest),
+    };
+    let Some(suffix) = suffix else {
+        return false;
+    };
+
+    let suffix = suffix.strip_prefix([' ', '_', '-', '.']).unwrap_or(suffix);
+    let number = suffix
+        .strip_prefix('(')
+        .and_then(|it| it.strip_suffix(')'))
+        .unwrap_or(suffix);
+
+    !number.is_empty() && number.chars().all(|it| it.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|it| it.to_string()).collect()
+    }
+
+    #[test]
+    fn matches_renamed_copies() {
+        assert!(is_saved_as("photo.jpg", "photo.jpg"));
+        assert!(is_saved_as("photo.jpg", "photo (1).jpg"));
+        assert!(is_saved_as("photo.jpg", "photo_2.jpg"));
+        assert!(is_saved_as("photo.jpg", "photo-12.jpg"));
+        assert!(is_saved_as("README", "README (3)"));
+        assert!(is_saved_as(".bashrc", ".bashrc (1)"));
+
+        assert!(!is_saved_as("photo.jpg", "photo.png"));
+        assert!(!is_saved_as("photo.jpg", "photo (1).jpg.crdownload"));
+        assert!(!is_saved_as("photo.jpg", "photograph.jpg"));
+        assert!(!is_saved_as("photo.jpg", "photo ().jpg"));
+    }
+
+    #[test]
+    fn finds_where_files_were_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("photo.jpg"), "mine").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "mine").unwrap();
+
+        let files = ReceivedFiles::new(
+            dir.path(),
+            &names(&["photo.jpg", "video.mp4", "../escape.txt", "unwritten.txt"]),
+        );
+        std::fs::write(dir.path().join("photo (1).jpg"), "received").unwrap();
+        std::fs::write(dir.path().join("video.mp4"), "received").unwrap();
+        std::fs::write(dir.path().join("escape.txt"), "received").unwrap();
+        // Not from the transfer
+        std::fs::write(dir.path().join("download.zip"), "someone else's").unwrap();
+
+        assert_eq!(
+            files.written(),
+            vec![
+                dir.path().join("escape.txt"),
+                dir.path().join("photo (1).jpg"),
+                dir.path().join("video.mp4"),
+            ]
+        );
+        assert_eq!(
+            files.destinations(),
+            vec![
+                ("photo.jpg".into(), Some(dir.path().join("photo (1).jpg"))),
+                ("video.mp4".into(), Some(dir.path().join("video.mp4"))),
+                ("escape.txt".into(), Some(dir.path().join("escape.txt"))),
+                ("unwritten.txt".into(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_files_that_were_already_there() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("report.pdf"), "mine").unwrap();
+        std::fs::write(dir.path().join("report (1).pdf"), "also mine").unwrap();
+
+        let files = ReceivedFiles::new(dir.path(), &names(&["report.pdf"]));
+        // Edited by something else while the transfer's going on
+        std::fs::write(dir.path().join("report (1).pdf"), "edited").unwrap();
+
+        assert!(files.written().is_empty());
+        assert_eq!(files.destinations(), vec![("report.pdf".into(), None)]);
+
+        std::fs::write(dir.path().join("report (2).pdf"), "received").unwrap();
+        assert_eq!(files.written(), vec![dir.path().join("report (2).pdf")]);
+    }
+
+    #[test]
+    fn same_names_go_to_separate_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let files = ReceivedFiles::new(dir.path(), &names(&["a/scan.pdf", "b/scan.pdf"]));
+        std::fs::write(dir.path().join("scan.pdf"), "first").unwrap();
+        std::fs::write(dir.path().join("scan (1).pdf"), "second").unwrap();
+
+        assert_eq!(
+            files.destinations(),
+            vec![
+                ("scan.pdf".into(), Some(dir.path().join("scan.pdf"))),
+                ("scan.pdf".into(), Some(dir.path().join("scan (1).pdf"))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Keeping secrets in the keyring, through the Secret Service D-Bus API.
+//!
+//! Secrets are only passed over the session bus, so they're sent as is, with
+//! the `plain` algorithm.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, bail};
+use futures_lite::StreamExt;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+use crate::config::APP_ID;
+
+const SERVICE: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+
+/// The session, parameters, value and content type.
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// The attributes the secret called `name` is stored with.
+fn attributes(name: &str) -> HashMap<&str, &str> {
+    HashMap::from([("xdg:schema", APP_ID), ("name", name)])
+}
+
+struct Session {
+    conn: zbus::Connection,
+    service: zbus::Proxy<'static>,
+    path: OwnedObjectPath,
+}
+
+impl Session {
+    async fn open() -> anyhow::Result<Self> {
+        let conn = zbus::Connection::session().await?;
+        let service = zbus::Proxy::new(
+            &conn,
+            SERVICE,
+            SERVICE_PATH,
+            "org.freedesktop.Secret.Service",
+        )
+        .await?;
+        let (_, path): (OwnedValue, OwnedObjectPath) = service
+            .call("OpenSession", &("plain", Value::from("")))
+            .await
+            .context("Couldn't open a Secret Service session")?;
+
+        Ok(Self {
+            conn,
+            service,
+            path,
+        })
+    }
+
+    async fn proxy(
+        &self,
+        path: OwnedObjectPath,
+        interface: &'static str,
+    ) -> zbus::Result<zbus::Proxy<'static>> {
+        zbus::Proxy::new(&self.conn, SERVICE, path, interface).await
+    }
+
+    /// Unlocked items with the secret's attributes.
+    async fn items(&self, name: &str) -> anyhow::Result<Vec<OwnedObjectPath>> {
+        let (mut unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = self
+            .service
+            .call("SearchItems", &(attributes(name),))
+            .await?;
+        if !locked.is_empty() {
+            unlocked.extend(self.unlock(locked).await?);
+        }
+
+        Ok(unlocked)
+    }
+
+    /// Might ask the user to unlock the keyring.
+    async fn unlock(&self, objects: Vec<OwnedObjectPath>) -> anyhow::Result<Vec<OwnedObjectPath>> {
+        let (unlocked, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) =
+            self.service.call("Unlock", &(objects,)).await?;
+        if prompt.as_str() == "/" {
+            return Ok(unlocked);
+        }
+
+        let result = self.prompt(prompt).await?;
+        Ok(Vec::<OwnedObjectPath>::try_from(result)?)
+    }
+
+    /// Returns the result of the prompt, if it wasn't dismissed.
+    async fn prompt(&self, path: OwnedObjectPath) -> anyhow::Result<OwnedValue> {
+        let prompt = self.proxy(path, "org.freedesktop.Secret.Prompt").await?;
+        let mut completed = prompt.receive_signal("Completed").await?;
+        prompt.call_method("Prompt", &("",)).await?;
+
+        let message = completed
+            .next()
+            .await
+            .context("Prompt went away without completing")?;
+        let (dismissed, result): (bool, OwnedValue) = message.body().deserialize()?;
+        if dismissed {
+            bail!("Prompt was dismissed");
+        }
+
+        Ok(result)
+    }
+
+    async fn close(self) {
+        if let Ok(session) = self
+            .proxy(self.path.clone(), "org.freedesktop.Secret.Session")
+            .await
+        {
+            _ = session.call_method("Close", &()).await;
+        }
+    }
+}
+
+/// Looks up the secret called `name`.
+pub async fn lookup(name: &str) -> anyhow::Result<Option<String>> {
+    let session = Session::open().await?;
+    let result = async {
+        let Some(item) = session.items(name).await?.into_iter().next() else {
+            return Ok(None);
+        };
+        let item = session.proxy(item, "org.freedesktop.Secret.Item").await?;
+        let (_, _, value, _): Secret = item.call("GetSecret", &(&session.path,)).await?;
+
+        Ok(Some(
+            String::from_utf8(value).context("Secret isn't valid UTF-8")?,
+        ))
+    }
+    .await;
+    session.close().await;
+
+    result
+}
+
+/// Stores the secret called `name`, replacing the one that's there. It's
+/// removed if `secret` is empty.
+pub async fn store(name: &str, label: &str, secret: &str) -> anyhow::Result<()> {
+    let session = Session::open().await?;
+    let result = async {
+        if secret.is_empty() {
+            for item in session.items(name).await? {
+                let item = session.proxy(item, "org.freedesktop.Secret.Item").await?;
+                let prompt: OwnedObjectPath = item.call("Delete", &()).await?;
+                if prompt.as_str() != "/" {
+                    session.prompt(prompt).await?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        let collection = OwnedObjectPath::try_from(DEFAULT_COLLECTION)?;
+        session.unlock(vec![collection.clone()]).await?;
+        let collection = session
+            .proxy(collection, "org.freedesktop.Secret.Collection")
+            .await?;
+
+        let properties = HashMap::from([
+            ("org.freedesktop.Secret.Item.Label", Value::from(label)),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                Value::from(attributes(name)),
+            ),
+        ]);
+        let secret: Secret = (
+            session.path.clone(),
+            vec![],
+            secret.as_bytes().to_vec(),
+            "text/plain".to_string(),
+        );
+        let (_, prompt): (OwnedObjectPath, OwnedObjectPath) = collection
+            .call("CreateItem", &(properties, secret, true))
+            .await
+            .context("Couldn't add the secret to the keyring")?;
+        if prompt.as_str() != "/" {
+            session.prompt(prompt).await?;
+        }
+
+        Ok(())
+    }
+    .await;
+    session.close().await;
+
+    result
+}
@@ -0,0 +1,516 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, anyhow};
+use rqs_lib::channel::{ChannelMessage, TransferAction};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+use crate::{backend::QuickShareBackend, tokio_runtime};
+
+/// Commands understood by the service task, see [`ServiceHandle`].
+#[derive(Debug)]
+pub enum ServiceCommand {
+    /// Accept, decline or cancel the transfer with the given id.
+    TransferAction {
+        id: String,
+        action: TransferAction,
+    },
+    Send(rqs_lib::SendInfo),
+    StartDiscovery,
+    StopDiscovery,
+    /// Stops and starts discovery again, so that all the nearby endpoints get
+    /// announced again.
+    RestartDiscovery,
+    SetVisibility(rqs_lib::Visibility),
+    SetDownloadPath(PathBuf),
+    Stop {
+        done: oneshot::Sender<()>,
+    },
+}
+
+impl ServiceCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            ServiceCommand::TransferAction { .. } => "transfer-action",
+            ServiceCommand::Send(_) => "send",
+            ServiceCommand::StartDiscovery => "start-discovery",
+            ServiceCommand::StopDiscovery => "stop-discovery",
+            ServiceCommand::RestartDiscovery => "restart-discovery",
+            ServiceCommand::SetVisibility(_) => "set-visibility",
+            ServiceCommand::SetDownloadPath(_) => "set-download-path",
+            ServiceCommand::Stop { .. } => "stop",
+        }
+    }
+}
+
+/// Sent back to the UI by the service task.
+#[derive(Debug)]
+pub enum ServiceEvent {
+    CommandFailed {
+        command: &'static str,
+        err: anyhow::Error,
+    },
+}
+
+/// The UI's way of talking to the RQS service.
+///
+/// The backend is owned by a single task on the Tokio runtime, and
+/// everything else goes through the command channel. That way the GTK main
+/// thread never has to wait on a lock to get something done.
+#[derive(Debug, Clone)]
+pub struct ServiceHandle {
+    commands_tx: mpsc::UnboundedSender<ServiceCommand>,
+    message_sender: broadcast::Sender<ChannelMessage>,
+    visibility_sender: Arc<Mutex<watch::Sender<rqs_lib::Visibility>>>,
+    port_number: Option<u32>,
+}
+
+impl ServiceHandle {
+    /// Moves the backend into a new service task.
+    ///
+    /// `file_sender` is from what [`QuickShareBackend::run`] returned, and is
+    /// `None` if the service failed to start, in which case the task is only
+    /// around to clean up.
+    pub fn spawn<B: QuickShareBackend>(
+        backend: B,
+        file_sender: Option<mpsc::Sender<rqs_lib::SendInfo>>,
+        discovery_tx: broadcast::Sender<rqs_lib::EndpointInfo>,
+        events_tx: async_channel::Sender<ServiceEvent>,
+    ) -> Self {
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+
+        let handle = Self {
+            commands_tx,
+            message_sender: backend.message_sender(),
+            visibility_sender: backend.visibility_sender(),
+            port_number: backend.port_number(),
+        };
+
+        tokio_runtime().spawn(
+            ServiceTask {
+                backend,
+                file_sender,
+                discovery_tx,
+                events_tx,
+            }
+            .run(commands_rx),
+        );
+
+        handle
+    }
+
+    pub fn send(&self, command: ServiceCommand) {
+        if let Err(err) = self.commands_tx.send(command) {
+            tracing::warn!(
+                command = err.0.name(),
+                "Service task is gone, dropping command"
+            );
+        }
+    }
+
+    /// Stops the service and waits for it to finish.
+    pub async fn stop(&self) {
+        let (done, done_rx) = oneshot::channel();
+        self.send(ServiceCommand::Stop { done });
+        _ = done_rx.await;
+    }
+
+    pub fn subscribe_messages(&self) -> broadcast::Receiver<ChannelMessage> {
+        self.message_sender.subscribe()
+    }
+
+    pub fn subscribe_visibility(&self) -> watch::Receiver<rqs_lib::Visibility> {
+        self.visibility_sender.lock().unwrap().subscribe()
+    }
+
+    pub fn port_number(&self) -> Option<u32> {
+        self.port_number
+    }
+}
+
+struct ServiceTask<B> {
+    backend: B,
+    file_sender: Option<mpsc::Sender<rqs_lib::SendInfo>>,
+    discovery_tx: broadcast::Sender<rqs_lib::EndpointInfo>,
+    events_tx: async_channel::Sender<ServiceEvent>,
+}
+
+impl<B: QuickShareBackend> ServiceTask<B> {
+    async fn run(mut self, mut commands_rx: mpsc::UnboundedReceiver<ServiceCommand>) {
+        while let Some(command) = commands_rx.recv().await {
+            let name = command.name();
+            tracing::debug!(command = name, "Handling service command");
+
+            if let ServiceCommand::Stop { done } = command {
+                self.backend.stop().await;
+                tracing::info!("Stopped RQS service");
+                _ = done.send(());
+                break;
+            }
+
+            if let Err(err) = self.handle(command) {
+                self.report_failure(name, err);
+            }
+        }
+    }
+
+    fn handle(&mut self, command: ServiceCommand) -> anyhow::Result<()> {
+        match command {
+            ServiceCommand::TransferAction { id, action } => {
+                self.backend
+                    .message_sender()
+                    .send(ChannelMessage {
+                        id,
+                        msg: rqs_lib::channel::Message::Lib { action },
+                    })
+                    .map_err(|_| anyhow!("No one is listening for transfer actions"))?;
+            }
+            ServiceCommand::Send(send_info) => {
+                let file_sender = self.file_sender.clone().context("Service isn't running")?;
+
+                // Could be waiting on the current transfer, so don't hold up
+                // the rest of the commands for it
+                let events_tx = self.events_tx.clone();
+                tokio::spawn(async move {
+                    if file_sender.send(send_info).await.is_err() {
+                        _ = events_tx
+                            .send(ServiceEvent::CommandFailed {
+                                command: "send",
+                                err: anyhow!("Service stopped before the files could be sent"),
+                            })
+                            .await;
+                    }
+                });
+            }
+            ServiceCommand::StartDiscovery => {
+                self.backend
+                    .start_discovery(self.discovery_tx.clone())
+                    .context("Failed to start mDNS discovery task")?;
+            }
+            ServiceCommand::StopDiscovery => self.backend.stop_discovery(),
+            ServiceCommand::RestartDiscovery => {
+                self.backend.stop_discovery();
+                self.backend
+                    .start_discovery(self.discovery_tx.clone())
+                    .context("Failed to restart mDNS discovery task")?;
+            }
+            ServiceCommand::SetVisibility(visibility) => self.backend.set_visibility(visibility),
+            ServiceCommand::SetDownloadPath(path) => self.backend.set_download_path(path),
+            ServiceCommand::Stop { .. } => unreachable!("Handled by the run loop"),
+        };
+
+        Ok(())
+    }
+
+    fn report_failure(&self, command: &'static str, err: anyhow::Error) {
+        tracing::error!(command, err = format!("{err:#}"), "Service command failed");
+        _ = self
+            .events_tx
+            .try_send(ServiceEvent::CommandFailed { command, err });
+    }
+}
+
+#[cfg(test)]
+mod loopback;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rqs_lib::{
+        TransferState as RqsState,
+        channel::{TransferAction, TransferKind},
+    };
+
+    use super::*;
+    use crate::{
+        backend::{
+            QuickShareBackend,
+            scripted::{Recorded, ScriptedBackend, Step, event},
+        },
+        objects::{self, UserAction},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    struct Harness {
+        handle: ServiceHandle,
+        messages_rx: broadcast::Receiver<ChannelMessage>,
+        events_rx: async_channel::Receiver<ServiceEvent>,
+        recorded: Arc<Mutex<Recorded>>,
+    }
+
+    impl Harness {
+        async fn start(mut backend: ScriptedBackend) -> Self {
+            let recorded = backend.recorded();
+            // Subscribed before `run`, so that nothing the script emits is missed
+            let messages_rx = backend.message_sender().subscribe();
+            let file_sender = backend.run().await.ok().map(|it| it.file_sender);
+
+            let (events_tx, events_rx) = async_channel::unbounded();
+            let handle =
+                ServiceHandle::spawn(backend, file_sender, broadcast::channel(1).0, events_tx);
+
+            Self {
+                handle,
+                messages_rx,
+                events_rx,
+                recorded,
+            }
+        }
+
+        /// Next state reported for a transfer, skipping over library messages.
+        async fn next_state(&mut self) -> RqsState {
+            loop {
+                let msg = tokio::time::timeout(TIMEOUT, self.messages_rx.recv())
+                    .await
+                    .expect("Timed out waiting for an event")
+                    .expect("Message channel closed");
+                if let Some(client_msg) = msg.msg.as_client() {
+                    return client_msg.state.clone().unwrap_or(RqsState::Initial);
+                }
+            }
+        }
+
+        fn user_action(&self, id: &str, action: UserAction) {
+            self.handle.send(ServiceCommand::TransferAction {
+                id: id.into(),
+                action: action.into(),
+            });
+        }
+    }
+
+    fn inbound(state: RqsState, ack_bytes: u64) -> Step {
+        Step::Emit(Box::new(event(
+            "inbound",
+            TransferKind::Inbound,
+            state,
+            ack_bytes,
+        )))
+    }
+
+    fn outbound(state: RqsState, ack_bytes: u64) -> Step {
+        Step::Emit(Box::new(event(
+            "outbound",
+            TransferKind::Outbound,
+            state,
+            ack_bytes,
+        )))
+    }
+
+    /// The recipient card's state after going through all the given states.
+    fn card_state(states: &[RqsState]) -> objects::TransferState {
+        states
+            .iter()
+            .filter_map(objects::TransferState::from_outbound)
+            .fold(objects::TransferState::Queued, |_, it| it)
+    }
+
+    #[tokio::test]
+    async fn inbound_consent_accepted() {
+        let mut harness = Harness::start(ScriptedBackend::new(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentAccept),
+            inbound(RqsState::ReceivingFiles, 50),
+            inbound(RqsState::ReceivingFiles, 100),
+            inbound(RqsState::Finished, 100),
+        ]))
+        .await;
+
+        assert!(matches!(
+            harness.next_state().await,
+            RqsState::WaitingForUserConsent
+        ));
+        harness.user_action("inbound", UserAction::ConsentAccept);
+
+        assert!(matches!(
+            harness.next_state().await,
+            RqsState::ReceivingFiles
+        ));
+        assert!(matches!(
+            harness.next_state().await,
+            RqsState::ReceivingFiles
+        ));
+        assert!(matches!(harness.next_state().await, RqsState::Finished));
+
+        let recorded = harness.recorded.lock().unwrap();
+        assert!(matches!(
+            recorded.actions.as_slice(),
+            [TransferAction::ConsentAccept]
+        ));
+    }
+
+    #[tokio::test]
+    async fn inbound_consent_declined() {
+        let mut harness = Harness::start(ScriptedBackend::new(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentDecline),
+            inbound(RqsState::Rejected, 0),
+        ]))
+        .await;
+
+        assert!(matches!(
+            harness.next_state().await,
+            RqsState::WaitingForUserConsent
+        ));
+        harness.user_action("inbound", UserAction::ConsentDecline);
+        assert!(matches!(harness.next_state().await, RqsState::Rejected));
+    }
+
+    #[tokio::test]
+    async fn inbound_cancelled_midway() {
+        let mut harness = Harness::start(ScriptedBackend::new(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentAccept),
+            inbound(RqsState::ReceivingFiles, 30),
+            Step::WaitForAction(TransferAction::TransferCancel),
+            inbound(RqsState::Cancelled, 30),
+        ]))
+        .await;
+
+        assert!(matches!(
+            harness.next_state().await,
+            RqsState::WaitingForUserConsent
+        ));
+        harness.user_action("inbound", UserAction::ConsentAccept);
+        assert!(matches!(
+            harness.next_state().await,
+            RqsState::ReceivingFiles
+        ));
+        harness.user_action("inbound", UserAction::TransferCancel);
+        assert!(matches!(harness.next_state().await, RqsState::Cancelled));
+
+        let recorded = harness.recorded.lock().unwrap();
+        assert!(matches!(
+            recorded.actions.as_slice(),
+            [
+                TransferAction::ConsentAccept,
+                TransferAction::TransferCancel
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn outbound_send_finishes() {
+        let states = [
+            RqsState::SentIntroduction,
+            RqsState::SendingFiles,
+            RqsState::SendingFiles,
+            RqsState::Finished,
+        ];
+        let mut steps = vec![Step::WaitForSend];
+        steps.extend(states.iter().map(|it| outbound(it.clone(), 50)));
+        let mut harness = Harness::start(ScriptedBackend::new(steps)).await;
+
+        harness.handle.send(ServiceCommand::Send(rqs_lib::SendInfo {
+            id: "outbound".into(),
+            name: "Phone".into(),
+            addr: "127.0.0.1:1234".into(),
+            ob: rqs_lib::OutboundPayload::Files(vec!["/tmp/file.txt".into()]),
+        }));
+
+        let mut seen = vec![];
+        for _ in 0..states.len() {
+            seen.push(harness.next_state().await);
+        }
+        assert_eq!(
+            card_state(&seen[..1]),
+            objects::TransferState::RequestedForConsent
+        );
+        assert_eq!(
+            card_state(&seen[..2]),
+            objects::TransferState::OngoingTransfer
+        );
+        assert_eq!(card_state(&seen), objects::TransferState::Done);
+
+        let recorded = harness.recorded.lock().unwrap();
+        assert_eq!(recorded.sends.len(), 1);
+        assert_eq!(recorded.sends[0].id, "outbound");
+    }
+
+    #[tokio::test]
+    async fn outbound_disconnect_fails_card() {
+        let mut harness = Harness::start(ScriptedBackend::new(vec![
+            outbound(RqsState::SentIntroduction, 0),
+            outbound(RqsState::SendingFiles, 10),
+            outbound(RqsState::Disconnected, 10),
+        ]))
+        .await;
+
+        let mut seen = vec![];
+        for _ in 0..3 {
+            seen.push(harness.next_state().await);
+        }
+        assert_eq!(card_state(&seen), objects::TransferState::Failed);
+    }
+
+    #[tokio::test]
+    async fn outbound_cancel_resets_card() {
+        let mut harness = Harness::start(ScriptedBackend::new(vec![
+            outbound(RqsState::SentIntroduction, 0),
+            Step::WaitForAction(TransferAction::TransferCancel),
+            outbound(RqsState::Cancelled, 0),
+        ]))
+        .await;
+
+        let first = harness.next_state().await;
+        harness.user_action("outbound", UserAction::TransferCancel);
+        let second = harness.next_state().await;
+        assert_eq!(
+            card_state(&[first, second]),
+            objects::TransferState::AwaitingConsentOrIdle
+        );
+    }
+
+    #[tokio::test]
+    async fn send_fails_if_service_never_started() {
+        let harness =
+            Harness::start(ScriptedBackend::failing(anyhow!("Address already in use"))).await;
+
+        harness.handle.send(ServiceCommand::Send(rqs_lib::SendInfo {
+            id: "outbound".into(),
+            name: "Phone".into(),
+            addr: "127.0.0.1:1234".into(),
+            ob: rqs_lib::OutboundPayload::Files(vec![]),
+        }));
+
+        let event = tokio::time::timeout(TIMEOUT, harness.events_rx.recv())
+            .await
+            .expect("Timed out waiting for the failure")
+            .unwrap();
+        assert!(matches!(
+            event,
+            ServiceEvent::CommandFailed {
+                command: "send",
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn commands_reach_backend_and_stop_waits() {
+        let harness = Harness::start(ScriptedBackend::new(vec![])).await;
+
+        harness.handle.send(ServiceCommand::StartDiscovery);
+        harness.handle.send(ServiceCommand::SetVisibility(
+            rqs_lib::Visibility::Invisible,
+        ));
+        tokio::time::timeout(TIMEOUT, harness.handle.stop())
+            .await
+            .expect("Timed out waiting for the service to stop");
+
+        let recorded = harness.recorded.lock().unwrap();
+        assert!(recorded.discovery_running);
+        assert!(matches!(
+            recorded.visibility.as_slice(),
+            [rqs_lib::Visibility::Invisible]
+        ));
+        assert!(recorded.stopped);
+        assert!(matches!(
+            *harness.handle.subscribe_visibility().borrow(),
+            rqs_lib::Visibility::Invisible
+        ));
+    }
+}
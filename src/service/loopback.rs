@@ -0,0 +1,342 @@
+//! End-to-end transfers between two Packet services over loopback.
+//!
+//! Each peer is a [`ServiceHandle`] around a real `rqs_lib` backend, started
+//! invisible with a static port, and transfers are addressed manually to
+//! `127.0.0.1:<port>`, so neither mDNS nor BLE is involved. The point is to
+//! catch behavior changes whenever the pinned `rqs_lib` revision is bumped.
+
+use std::{mem, path::PathBuf, time::Duration};
+
+use rqs_lib::{
+    OutboundPayload, RQS, SendInfo, TransferState, Visibility,
+    channel::{TransferAction, TransferKind},
+};
+use tempfile::TempDir;
+
+use super::*;
+use crate::backend::{BackendChannels, QuickShareBackend};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Peer {
+    name: String,
+    service: ServiceHandle,
+    port: u32,
+    download_dir: TempDir,
+}
+
+impl Peer {
+    async fn start(name: &str) -> Self {
+        let download_dir = tempfile::tempdir().unwrap();
+        let port = free_port();
+
+        let mut backend = RQS::new(
+            Visibility::Invisible,
+            Some(port),
+            Some(download_dir.path().to_path_buf()),
+            Some(name.to_string()),
+        );
+        let BackendChannels { file_sender, .. } = QuickShareBackend::run(&mut backend)
+            .await
+            .expect("Peer failed to start");
+
+        let (events_tx, _) = async_channel::unbounded();
+        let service = ServiceHandle::spawn(
+            backend,
+            Some(file_sender),
+            broadcast::channel(1).0,
+            events_tx,
+        );
+
+        Self {
+            name: name.to_string(),
+            service,
+            port,
+            download_dir,
+        }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ChannelMessage> {
+        self.service.subscribe_messages()
+    }
+
+    /// Replies to every inbound consent request with `action`.
+    fn answer_consent(&self, action: TransferAction) -> tokio::task::JoinHandle<()> {
+        let service = self.service.clone();
+        let mut messages_rx = self.subscribe();
+        tokio::spawn(async move {
+            while let Ok(msg) = messages_rx.recv().await {
+                let is_consent_request = msg.msg.as_client().is_some_and(|it| {
+                    matches!(it.kind, TransferKind::Inbound)
+                        && matches!(it.state, Some(TransferState::WaitingForUserConsent))
+                });
+                if is_consent_request {
+                    service.send(ServiceCommand::TransferAction {
+                        id: msg.id,
+                        action: action.clone(),
+                    });
+                }
+            }
+        })
+    }
+
+    fn send_files(&self, to: &Peer, files: &[PathBuf]) {
+        self.service.send(ServiceCommand::Send(SendInfo {
+            id: to.name.clone(),
+            name: to.name.clone(),
+            addr: format!("127.0.0.1:{}", to.port),
+            ob: OutboundPayload::Files(
+                files
+                    .iter()
+                    .map(|it| it.to_string_lossy().into_owned())
+                    .collect(),
+            ),
+        }));
+    }
+
+    fn received(&self, file_name: &str) -> PathBuf {
+        self.download_dir.path().join(file_name)
+    }
+}
+
+fn free_port() -> u32 {
+    let listener = std::net::TcpListener::bind(("0.0.0.0", 0)).unwrap();
+    listener.local_addr().unwrap().port() as u32
+}
+
+/// Waits for the transfer of the given kind to reach one of the final states.
+async fn final_state(
+    messages_rx: &mut broadcast::Receiver<ChannelMessage>,
+    kind: TransferKind,
+) -> TransferState {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let msg = match messages_rx.recv().await {
+                Ok(msg) => msg,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => panic!("Peer stopped"),
+            };
+            let Some(client_msg) = msg.msg.as_client() else {
+                continue;
+            };
+            if mem::discriminant(&client_msg.kind) != mem::discriminant(&kind) {
+                continue;
+            }
+
+            if let Some(
+                state @ (TransferState::Finished
+                | TransferState::Cancelled
+                | TransferState::Rejected
+                | TransferState::Disconnected),
+            ) = &client_msg.state
+            {
+                return state.clone();
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for the transfer to end")
+}
+
+/// Waits until the inbound transfer starts writing files.
+async fn receiving_started(messages_rx: &mut broadcast::Receiver<ChannelMessage>) -> String {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            if let Ok(msg) = messages_rx.recv().await
+                && let Some(client_msg) = msg.msg.as_client()
+                && matches!(client_msg.kind, TransferKind::Inbound)
+                && matches!(client_msg.state, Some(TransferState::ReceivingFiles))
+            {
+                return msg.id;
+            }
+        }
+    })
+    .await
+    .expect("Timed out waiting for the transfer to start")
+}
+
+fn write_file(dir: &TempDir, name: &str, len: usize) -> (PathBuf, Vec<u8>) {
+    let contents = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let path = dir.path().join(name);
+    std::fs::write(&path, &contents).unwrap();
+    (path, contents)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn single_file() {
+    let sender = Peer::start("Sender").await;
+    let receiver = Peer::start("Receiver").await;
+    let _consent = receiver.answer_consent(TransferAction::ConsentAccept);
+
+    let mut sender_rx = sender.subscribe();
+    let mut receiver_rx = receiver.subscribe();
+
+    let source_dir = tempfile::tempdir().unwrap();
+    let (path, contents) = write_file(&source_dir, "photo.jpg", 256 * 1024);
+    sender.send_files(&receiver, &[path]);
+
+    assert!(matches!(
+        final_state(&mut receiver_rx, TransferKind::Inbound).await,
+        TransferState::Finished
+    ));
+    assert!(matches!(
+        final_state(&mut sender_rx, TransferKind::Outbound).await,
+        TransferState::Finished
+    ));
+    assert_eq!(
+        std::fs::read(receiver.received("photo.jpg")).unwrap(),
+        contents
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn multiple_files() {
+    let sender = Peer::start("Sender").await;
+    let receiver = Peer::start("Receiver").await;
+    let _consent = receiver.answer_consent(TransferAction::ConsentAccept);
+
+    let mut receiver_rx = receiver.subscribe();
+
+    let source_dir = tempfile::tempdir().unwrap();
+    let files = [
+        write_file(&source_dir, "empty.txt", 0),
+        write_file(&source_dir, "small.txt", 17),
+        write_file(&source_dir, "large.bin", 4 * 1024 * 1024),
+    ];
+    sender.send_files(
+        &receiver,
+        &files
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>(),
+    );
+
+    assert!(matches!(
+        final_state(&mut receiver_rx, TransferKind::Inbound).await,
+        TransferState::Finished
+    ));
+    for (path, contents) in &files {
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(
+            &std::fs::read(receiver.received(file_name)).unwrap(),
+            contents,
+            "{file_name} doesn't match"
+        );
+    }
+}
+
+/// Text and links go out the way "Send Clipboard Contents" sends them, as a
+/// text file, since `rqs_lib` only has `OutboundPayload::Files` to send with.
+#[tokio::test(flavor = "multi_thread")]
+async fn text_and_url() {
+    let sender = Peer::start("Sender").await;
+    let receiver = Peer::start("Receiver").await;
+    let _consent = receiver.answer_consent(TransferAction::ConsentAccept);
+
+    let source_dir = tempfile::tempdir().unwrap();
+    let payloads = [
+        (
+            "clipboard-text.txt",
+            "Grüße aus der Küche 🍜\nSecond line\n",
+        ),
+        (
+            "clipboard-url.txt",
+            "https://example.com/path?q=a%20b&lang=de#frag",
+        ),
+    ];
+
+    for (name, text) in payloads {
+        let mut receiver_rx = receiver.subscribe();
+        let path = source_dir.path().join(name);
+        std::fs::write(&path, text).unwrap();
+        sender.send_files(&receiver, &[path]);
+
+        assert!(matches!(
+            final_state(&mut receiver_rx, TransferKind::Inbound).await,
+            TransferState::Finished
+        ));
+        assert_eq!(
+            std::fs::read_to_string(receiver.received(name)).unwrap(),
+            text
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn declined() {
+    let sender = Peer::start("Sender").await;
+    let receiver = Peer::start("Receiver").await;
+    let _consent = receiver.answer_consent(TransferAction::ConsentDecline);
+
+    let mut sender_rx = sender.subscribe();
+    let mut receiver_rx = receiver.subscribe();
+
+    let source_dir = tempfile::tempdir().unwrap();
+    let (path, _) = write_file(&source_dir, "notes.txt", 1024);
+    sender.send_files(&receiver, &[path]);
+
+    assert!(matches!(
+        final_state(&mut receiver_rx, TransferKind::Inbound).await,
+        TransferState::Rejected
+    ));
+    // The outbound side doesn't report rejections on its own, it just sees
+    // the connection go away
+    assert!(matches!(
+        final_state(&mut sender_rx, TransferKind::Outbound).await,
+        TransferState::Rejected | TransferState::Disconnected
+    ));
+    assert!(!receiver.received("notes.txt").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancelled_by_receiver() {
+    let sender = Peer::start("Sender").await;
+    let receiver = Peer::start("Receiver").await;
+    let _consent = receiver.answer_consent(TransferAction::ConsentAccept);
+
+    let mut sender_rx = sender.subscribe();
+    let mut receiver_rx = receiver.subscribe();
+
+    let source_dir = tempfile::tempdir().unwrap();
+    let len = 64 * 1024 * 1024;
+    let (path, _) = write_file(&source_dir, "video.mkv", len);
+    sender.send_files(&receiver, &[path]);
+
+    let id = receiving_started(&mut receiver_rx).await;
+    receiver.service.send(ServiceCommand::TransferAction {
+        id,
+        action: TransferAction::TransferCancel,
+    });
+
+    assert!(matches!(
+        final_state(&mut receiver_rx, TransferKind::Inbound).await,
+        TransferState::Cancelled
+    ));
+    assert!(matches!(
+        final_state(&mut sender_rx, TransferKind::Outbound).await,
+        TransferState::Cancelled | TransferState::Disconnected
+    ));
+
+    let received_len = std::fs::metadata(receiver.received("video.mkv"))
+        .map(|it| it.len() as usize)
+        .unwrap_or_default();
+    assert!(received_len < len);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stop_releases_port() {
+    let peer = Peer::start("Peer").await;
+    let port = peer.port;
+    tokio::time::timeout(TIMEOUT, peer.service.stop())
+        .await
+        .expect("Timed out waiting for the service to stop");
+
+    // Port should be free again for the next instance
+    tokio::time::timeout(TIMEOUT, async {
+        while std::net::TcpListener::bind(("0.0.0.0", port as u16)).is_err() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Port wasn't released after stopping");
+}
@@ -0,0 +1,504 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures_lite::FutureExt;
+use tokio::sync::{mpsc, watch};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ServiceHealth {
+    #[default]
+    Starting,
+    Healthy,
+    /// Some events were missed and the state is being resynchronized.
+    Degraded,
+    Restarting,
+    /// Ran out of restarts, the service needs to be restarted by hand.
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig {
+    /// How often busy tasks are checked for being wedged.
+    pub check_interval: Duration,
+    /// A task that's been busy with a single item for this long is
+    /// considered wedged.
+    pub wedge_timeout: Duration,
+    /// Maximum number of restarts allowed within `restart_window`.
+    pub max_restarts: usize,
+    pub restart_window: Duration,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(5),
+            wedge_timeout: Duration::from_secs(30),
+            max_restarts: 3,
+            restart_window: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Why a supervised task gave up.
+#[derive(Debug)]
+pub enum TaskError {
+    /// The channel the task was reading from, or forwarding to, was closed.
+    Closed,
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for TaskError {
+    fn from(value: anyhow::Error) -> Self {
+        Self::Other(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The task returned or panicked.
+    Died {
+        task: &'static str,
+        reason: String,
+    },
+    Closed {
+        task: &'static str,
+    },
+    Wedged {
+        task: &'static str,
+    },
+    Lagged {
+        task: &'static str,
+        skipped: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    /// Events were missed by the task, the state it feeds should be
+    /// refreshed from the service.
+    Resync {
+        task: &'static str,
+    },
+    Restart,
+    /// Restarted too many times in a short while, there's likely something
+    /// wrong that a restart won't fix.
+    GiveUp {
+        fault: Fault,
+    },
+}
+
+#[derive(Debug, Default)]
+struct TaskState {
+    busy_since: Mutex<Option<Instant>>,
+}
+
+/// Handle given to a supervised task to report on itself.
+#[derive(Debug, Clone)]
+pub struct TaskMonitor {
+    name: &'static str,
+    generation: u64,
+    state: Arc<TaskState>,
+    faults_tx: mpsc::UnboundedSender<(u64, Fault)>,
+}
+
+impl TaskMonitor {
+    /// Marks the task as busy until the returned guard is dropped. Should
+    /// wrap anything that could block indefinitely, such as forwarding an
+    /// item to a bounded channel.
+    pub fn busy(&self) -> BusyGuard<'_> {
+        *self.state.busy_since.lock().unwrap() = Some(Instant::now());
+        BusyGuard { monitor: self }
+    }
+
+    pub fn lagged(&self, skipped: u64) {
+        tracing::warn!(task = self.name, skipped, "Supervised task lagged behind");
+        self.report(Fault::Lagged {
+            task: self.name,
+            skipped,
+        });
+    }
+
+    fn report(&self, fault: Fault) {
+        _ = self.faults_tx.send((self.generation, fault));
+    }
+}
+
+#[derive(Debug)]
+pub struct BusyGuard<'a> {
+    monitor: &'a TaskMonitor,
+}
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        *self.monitor.state.busy_since.lock().unwrap() = None;
+    }
+}
+
+#[derive(Debug)]
+struct SupervisorInner {
+    config: SupervisorConfig,
+    runtime: tokio::runtime::Handle,
+    /// Bumped on every (re)start of the service, so that faults from the
+    /// tasks of a previous run don't trigger another restart.
+    generation: AtomicU64,
+    tasks: Mutex<HashMap<&'static str, Arc<TaskState>>>,
+    faults_tx: mpsc::UnboundedSender<(u64, Fault)>,
+    faults_rx: Mutex<Option<mpsc::UnboundedReceiver<(u64, Fault)>>>,
+    health_tx: watch::Sender<ServiceHealth>,
+}
+
+/// Keeps an eye on the background tasks that shuttle events between the RQS
+/// service and the UI, and decides how to recover when one of them misbehaves.
+#[derive(Debug, Clone)]
+pub struct Supervisor(Arc<SupervisorInner>);
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig, runtime: tokio::runtime::Handle) -> Self {
+        let (faults_tx, faults_rx) = mpsc::unbounded_channel();
+        let (health_tx, _) = watch::channel(ServiceHealth::default());
+
+        Self(Arc::new(SupervisorInner {
+            config,
+            runtime,
+            generation: Default::default(),
+            tasks: Default::default(),
+            faults_tx,
+            faults_rx: Mutex::new(Some(faults_rx)),
+            health_tx,
+        }))
+    }
+
+    pub fn health(&self) -> ServiceHealth {
+        *self.0.health_tx.borrow()
+    }
+
+    pub fn set_health(&self, health: ServiceHealth) {
+        self.0.health_tx.send_if_modified(|it| {
+            if *it == health {
+                return false;
+            }
+
+            tracing::info!(from = ?*it, to = ?health, "Service health changed");
+            *it = health;
+            true
+        });
+    }
+
+    /// Forgets the tasks from the previous run of the service. Must be called
+    /// before spawning the tasks for a new run.
+    pub fn begin_generation(&self) {
+        self.0.generation.fetch_add(1, Ordering::SeqCst);
+        self.0.tasks.lock().unwrap().clear();
+        self.set_health(ServiceHealth::Starting);
+    }
+
+    /// Spawns a supervised task on the runtime.
+    ///
+    /// The task is expected to run for as long as the service does, so it
+    /// returning at all, or panicking, is reported as a fault.
+    pub fn spawn<F, Fut>(&self, name: &'static str, f: F) -> tokio::task::JoinHandle<()>
+    where
+        F: FnOnce(TaskMonitor) -> Fut,
+        Fut: Future<Output = Result<(), TaskError>> + Send + 'static,
+    {
+        let state = Arc::new(TaskState::default());
+        self.0.tasks.lock().unwrap().insert(name, state.clone());
+
+        let monitor = TaskMonitor {
+            name,
+            generation: self.0.generation.load(Ordering::SeqCst),
+            state,
+            faults_tx: self.0.faults_tx.clone(),
+        };
+        let fut = f(monitor.clone());
+
+        self.0.runtime.spawn(async move {
+            let fault = match AssertUnwindSafe(fut).catch_unwind().await {
+                Ok(Ok(())) => Fault::Died {
+                    task: name,
+                    reason: "Returned".into(),
+                },
+                Ok(Err(TaskError::Closed)) => Fault::Closed { task: name },
+                Ok(Err(TaskError::Other(err))) => Fault::Died {
+                    task: name,
+                    reason: format!("{err:#}"),
+                },
+                Err(panic) => Fault::Died {
+                    task: name,
+                    reason: panic
+                        .downcast_ref::<&str>()
+                        .map(|it| it.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "Panicked".into()),
+                },
+            };
+
+            tracing::error!(?fault, "Supervised task stopped");
+            monitor.report(fault);
+        })
+    }
+
+    /// Watches over the tasks and sends out what should be done to recover
+    /// from their faults. Can only be run once.
+    pub async fn run(self, recovery_tx: async_channel::Sender<Recovery>) {
+        let Some(mut faults_rx) = self.0.faults_rx.lock().unwrap().take() else {
+            tracing::error!("Supervisor is already running");
+            return;
+        };
+
+        let mut policy = RestartPolicy::new(&self.0.config);
+        let mut interval = tokio::time::interval(self.0.config.check_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let fault = tokio::select! {
+                fault = faults_rx.recv() => {
+                    let Some((generation, fault)) = fault else {
+                        break;
+                    };
+                    if generation != self.0.generation.load(Ordering::SeqCst) {
+                        tracing::debug!(?fault, "Ignoring fault from a previous run");
+                        continue;
+                    }
+
+                    fault
+                }
+                _ = interval.tick() => {
+                    match self.find_wedged_task() {
+                        Some(task) => Fault::Wedged { task },
+                        None => continue,
+                    }
+                }
+            };
+
+            let recovery = policy.recover(fault);
+            tracing::info!(?recovery, "Recovering the service");
+
+            match &recovery {
+                Recovery::Resync { .. } => self.set_health(ServiceHealth::Degraded),
+                Recovery::Restart => {
+                    // Stop watching the current tasks, they're getting replaced
+                    self.0.generation.fetch_add(1, Ordering::SeqCst);
+                    self.0.tasks.lock().unwrap().clear();
+                    self.set_health(ServiceHealth::Restarting);
+                }
+                Recovery::GiveUp { .. } => {
+                    self.0.generation.fetch_add(1, Ordering::SeqCst);
+                    self.0.tasks.lock().unwrap().clear();
+                    self.set_health(ServiceHealth::Failed);
+                }
+            }
+
+            if recovery_tx.send(recovery).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    fn find_wedged_task(&self) -> Option<&'static str> {
+        let now = Instant::now();
+        self.0
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, state)| {
+                state
+                    .busy_since
+                    .lock()
+                    .unwrap()
+                    .is_some_and(|since| now.duration_since(since) >= self.0.config.wedge_timeout)
+            })
+            .map(|(name, _)| *name)
+    }
+}
+
+#[derive(Debug)]
+struct RestartPolicy {
+    max_restarts: usize,
+    restart_window: Duration,
+    restarts: VecDeque<Instant>,
+}
+
+impl RestartPolicy {
+    fn new(config: &SupervisorConfig) -> Self {
+        Self {
+            max_restarts: config.max_restarts,
+            restart_window: config.restart_window,
+            restarts: Default::default(),
+        }
+    }
+
+    fn recover(&mut self, fault: Fault) -> Recovery {
+        if let Fault::Lagged { task, .. } = fault {
+            return Recovery::Resync { task };
+        }
+
+        let now = Instant::now();
+        while self
+            .restarts
+            .front()
+            .is_some_and(|it| now.duration_since(*it) > self.restart_window)
+        {
+            self.restarts.pop_front();
+        }
+
+        if self.restarts.len() >= self.max_restarts {
+            return Recovery::GiveUp { fault };
+        }
+
+        self.restarts.push_back(now);
+        Recovery::Restart
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> SupervisorConfig {
+        SupervisorConfig {
+            check_interval: Duration::from_millis(10),
+            wedge_timeout: Duration::from_millis(50),
+            max_restarts: 2,
+            restart_window: Duration::from_secs(60),
+        }
+    }
+
+    fn start_supervisor(
+        config: SupervisorConfig,
+    ) -> (Supervisor, async_channel::Receiver<Recovery>) {
+        let supervisor = Supervisor::new(config, tokio::runtime::Handle::current());
+        supervisor.begin_generation();
+
+        let (tx, rx) = async_channel::unbounded();
+        tokio::spawn(supervisor.clone().run(tx));
+
+        (supervisor, rx)
+    }
+
+    async fn next_recovery(rx: &async_channel::Receiver<Recovery>) -> Recovery {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("Timed out waiting for a recovery")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn restarts_on_panic() {
+        let (supervisor, rx) = start_supervisor(test_config());
+
+        supervisor.spawn("panics", |_| async { panic!("injected failure") });
+
+        assert_eq!(next_recovery(&rx).await, Recovery::Restart);
+        assert_eq!(supervisor.health(), ServiceHealth::Restarting);
+    }
+
+    #[tokio::test]
+    async fn restarts_on_closed_channel() {
+        let (supervisor, rx) = start_supervisor(test_config());
+
+        let (tx, ui_rx) = async_channel::bounded::<()>(1);
+        // The UI side going away
+        drop(ui_rx);
+        supervisor.spawn("forwarder", |_| async move {
+            tx.send(()).await.map_err(|_| TaskError::Closed)
+        });
+
+        assert_eq!(next_recovery(&rx).await, Recovery::Restart);
+    }
+
+    #[tokio::test]
+    async fn resyncs_on_lag() {
+        let (supervisor, rx) = start_supervisor(test_config());
+
+        let (tx, mut lagging_rx) = tokio::sync::broadcast::channel(1);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        supervisor.spawn("lagging", |monitor| async move {
+            loop {
+                match lagging_rx.recv().await {
+                    Ok(_) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        monitor.lagged(skipped)
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                        return Err(TaskError::Closed);
+                    }
+                }
+            }
+        });
+
+        assert_eq!(
+            next_recovery(&rx).await,
+            Recovery::Resync { task: "lagging" }
+        );
+        assert_eq!(supervisor.health(), ServiceHealth::Degraded);
+
+        drop(tx);
+    }
+
+    #[tokio::test]
+    async fn restarts_wedged_task() {
+        let (supervisor, rx) = start_supervisor(test_config());
+
+        supervisor.spawn("wedged", |monitor| async move {
+            let _busy = monitor.busy();
+            std::future::pending::<()>().await;
+            Ok(())
+        });
+
+        assert_eq!(next_recovery(&rx).await, Recovery::Restart);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_restart_budget() {
+        let (supervisor, rx) = start_supervisor(test_config());
+
+        for _ in 0..2 {
+            supervisor.begin_generation();
+            supervisor.spawn("panics", |_| async { panic!("injected failure") });
+            assert_eq!(next_recovery(&rx).await, Recovery::Restart);
+        }
+
+        supervisor.begin_generation();
+        supervisor.spawn("panics", |_| async { panic!("injected failure") });
+        assert!(matches!(
+            next_recovery(&rx).await,
+            Recovery::GiveUp {
+                fault: Fault::Died { task: "panics", .. }
+            }
+        ));
+        assert_eq!(supervisor.health(), ServiceHealth::Failed);
+    }
+
+    #[tokio::test]
+    async fn ignores_faults_from_previous_run() {
+        let (supervisor, rx) = start_supervisor(test_config());
+
+        let (tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        supervisor.spawn("stale", |_| async move {
+            _ = stop_rx.await;
+            Err(TaskError::Closed)
+        });
+
+        // Service restarted by hand before the old task noticed
+        supervisor.begin_generation();
+        tx.send(()).unwrap();
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), rx.recv())
+                .await
+                .is_err()
+        );
+        assert_eq!(supervisor.health(), ServiceHealth::Starting);
+    }
+}
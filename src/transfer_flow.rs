@@ -0,0 +1,884 @@
+//! What the receive dialogs and the recipient cards do in response to the
+//! transfer events and the user, decided apart from the widgets so that it
+//! can be tested.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use rqs_lib::{
+    TransferState as RqsState,
+    channel::{ChannelMessage, Message, MessageClient, TransferAction},
+    hdl::info::TransferPayloadKind,
+};
+
+use crate::utils;
+
+/// Something the receive dialogs should do, in the order they're returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReceiveEffect {
+    PresentConsent(ConsentRequest),
+    CloseConsent,
+    /// Sends the action for the transfer to the service.
+    SendAction(TransferAction),
+    PresentProgress,
+    CloseProgress,
+    Progress {
+        ack_bytes: u64,
+        total_bytes: u64,
+    },
+    RemoveNotification,
+    Notify(ReceiveNotice),
+    CleanUpPartialFiles,
+    PresentReceived,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsentRequest {
+    /// How much space is free in the download folder, if it isn't enough for
+    /// the files.
+    pub short_on_space: Option<u64>,
+}
+
+/// Why a receive ended, or was declined, without the user asking for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiveNotice {
+    DeclinedLowOnSpace,
+    TimedOut,
+    OutOfSpace,
+    Disconnected,
+    CancelledBySender,
+}
+
+/// Keeps track of an incoming transfer, from the consent request until it
+/// ends.
+#[derive(Debug)]
+pub struct ReceiveFlow {
+    id: String,
+    decline_if_low_on_space: bool,
+    user_action: Option<TransferAction>,
+    was_accepted: bool,
+    is_over: bool,
+}
+
+impl ReceiveFlow {
+    pub fn new(id: impl Into<String>, decline_if_low_on_space: bool) -> Self {
+        Self {
+            id: id.into(),
+            decline_if_low_on_space,
+            user_action: None,
+            was_accepted: false,
+            is_over: false,
+        }
+    }
+
+    /// Accepted, and neither cancelled nor over yet.
+    pub fn is_receiving(&self) -> bool {
+        !self.is_over && matches!(self.user_action, Some(TransferAction::ConsentAccept))
+    }
+
+    /// `free_bytes` is the free space in the download folder, if it was
+    /// checked for this event.
+    pub fn handle_event(
+        &mut self,
+        channel_message: &ChannelMessage,
+        free_bytes: Option<u64>,
+    ) -> Vec<ReceiveEffect> {
+        let Some(client_msg) = channel_message.msg.as_client() else {
+            return vec![];
+        };
+        if self.is_over || channel_message.id != self.id {
+            return vec![];
+        }
+
+        let metadata = client_msg.metadata.as_ref();
+        // Text doesn't end up in the download folder
+        let is_files =
+            metadata.is_some_and(|it| matches!(it.payload_kind, TransferPayloadKind::Files));
+
+        match client_msg.state.clone().unwrap_or(RqsState::Initial) {
+            RqsState::WaitingForUserConsent => {
+                let total_bytes = metadata.map(|it| it.total_bytes).unwrap_or_default();
+                let short_on_space =
+                    free_bytes.filter(|it| is_files && !utils::has_room_for(*it, total_bytes));
+
+                if short_on_space.is_some() && self.decline_if_low_on_space {
+                    tracing::info!(
+                        id = self.id,
+                        total_bytes,
+                        ?free_bytes,
+                        "Declining transfer that doesn't fit in the download folder"
+                    );
+                    let mut effects = self.handle_user_action(TransferAction::ConsentDecline);
+                    effects.push(ReceiveEffect::Notify(ReceiveNotice::DeclinedLowOnSpace));
+                    return effects;
+                }
+
+                vec![ReceiveEffect::PresentConsent(ConsentRequest {
+                    short_on_space,
+                })]
+            }
+            RqsState::ReceivingFiles => {
+                let Some(metadata) = metadata.filter(|_| is_files && self.is_receiving()) else {
+                    return vec![];
+                };
+
+                // Only once it's actually running out rather than when it
+                // merely won't fit, since it might've been accepted regardless
+                let remaining_bytes = metadata.total_bytes.saturating_sub(metadata.ack_bytes);
+                if let Some(free_bytes) = free_bytes
+                    && free_bytes < remaining_bytes.min(utils::FREE_SPACE_RESERVE)
+                {
+                    tracing::warn!(
+                        id = self.id,
+                        remaining_bytes,
+                        free_bytes,
+                        "Ran out of space, cancelling transfer"
+                    );
+                    let mut effects = self.handle_user_action(TransferAction::TransferCancel);
+                    effects.push(ReceiveEffect::Notify(ReceiveNotice::OutOfSpace));
+                    return effects;
+                }
+
+                vec![ReceiveEffect::Progress {
+                    ack_bytes: metadata.ack_bytes,
+                    total_bytes: metadata.total_bytes,
+                }]
+            }
+            RqsState::Disconnected => {
+                let is_user_cancelled =
+                    matches!(self.user_action, Some(TransferAction::TransferCancel));
+                self.end((!is_user_cancelled).then_some(ReceiveNotice::Disconnected))
+            }
+            RqsState::Cancelled => {
+                let is_user_cancelled =
+                    matches!(self.user_action, Some(TransferAction::TransferCancel));
+                self.end((!is_user_cancelled).then_some(ReceiveNotice::CancelledBySender))
+            }
+            RqsState::Finished => {
+                self.is_over = true;
+                vec![self.close_dialog(), ReceiveEffect::PresentReceived]
+            }
+            RqsState::Initial
+            | RqsState::ReceivedConnectionRequest
+            | RqsState::SentUkeyServerInit
+            | RqsState::SentUkeyClientInit
+            | RqsState::SentUkeyClientFinish
+            | RqsState::SentPairedKeyEncryption
+            | RqsState::ReceivedUkeyClientFinish
+            | RqsState::SentConnectionResponse
+            | RqsState::SentPairedKeyResult
+            | RqsState::SentIntroduction
+            | RqsState::ReceivedPairedKeyResult
+            | RqsState::SendingFiles
+            | RqsState::Rejected => vec![],
+        }
+    }
+
+    /// Actions that no longer apply are ignored, e.g. a decline from the
+    /// notification after the dialog was accepted.
+    pub fn handle_user_action(&mut self, action: TransferAction) -> Vec<ReceiveEffect> {
+        if self.is_over {
+            return vec![];
+        }
+
+        match action {
+            TransferAction::ConsentAccept if self.user_action.is_none() => {
+                self.user_action = Some(action.clone());
+                self.was_accepted = true;
+                vec![
+                    ReceiveEffect::CloseConsent,
+                    ReceiveEffect::SendAction(action),
+                    ReceiveEffect::PresentProgress,
+                ]
+            }
+            TransferAction::ConsentDecline if self.user_action.is_none() => {
+                self.user_action = Some(action.clone());
+                vec![
+                    ReceiveEffect::CloseConsent,
+                    ReceiveEffect::RemoveNotification,
+                    ReceiveEffect::SendAction(action),
+                ]
+            }
+            TransferAction::TransferCancel if self.is_receiving() => {
+                self.user_action = Some(action.clone());
+                vec![
+                    ReceiveEffect::CloseProgress,
+                    ReceiveEffect::RemoveNotification,
+                    ReceiveEffect::SendAction(action),
+                ]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Since there's no telling whether the notification was dismissed, the
+    /// request is declined if it isn't answered in time.
+    pub fn consent_timed_out(&mut self) -> Vec<ReceiveEffect> {
+        if self.user_action.is_some() {
+            return vec![];
+        }
+
+        let mut effects = self.handle_user_action(TransferAction::ConsentDecline);
+        effects.push(ReceiveEffect::Notify(ReceiveNotice::TimedOut));
+        effects
+    }
+
+    fn end(&mut self, notice: Option<ReceiveNotice>) -> Vec<ReceiveEffect> {
+        self.is_over = true;
+
+        let mut effects = vec![self.close_dialog()];
+        effects.extend(notice.map(ReceiveEffect::Notify));
+        if self.was_accepted {
+            effects.push(ReceiveEffect::CleanUpPartialFiles);
+        }
+
+        effects
+    }
+
+    fn close_dialog(&self) -> ReceiveEffect {
+        if self.was_accepted {
+            ReceiveEffect::CloseProgress
+        } else {
+            ReceiveEffect::CloseConsent
+        }
+    }
+}
+
+/// What the result label of a recipient card says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardResult {
+    Requested,
+    Failed,
+    Sent,
+}
+
+/// How a recipient card shows the state of the transfer to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecipientCardView {
+    pub result: Option<CardResult>,
+    pub pin_code: Option<String>,
+    /// Shown along with the time that's left, if set. The fraction is `None`
+    /// if the size isn't known.
+    pub progress: Option<Option<f64>>,
+    pub can_cancel: bool,
+    pub can_retry: bool,
+    pub is_unavailable: bool,
+    /// Whether the card can be clicked to send to the device again, left as
+    /// is if `None`.
+    pub is_activatable: Option<bool>,
+    /// Set when a new transfer was just requested.
+    pub is_new_transfer: bool,
+    /// Set when the card goes back to not having a transfer.
+    pub is_reset: bool,
+}
+
+impl RecipientCardView {
+    /// `None` if the card shouldn't change for the state.
+    pub fn from_outbound(client_msg: &MessageClient, is_present: bool) -> Option<Self> {
+        let metadata = client_msg.metadata.as_ref();
+
+        match client_msg.state.as_ref().unwrap_or(&RqsState::Initial) {
+            RqsState::SentUkeyClientInit
+            | RqsState::SentUkeyClientFinish
+            | RqsState::SentIntroduction => Some(Self {
+                result: Some(CardResult::Requested),
+                pin_code: metadata.and_then(|it| it.pin_code.clone()),
+                can_cancel: true,
+                is_activatable: Some(false),
+                is_new_transfer: true,
+                ..Default::default()
+            }),
+            RqsState::SendingFiles => Some(Self {
+                progress: Some(
+                    metadata
+                        .filter(|it| it.total_bytes > 0)
+                        .map(|it| it.ack_bytes as f64 / it.total_bytes as f64),
+                ),
+                can_cancel: true,
+                ..Default::default()
+            }),
+            // FIXME: Wait for 5~10 seconds after a send and timeout
+            // if did not receive SendingFiles within that timeframe
+            // This is how google does it in their client
+            RqsState::Disconnected => Some(Self {
+                result: Some(CardResult::Failed),
+                can_retry: true,
+                ..Default::default()
+            }),
+            RqsState::Cancelled => Some(Self {
+                is_unavailable: !is_present,
+                is_activatable: Some(true),
+                is_reset: true,
+                ..Default::default()
+            }),
+            RqsState::Finished => Some(Self {
+                result: Some(CardResult::Sent),
+                ..Default::default()
+            }),
+            RqsState::Initial
+            | RqsState::ReceivedConnectionRequest
+            | RqsState::SentUkeyServerInit
+            | RqsState::SentPairedKeyEncryption
+            | RqsState::ReceivedUkeyClientFinish
+            | RqsState::SentConnectionResponse
+            | RqsState::SentPairedKeyResult
+            | RqsState::ReceivedPairedKeyResult
+            | RqsState::WaitingForUserConsent
+            | RqsState::ReceivingFiles
+            | RqsState::Rejected => None,
+        }
+    }
+}
+
+/// How long a transfer has to send another event after its events were lost,
+/// before it's taken to have ended.
+const LOST_TRANSFER_GRACE: Duration = Duration::from_secs(5);
+
+/// Keeps track of the transfers that haven't ended, so that they don't stay
+/// active forever if the event that ended them is lost.
+///
+/// There's no asking the service where a transfer is at, so the ones that
+/// were active when events were lost are cancelled. Those that are still
+/// going end with their own event, and the rest are ended here as
+/// disconnected once they've had some time to.
+#[derive(Debug, Default)]
+pub struct ActiveTransfers {
+    last_events: HashMap<String, ChannelMessage>,
+    /// Until when the transfers whose events were lost have to send another
+    lost: HashMap<String, Instant>,
+}
+
+impl ActiveTransfers {
+    pub fn update(&mut self, channel_message: &ChannelMessage) {
+        let Some(client_msg) = channel_message.msg.as_client() else {
+            return;
+        };
+        let id = &channel_message.id;
+
+        self.lost.remove(id);
+        match client_msg.state {
+            Some(
+                RqsState::Finished
+                | RqsState::Cancelled
+                | RqsState::Rejected
+                | RqsState::Disconnected,
+            ) => {
+                self.last_events.remove(id);
+            }
+            _ => {
+                self.last_events.insert(id.clone(), channel_message.clone());
+            }
+        }
+    }
+
+    /// Returns the ids of the transfers to cancel.
+    pub fn events_lost(&mut self, now: Instant) -> Vec<String> {
+        let deadline = now + LOST_TRANSFER_GRACE;
+        let mut ids = self.last_events.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        for id in &ids {
+            self.lost.insert(id.clone(), deadline);
+        }
+
+        ids
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.lost.values().min().copied()
+    }
+
+    /// Disconnected events for the transfers that didn't send another event
+    /// in time.
+    pub fn expired(&mut self, now: Instant) -> Vec<ChannelMessage> {
+        let mut ids = self
+            .lost
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        ids.sort();
+
+        ids.into_iter()
+            .filter_map(|id| {
+                self.lost.remove(&id);
+                let mut channel_message = self.last_events.remove(&id)?;
+                if let Message::Client(client_msg) = &mut channel_message.msg {
+                    client_msg.state = Some(RqsState::Disconnected);
+                }
+                Some(channel_message)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use rqs_lib::channel::TransferKind;
+    use tokio::sync::broadcast;
+
+    use super::*;
+    use crate::{
+        backend::{
+            QuickShareBackend,
+            scripted::{Recorded, ScriptedBackend, Step, event},
+        },
+        service::{ServiceCommand, ServiceHandle},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    const ID: &str = "transfer";
+
+    /// Plays the script through a service task, the way the window does.
+    struct Harness {
+        handle: ServiceHandle,
+        messages_rx: broadcast::Receiver<ChannelMessage>,
+        recorded: Arc<Mutex<Recorded>>,
+    }
+
+    impl Harness {
+        async fn start(steps: Vec<Step>) -> Self {
+            let mut backend = ScriptedBackend::new(steps);
+            let recorded = backend.recorded();
+            let messages_rx = backend.message_sender().subscribe();
+            let file_sender = backend.run().await.ok().map(|it| it.file_sender);
+            let handle = ServiceHandle::spawn(
+                backend,
+                file_sender,
+                broadcast::channel(1).0,
+                async_channel::unbounded().0,
+            );
+
+            Self {
+                handle,
+                messages_rx,
+                recorded,
+            }
+        }
+
+        async fn next_event(&mut self) -> ChannelMessage {
+            loop {
+                let msg = tokio::time::timeout(TIMEOUT, self.messages_rx.recv())
+                    .await
+                    .expect("Timed out waiting for an event")
+                    .expect("Message channel closed");
+                if msg.msg.as_client().is_some() {
+                    return msg;
+                }
+            }
+        }
+
+        /// Does what the receive dialogs would with the effects that reach
+        /// the service.
+        fn apply(&self, effects: &[ReceiveEffect]) {
+            for effect in effects {
+                if let ReceiveEffect::SendAction(action) = effect {
+                    self.handle.send(ServiceCommand::TransferAction {
+                        id: ID.into(),
+                        action: action.clone(),
+                    });
+                }
+            }
+        }
+
+        /// Feeds the next event to the flow, and applies what comes out.
+        async fn step(
+            &mut self,
+            flow: &mut ReceiveFlow,
+            free_bytes: Option<u64>,
+        ) -> Vec<ReceiveEffect> {
+            let msg = self.next_event().await;
+            let effects = flow.handle_event(&msg, free_bytes);
+            self.apply(&effects);
+            effects
+        }
+
+        fn actions(&self) -> Vec<TransferAction> {
+            self.recorded.lock().unwrap().actions.clone()
+        }
+    }
+
+    fn inbound(state: RqsState, ack_bytes: u64) -> Step {
+        Step::Emit(Box::new(event(ID, TransferKind::Inbound, state, ack_bytes)))
+    }
+
+    fn outbound(state: RqsState, ack_bytes: u64) -> MessageClient {
+        event(ID, TransferKind::Outbound, state, ack_bytes)
+            .msg
+            .as_client()
+            .unwrap()
+            .clone()
+    }
+
+    fn consent(short_on_space: Option<u64>) -> ReceiveEffect {
+        ReceiveEffect::PresentConsent(ConsentRequest { short_on_space })
+    }
+
+    #[tokio::test]
+    async fn accepted_receive_finishes() {
+        let mut harness = Harness::start(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentAccept),
+            inbound(RqsState::ReceivingFiles, 50),
+            inbound(RqsState::Finished, 100),
+        ])
+        .await;
+        let mut flow = ReceiveFlow::new(ID, false);
+
+        assert_eq!(harness.step(&mut flow, None).await, [consent(None)]);
+
+        let effects = flow.handle_user_action(TransferAction::ConsentAccept);
+        harness.apply(&effects);
+        assert_eq!(
+            effects,
+            [
+                ReceiveEffect::CloseConsent,
+                ReceiveEffect::SendAction(TransferAction::ConsentAccept),
+                ReceiveEffect::PresentProgress,
+            ]
+        );
+        // Too late to decline from the notification
+        assert_eq!(flow.handle_user_action(TransferAction::ConsentDecline), []);
+
+        assert_eq!(
+            harness.step(&mut flow, None).await,
+            [ReceiveEffect::Progress {
+                ack_bytes: 50,
+                total_bytes: 100
+            }]
+        );
+        assert_eq!(
+            harness.step(&mut flow, None).await,
+            [ReceiveEffect::CloseProgress, ReceiveEffect::PresentReceived]
+        );
+        assert!(matches!(
+            harness.actions().as_slice(),
+            [TransferAction::ConsentAccept]
+        ));
+    }
+
+    #[tokio::test]
+    async fn declined_receive() {
+        let mut harness = Harness::start(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentDecline),
+            inbound(RqsState::Rejected, 0),
+        ])
+        .await;
+        let mut flow = ReceiveFlow::new(ID, false);
+
+        assert_eq!(harness.step(&mut flow, None).await, [consent(None)]);
+
+        let effects = flow.handle_user_action(TransferAction::ConsentDecline);
+        harness.apply(&effects);
+        assert_eq!(
+            effects,
+            [
+                ReceiveEffect::CloseConsent,
+                ReceiveEffect::RemoveNotification,
+                ReceiveEffect::SendAction(TransferAction::ConsentDecline),
+            ]
+        );
+        assert_eq!(harness.step(&mut flow, None).await, []);
+        assert_eq!(flow.consent_timed_out(), []);
+        assert!(matches!(
+            harness.actions().as_slice(),
+            [TransferAction::ConsentDecline]
+        ));
+    }
+
+    #[tokio::test]
+    async fn unanswered_consent_times_out() {
+        let mut harness = Harness::start(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentDecline),
+        ])
+        .await;
+        let mut flow = ReceiveFlow::new(ID, false);
+
+        harness.step(&mut flow, None).await;
+        let effects = flow.consent_timed_out();
+        harness.apply(&effects);
+        assert_eq!(
+            effects.last(),
+            Some(&ReceiveEffect::Notify(ReceiveNotice::TimedOut))
+        );
+
+        // Waits for the script to see the decline
+        tokio::time::timeout(TIMEOUT, async {
+            while harness.actions().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(matches!(
+            harness.actions().as_slice(),
+            [TransferAction::ConsentDecline]
+        ));
+    }
+
+    #[tokio::test]
+    async fn user_cancels_midway() {
+        let mut harness = Harness::start(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentAccept),
+            inbound(RqsState::ReceivingFiles, 30),
+            Step::WaitForAction(TransferAction::TransferCancel),
+            inbound(RqsState::Cancelled, 30),
+        ])
+        .await;
+        let mut flow = ReceiveFlow::new(ID, false);
+
+        harness.step(&mut flow, None).await;
+        let effects = flow.handle_user_action(TransferAction::ConsentAccept);
+        harness.apply(&effects);
+        harness.step(&mut flow, None).await;
+
+        let effects = flow.handle_user_action(TransferAction::TransferCancel);
+        harness.apply(&effects);
+        assert_eq!(
+            effects,
+            [
+                ReceiveEffect::CloseProgress,
+                ReceiveEffect::RemoveNotification,
+                ReceiveEffect::SendAction(TransferAction::TransferCancel),
+            ]
+        );
+
+        // No "cancelled by sender" for our own cancel
+        assert_eq!(
+            harness.step(&mut flow, None).await,
+            [
+                ReceiveEffect::CloseProgress,
+                ReceiveEffect::CleanUpPartialFiles
+            ]
+        );
+        assert!(matches!(
+            harness.actions().as_slice(),
+            [
+                TransferAction::ConsentAccept,
+                TransferAction::TransferCancel
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn sender_cancels_or_disconnects() {
+        for (state, notice) in [
+            (RqsState::Cancelled, ReceiveNotice::CancelledBySender),
+            (RqsState::Disconnected, ReceiveNotice::Disconnected),
+        ] {
+            let mut harness = Harness::start(vec![
+                inbound(RqsState::WaitingForUserConsent, 0),
+                Step::WaitForAction(TransferAction::ConsentAccept),
+                inbound(RqsState::ReceivingFiles, 30),
+                inbound(state, 30),
+            ])
+            .await;
+            let mut flow = ReceiveFlow::new(ID, false);
+
+            harness.step(&mut flow, None).await;
+            harness.apply(&flow.handle_user_action(TransferAction::ConsentAccept));
+            harness.step(&mut flow, None).await;
+
+            assert_eq!(
+                harness.step(&mut flow, None).await,
+                [
+                    ReceiveEffect::CloseProgress,
+                    ReceiveEffect::Notify(notice),
+                    ReceiveEffect::CleanUpPartialFiles,
+                ]
+            );
+            assert_eq!(flow.handle_user_action(TransferAction::TransferCancel), []);
+        }
+    }
+
+    #[tokio::test]
+    async fn sender_gives_up_before_consent() {
+        let mut harness = Harness::start(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            inbound(RqsState::Disconnected, 0),
+        ])
+        .await;
+        let mut flow = ReceiveFlow::new(ID, false);
+
+        harness.step(&mut flow, None).await;
+        assert_eq!(
+            harness.step(&mut flow, None).await,
+            [
+                ReceiveEffect::CloseConsent,
+                ReceiveEffect::Notify(ReceiveNotice::Disconnected),
+            ]
+        );
+        assert_eq!(flow.handle_user_action(TransferAction::ConsentAccept), []);
+    }
+
+    #[tokio::test]
+    async fn low_on_space() {
+        // `event` makes 100 byte transfers
+        let free_bytes = Some(50);
+
+        let mut harness = Harness::start(vec![inbound(RqsState::WaitingForUserConsent, 0)]).await;
+        let mut flow = ReceiveFlow::new(ID, false);
+        assert_eq!(
+            harness.step(&mut flow, free_bytes).await,
+            [consent(free_bytes)]
+        );
+
+        let mut harness = Harness::start(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentDecline),
+        ])
+        .await;
+        let mut flow = ReceiveFlow::new(ID, true);
+        let effects = harness.step(&mut flow, free_bytes).await;
+        assert!(effects.contains(&ReceiveEffect::SendAction(TransferAction::ConsentDecline)));
+        assert_eq!(
+            effects.last(),
+            Some(&ReceiveEffect::Notify(ReceiveNotice::DeclinedLowOnSpace))
+        );
+    }
+
+    #[tokio::test]
+    async fn running_out_of_space_cancels() {
+        let mut harness = Harness::start(vec![
+            inbound(RqsState::WaitingForUserConsent, 0),
+            Step::WaitForAction(TransferAction::ConsentAccept),
+            inbound(RqsState::ReceivingFiles, 10),
+            inbound(RqsState::ReceivingFiles, 20),
+            Step::WaitForAction(TransferAction::TransferCancel),
+            inbound(RqsState::Cancelled, 20),
+        ])
+        .await;
+        let mut flow = ReceiveFlow::new(ID, false);
+
+        harness.step(&mut flow, None).await;
+        harness.apply(&flow.handle_user_action(TransferAction::ConsentAccept));
+        // Plenty left for the remaining 90 bytes
+        assert!(matches!(
+            harness.step(&mut flow, Some(1000)).await.as_slice(),
+            [ReceiveEffect::Progress { .. }]
+        ));
+
+        let effects = harness.step(&mut flow, Some(10)).await;
+        assert!(effects.contains(&ReceiveEffect::SendAction(TransferAction::TransferCancel)));
+        assert_eq!(
+            effects.last(),
+            Some(&ReceiveEffect::Notify(ReceiveNotice::OutOfSpace))
+        );
+
+        assert_eq!(
+            harness.step(&mut flow, None).await,
+            [
+                ReceiveEffect::CloseProgress,
+                ReceiveEffect::CleanUpPartialFiles
+            ]
+        );
+    }
+
+    #[test]
+    fn recipient_card_follows_the_transfer() {
+        let requested =
+            RecipientCardView::from_outbound(&outbound(RqsState::SentIntroduction, 0), true)
+                .unwrap();
+        assert_eq!(requested.result, Some(CardResult::Requested));
+        assert_eq!(requested.pin_code.as_deref(), Some("1234"));
+        assert_eq!(requested.is_activatable, Some(false));
+        assert!(requested.can_cancel && requested.is_new_transfer);
+
+        let sending =
+            RecipientCardView::from_outbound(&outbound(RqsState::SendingFiles, 25), true).unwrap();
+        assert_eq!(sending.progress, Some(Some(0.25)));
+        assert_eq!(sending.result, None);
+        assert!(sending.can_cancel);
+
+        let failed =
+            RecipientCardView::from_outbound(&outbound(RqsState::Disconnected, 25), true).unwrap();
+        assert_eq!(failed.result, Some(CardResult::Failed));
+        assert!(failed.can_retry && !failed.can_cancel);
+
+        let sent =
+            RecipientCardView::from_outbound(&outbound(RqsState::Finished, 100), true).unwrap();
+        assert_eq!(sent.result, Some(CardResult::Sent));
+        assert_eq!(sent.progress, None);
+
+        assert_eq!(
+            RecipientCardView::from_outbound(&outbound(RqsState::WaitingForUserConsent, 0), true),
+            None
+        );
+    }
+
+    #[test]
+    fn cancelled_recipient_card_resets() {
+        let cancelled =
+            RecipientCardView::from_outbound(&outbound(RqsState::Cancelled, 25), false).unwrap();
+        assert!(cancelled.is_reset && cancelled.is_unavailable);
+        assert_eq!(cancelled.is_activatable, Some(true));
+        assert_eq!(cancelled.result, None);
+    }
+
+    #[test]
+    fn ends_transfers_whose_events_were_lost() {
+        let mut active = ActiveTransfers::default();
+        active.update(&event(
+            "finished",
+            TransferKind::Inbound,
+            RqsState::ReceivingFiles,
+            10,
+        ));
+        active.update(&event(
+            "finished",
+            TransferKind::Inbound,
+            RqsState::Finished,
+            100,
+        ));
+        active.update(&event(
+            "running",
+            TransferKind::Inbound,
+            RqsState::ReceivingFiles,
+            10,
+        ));
+        active.update(&event(
+            "ended",
+            TransferKind::Outbound,
+            RqsState::SendingFiles,
+            10,
+        ));
+
+        let now = Instant::now();
+        assert_eq!(active.events_lost(now), vec!["ended", "running"]);
+        assert_eq!(active.next_deadline(), Some(now + LOST_TRANSFER_GRACE));
+        assert!(active.expired(now).is_empty());
+
+        // Still going, so it ends with its own event
+        active.update(&event(
+            "running",
+            TransferKind::Inbound,
+            RqsState::ReceivingFiles,
+            20,
+        ));
+
+        let expired = active.expired(now + LOST_TRANSFER_GRACE);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, "ended");
+        let client_msg = expired[0].msg.as_client().unwrap();
+        assert!(matches!(client_msg.state, Some(RqsState::Disconnected)));
+        assert!(matches!(client_msg.kind, TransferKind::Outbound));
+
+        assert_eq!(active.next_deadline(), None);
+        active.update(&event(
+            "running",
+            TransferKind::Inbound,
+            RqsState::Cancelled,
+            20,
+        ));
+        assert!(active.events_lost(now).is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use gettextrs::gettext;
+use zbus::{
+    object_server::SignalEmitter,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, StructureBuilder, Type, Value},
+};
+
+use crate::config::APP_ID;
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+
+/// Ids of the tray menu items. `0` is reserved for the root.
+const MENU_OPEN_ID: i32 = 1;
+const MENU_VISIBILITY_ID: i32 = 2;
+const MENU_SEND_FILES_ID: i32 = 3;
+const MENU_SEPARATOR_ID: i32 = 4;
+const MENU_QUIT_ID: i32 = 5;
+
+/// Width, height and ARGB32 data
+type IconPixmap = (i32, i32, Vec<u8>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayAction {
+    ToggleWindow,
+    ToggleVisibility,
+    SendFiles,
+    Quit,
+}
+
+/// A StatusNotifierItem icon, for desktops that have a tray.
+///
+/// The icon goes away along with the last clone of it.
+#[derive(Debug, Clone)]
+pub struct Tray {
+    connection: zbus::Connection,
+}
+
+impl Tray {
+    /// Registers the icon with the desktop's StatusNotifierWatcher, failing if
+    /// there's none.
+    pub async fn new(
+        action_tx: async_channel::Sender<TrayAction>,
+        status: String,
+        is_visible: bool,
+    ) -> anyhow::Result<Self> {
+        let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+        let connection = zbus::connection::Builder::session()?
+            .name(name.as_str())?
+            .serve_at(
+                ITEM_PATH,
+                StatusNotifierItem {
+                    action_tx: action_tx.clone(),
+                    status,
+                },
+            )?
+            .serve_at(
+                MENU_PATH,
+                DBusMenu {
+                    action_tx,
+                    is_visible,
+                    revision: 0,
+                },
+            )?
+            .build()
+            .await?;
+
+        zbus::Proxy::new(
+            &connection,
+            "org.kde.StatusNotifierWatcher",
+            "/StatusNotifierWatcher",
+            "org.kde.StatusNotifierWatcher",
+        )
+        .await?
+        .call::<_, _, ()>("RegisterStatusNotifierItem", &(name.as_str(),))
+        .await
+        .context("No StatusNotifierWatcher to register the tray icon with")?;
+
+        tracing::info!(name, "Registered tray icon");
+
+        Ok(Self { connection })
+    }
+
+    /// Updates the tooltip and the menu, if they changed.
+    pub async fn update(&self, status: &str, is_visible: bool) -> anyhow::Result<()> {
+        let object_server = self.connection.object_server();
+
+        let item = object_server
+            .interface::<_, StatusNotifierItem>(ITEM_PATH)
+            .await?;
+        let is_status_changed = item.get().await.status != status;
+        if is_status_changed {
+            item.get_mut().await.status = status.to_string();
+            StatusNotifierItem::new_tool_tip(item.signal_emitter()).await?;
+        }
+
+        let menu = object_server.interface::<_, DBusMenu>(MENU_PATH).await?;
+        let revision = {
+            let mut menu = menu.get_mut().await;
+            if menu.is_visible == is_visible {
+                None
+            } else {
+                menu.is_visible = is_visible;
+                menu.revision += 1;
+                Some(menu.revision)
+            }
+        };
+        if let Some(revision) = revision {
+            DBusMenu::layout_updated(menu.signal_emitter(), revision, 0).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Takes the icon off the tray right away, instead of whenever the
+    /// connection happens to close.
+    pub async fn remove(self) {
+        self.connection.graceful_shutdown().await;
+    }
+}
+
+struct StatusNotifierItem {
+    action_tx: async_channel::Sender<TrayAction>,
+    status: String,
+}
+
+#[zbus::interface(name = "org.kde.StatusNotifierItem")]
+impl StatusNotifierItem {
+    async fn activate(&self, _x: i32, _y: i32) {
+        _ = self.action_tx.send(TrayAction::ToggleWindow).await;
+    }
+
+    async fn secondary_activate(&self, _x: i32, _y: i32) {
+        _ = self.action_tx.send(TrayAction::SendFiles).await;
+    }
+
+    /// The host shows the menu itself, going by [`Self::menu`].
+    fn context_menu(&self, _x: i32, _y: i32) {}
+
+    fn scroll(&self, _delta: i32, _orientation: String) {}
+
+    #[zbus(property)]
+    fn category(&self) -> &str {
+        "Communications"
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> &str {
+        APP_ID
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> String {
+        gettext("Packet")
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "Active"
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> String {
+        format!("{APP_ID}-symbolic")
+    }
+
+    #[zbus(property)]
+    fn icon_pixmap(&self) -> Vec<IconPixmap> {
+        vec![]
+    }
+
+    /// Icon name, icon pixmaps, title and description
+    #[zbus(property)]
+    fn tool_tip(&self) -> (String, Vec<IconPixmap>, String, String) {
+        (
+            String::new(),
+            vec![],
+            gettext("Packet"),
+            self.status.clone(),
+        )
+    }
+
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn menu(&self) -> OwnedObjectPath {
+        ObjectPath::from_static_str_unchecked(MENU_PATH).into()
+    }
+
+    #[zbus(signal)]
+    async fn new_tool_tip(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+/// An item of the menu along with its children, as `(ia{sv}av)`.
+#[derive(Debug, serde::Serialize, Type)]
+struct MenuLayout {
+    id: i32,
+    properties: HashMap<String, OwnedValue>,
+    children: Vec<OwnedValue>,
+}
+
+impl MenuLayout {
+    fn into_value(self) -> OwnedValue {
+        let structure = StructureBuilder::new()
+            .add_field(self.id)
+            .add_field(self.properties)
+            .add_field(self.children)
+            .build()
+            .expect("Menu layout has fields");
+        owned_value(structure)
+    }
+}
+
+fn owned_value<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+    OwnedValue::try_from(value.into()).expect("Menu values don't hold file descriptors")
+}
+
+struct DBusMenu {
+    action_tx: async_channel::Sender<TrayAction>,
+    is_visible: bool,
+    revision: u32,
+}
+
+impl DBusMenu {
+    fn item_properties(&self, id: i32) -> Option<HashMap<String, OwnedValue>> {
+        let mut properties = HashMap::new();
+        match id {
+            0 => {
+                properties.insert("children-display".into(), owned_value("submenu"));
+            }
+            MENU_OPEN_ID => {
+                properties.insert("label".into(), owned_value(gettext("_Open Packet")));
+            }
+            MENU_VISIBILITY_ID => {
+                properties.insert(
+                    "label".into(),
+                    owned_value(gettext("_Visible to Nearby Devices")),
+                );
+                properties.insert("toggle-type".into(), owned_value("checkmark"));
+                properties.insert("toggle-state".into(), owned_value(self.is_visible as i32));
+            }
+            MENU_SEND_FILES_ID => {
+                properties.insert("label".into(), owned_value(gettext("_Send Files…")));
+            }
+            MENU_SEPARATOR_ID => {
+                properties.insert("type".into(), owned_value("separator"));
+            }
+            MENU_QUIT_ID => {
+                properties.insert("label".into(), owned_value(gettext("_Quit")));
+            }
+            _ => return None,
+        }
+
+        Some(properties)
+    }
+
+    fn layout(&self, id: i32) -> Option<MenuLayout> {
+        let children = if id == 0 {
+            [
+                MENU_OPEN_ID,
+                MENU_VISIBILITY_ID,
+                MENU_SEND_FILES_ID,
+                MENU_SEPARATOR_ID,
+                MENU_QUIT_ID,
+            ]
+            .into_iter()
+            .filter_map(|it| self.layout(it))
+            .map(|it| it.into_value())
+            .collect()
+        } else {
+            vec![]
+        };
+
+        Some(MenuLayout {
+            id,
+            properties: self.item_properties(id)?,
+            children,
+        })
+    }
+
+    async fn activate(&self, id: i32) -> bool {
+        let action = match id {
+            MENU_OPEN_ID => TrayAction::ToggleWindow,
+            MENU_VISIBILITY_ID => TrayAction::ToggleVisibility,
+            MENU_SEND_FILES_ID => TrayAction::SendFiles,
+            MENU_QUIT_ID => TrayAction::Quit,
+            _ => return false,
+        };
+        _ = self.action_tx.send(action).await;
+
+        true
+    }
+}
+
+/// A fixed menu, as per the `com.canonical.dbusmenu` spec.
+#[zbus::interface(name = "com.canonical.dbusmenu")]
+impl DBusMenu {
+    fn get_layout(
+        &self,
+        parent_id: i32,
+        _recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> zbus::fdo::Result<(u32, MenuLayout)> {
+        let layout = self.layout(parent_id).ok_or_else(|| {
+            zbus::fdo::Error::InvalidArgs(format!("No menu item with id {parent_id}"))
+        })?;
+
+        Ok((self.revision, layout))
+    }
+
+    fn get_group_properties(
+        &self,
+        ids: Vec<i32>,
+        _property_names: Vec<String>,
+    ) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+        ids.into_iter()
+            .filter_map(|id| Some((id, self.item_properties(id)?)))
+            .collect()
+    }
+
+    fn get_property(&self, id: i32, name: String) -> zbus::fdo::Result<OwnedValue> {
+        self.item_properties(id)
+            .and_then(|mut it| it.remove(&name))
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("No property {name} on {id}")))
+    }
+
+    async fn event(&self, id: i32, event_id: String, _data: OwnedValue, _timestamp: u32) {
+        if event_id == "clicked" {
+            self.activate(id).await;
+        }
+    }
+
+    /// Returns the ids that weren't found.
+    async fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+        let mut not_found = vec![];
+        for (id, event_id, _, _) in events {
+            if event_id == "clicked" && !self.activate(id).await {
+                not_found.push(id);
+            }
+        }
+
+        not_found
+    }
+
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    /// Returns the ids that need updating, and the ones that weren't found.
+    fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (vec![], vec![])
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    #[zbus(property)]
+    fn text_direction(&self) -> &str {
+        "ltr"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "normal"
+    }
+
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> Vec<String> {
+        vec![]
+    }
+
+    #[zbus(signal)]
+    async fn layout_updated(
+        emitter: &SignalEmitter<'_>,
+        revision: u32,
+        parent: i32,
+    ) -> zbus::Result<()>;
+}
@@ -8,7 +8,10 @@ use std::{
 
 use ashpd::desktop::notification::Notification;
 use gettextrs::ngettext;
-use gtk::glib::{self};
+use gtk::{
+    gio::{self, prelude::*},
+    glib::{self},
+};
 
 #[macro_export]
 macro_rules! impl_deref_for_newtype {
@@ -123,6 +126,74 @@ pub fn remove_notification(id: String) {
     });
 }
 
+/// Formats the timestamp as local date and time, in the locale's format.
+pub fn format_unix_timestamp(timestamp: i64) -> String {
+    glib::DateTime::from_unix_local(timestamp)
+        .and_then(|it| it.format("%x %X"))
+        .map(|it| it.to_string())
+        .unwrap_or_default()
+}
+
+/// Reads a JSON file saved by [`save_json`], falling back to the default if
+/// there's nothing saved yet, or if it can't be read.
+pub fn load_json_or_default<T: serde::de::DeserializeOwned + Default>(path: &Path) -> T {
+    if !path.exists() {
+        return T::default();
+    }
+
+    match fs_err::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|it| Ok(serde_json::from_str(&it)?))
+    {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::warn!(?err, ?path, "Failed to load saved state");
+            T::default()
+        }
+    }
+}
+
+pub fn save_json<T: serde::Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+
+    // Write to a temporary file first so that a crash midway doesn't leave
+    // a truncated file behind
+    let tmp_path = path.with_extension("json.tmp");
+    fs_err::write(&tmp_path, serde_json::to_string_pretty(value)?)?;
+    fs_err::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+/// Kept free when checking whether a transfer fits, so that accepting one
+/// doesn't fill the filesystem to the brim.
+pub const FREE_SPACE_RESERVE: u64 = 32 * 1024 * 1024;
+
+/// Free space on the filesystem the path is on, or `None` if the filesystem
+/// doesn't report it.
+pub fn free_space(path: impl AsRef<Path>) -> Option<u64> {
+    let info = gio::File::for_path(path.as_ref())
+        .query_filesystem_info(
+            gio::FILE_ATTRIBUTE_FILESYSTEM_FREE,
+            None::<&gio::Cancellable>,
+        )
+        .inspect_err(
+            |err| tracing::warn!(path = ?path.as_ref(), "Couldn't query free space: {err}"),
+        )
+        .ok()?;
+
+    info.has_attribute(gio::FILE_ATTRIBUTE_FILESYSTEM_FREE)
+        .then(|| info.attribute_uint64(gio::FILE_ATTRIBUTE_FILESYSTEM_FREE))
+}
+
+/// Whether `needed_bytes` fit in `free_bytes` with [`FREE_SPACE_RESERVE`]
+/// still to spare.
+pub fn has_room_for(free_bytes: u64, needed_bytes: u64) -> bool {
+    free_bytes >= needed_bytes.saturating_add(FREE_SPACE_RESERVE)
+}
+
 pub fn strip_user_home_prefix<P: AsRef<Path>>(path: P) -> PathBuf {
     if let Some(home) = dirs::home_dir() {
         if path.as_ref().starts_with(&home) {
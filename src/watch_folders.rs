@@ -0,0 +1,161 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use gtk::{gio, glib};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deferred::{self, KnownDevice},
+    utils,
+};
+
+/// Older entries of a watch folder's send log are dropped.
+const MAX_LOG_ENTRIES: usize = 50;
+const STORE_FILE_NAME: &str = "watch-folders.json";
+
+/// Suffixes of files that are still being written by some other app.
+const TEMPORARY_FILE_SUFFIXES: &[&str] = &[
+    "~",
+    ".tmp",
+    ".temp",
+    ".part",
+    ".partial",
+    ".crdownload",
+    ".download",
+    ".swp",
+    ".kate-swp",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SendLogStatus {
+    Waiting,
+    Sent,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SendLogEntry {
+    pub file_name: String,
+    /// Unix timestamp
+    pub timestamp: i64,
+    pub status: SendLogStatus,
+    /// The deferred send the file went out with
+    pub send_id: String,
+}
+
+/// A folder whose new files are sent to a device automatically.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchFolder {
+    pub id: String,
+    pub path: PathBuf,
+    pub device: KnownDevice,
+    #[serde(default)]
+    pub is_paused: bool,
+    /// Newest first
+    #[serde(default)]
+    pub log: Vec<SendLogEntry>,
+}
+
+impl WatchFolder {
+    pub fn new(path: PathBuf, device: KnownDevice) -> Self {
+        Self {
+            id: glib::uuid_string_random().to_string(),
+            path,
+            device,
+            is_paused: false,
+            log: vec![],
+        }
+    }
+
+    pub fn log_file(&mut self, file_name: String, send_id: String) {
+        self.log.insert(
+            0,
+            SendLogEntry {
+                file_name,
+                timestamp: deferred::now(),
+                status: SendLogStatus::Waiting,
+                send_id,
+            },
+        );
+        self.log.truncate(MAX_LOG_ENTRIES);
+    }
+
+    /// Updates every file that went out with the deferred send.
+    pub fn set_send_status(&mut self, send_id: &str, status: SendLogStatus) {
+        for entry in self.log.iter_mut().filter(|it| it.send_id == send_id) {
+            entry.status = status;
+            entry.timestamp = deferred::now();
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WatchFolders {
+    #[serde(default)]
+    pub folders: Vec<WatchFolder>,
+}
+
+impl WatchFolders {
+    pub fn path() -> PathBuf {
+        glib::user_data_dir().join("packet").join(STORE_FILE_NAME)
+    }
+
+    pub fn load() -> Self {
+        utils::load_json_or_default(&Self::path())
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        utils::save_json(&Self::path(), self)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&WatchFolder> {
+        self.folders.iter().find(|it| it.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut WatchFolder> {
+        self.folders.iter_mut().find(|it| it.id == id)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<WatchFolder> {
+        let pos = self.folders.iter().position(|it| it.id == id)?;
+        Some(self.folders.remove(pos))
+    }
+}
+
+/// Watch folders along with what's needed to keep an eye on them.
+#[derive(Debug, Default)]
+pub struct WatchFolderState {
+    pub folders: RefCell<WatchFolders>,
+    /// Watch folder id to its monitor, for the ones that aren't paused
+    pub monitors: RefCell<HashMap<String, gio::FileMonitor>>,
+    /// Files that were changed recently, waiting to settle before being sent
+    pub settling_files: RefCell<HashMap<PathBuf, SettlingFile>>,
+    /// The list in the watch folders dialog, while it's open
+    pub dialog_listbox: glib::WeakRef<gtk::ListBox>,
+}
+
+#[derive(Debug)]
+pub struct SettlingFile {
+    pub watch_folder_id: String,
+    pub source_id: glib::SourceId,
+    /// Size at the last check, the file is considered complete once it stops
+    /// changing
+    pub size: Option<i64>,
+}
+
+/// Hidden and temporary files, which are most likely still being written to or
+/// not meant to be shared.
+pub fn is_ignored_file(path: &Path) -> bool {
+    let Some(file_name) = path.file_name().map(|it| it.to_string_lossy()) else {
+        return true;
+    };
+
+    file_name.starts_with('.')
+        || file_name.starts_with('~')
+        || TEMPORARY_FILE_SUFFIXES
+            .iter()
+            .any(|suffix| file_name.ends_with(suffix))
+}
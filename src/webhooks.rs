@@ -0,0 +1,580 @@
+//! Lets home automation and such know about transfers, by POSTing JSON to a
+//! URL when they start, finish or fail.
+//!
+//! Only plain `http://` is supported, since the URL is expected to be on the
+//! local network. See the "Webhook" section of the README for the payload.
+
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, bail};
+use gtk::glib;
+use rqs_lib::{TransferState as RqsState, channel::ChannelMessage, hdl::info::TransferPayload};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use crate::{
+    config::VERSION,
+    deferred,
+    event_stream::{Direction, PayloadKind},
+};
+
+pub const PAYLOAD_VERSION: u32 = 1;
+pub const SIGNATURE_HEADER: &str = "X-Packet-Signature";
+/// Older entries of the delivery log are dropped.
+pub const MAX_LOG_ENTRIES: usize = 50;
+
+const MAX_ATTEMPTS: u32 = 3;
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Doubles after every failed attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum WebhookEvent {
+    #[serde(rename = "transfer_started")]
+    Started,
+    #[serde(rename = "transfer_finished")]
+    Finished,
+    #[serde(rename = "transfer_failed")]
+    Failed,
+    #[serde(rename = "transfer_cancelled")]
+    Cancelled,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::Started => "transfer_started",
+            WebhookEvent::Finished => "transfer_finished",
+            WebhookEvent::Failed => "transfer_failed",
+            WebhookEvent::Cancelled => "transfer_cancelled",
+        }
+    }
+}
+
+/// The JSON body of a request.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub version: u32,
+    /// Milliseconds since the Unix epoch, as in the event stream
+    pub timestamp: u64,
+    pub event: WebhookEvent,
+    pub id: String,
+    pub direction: Direction,
+    pub device: String,
+    pub payload_kind: PayloadKind,
+    pub files: Vec<String>,
+    pub total_bytes: u64,
+    pub ack_bytes: u64,
+    /// `rejected` or `disconnected`, for failed transfers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+}
+
+/// Turns transfer events into webhook events, making sure that a transfer is
+/// only reported as started once.
+#[derive(Debug, Default)]
+pub struct WebhookTracker {
+    started: HashSet<String>,
+}
+
+impl WebhookTracker {
+    pub fn payload_for(
+        &mut self,
+        channel_message: &ChannelMessage,
+        device_name: &str,
+    ) -> Option<WebhookPayload> {
+        let client_msg = channel_message.msg.as_client()?;
+        let id = &channel_message.id;
+
+        let (event, reason) = match client_msg.state.as_ref()? {
+            RqsState::ReceivingFiles | RqsState::SendingFiles => {
+                if !self.started.insert(id.clone()) {
+                    return None;
+                }
+                (WebhookEvent::Started, None)
+            }
+            RqsState::Finished => (WebhookEvent::Finished, None),
+            RqsState::Rejected => (WebhookEvent::Failed, Some("rejected")),
+            RqsState::Disconnected => (WebhookEvent::Failed, Some("disconnected")),
+            RqsState::Cancelled => (WebhookEvent::Cancelled, None),
+            _ => return None,
+        };
+        if event != WebhookEvent::Started {
+            self.started.remove(id);
+        }
+
+        let metadata = client_msg.metadata.as_ref();
+        Some(WebhookPayload {
+            version: PAYLOAD_VERSION,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|it| it.as_millis() as u64)
+                .unwrap_or_default(),
+            event,
+            id: id.clone(),
+            direction: Direction::from_kind(&client_msg.kind),
+            device: device_name.to_string(),
+            payload_kind: PayloadKind::from_metadata(metadata),
+            files: metadata
+                .and_then(|it| match &it.payload {
+                    Some(TransferPayload::Files(files)) => Some(files.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+            total_bytes: metadata.map(|it| it.total_bytes).unwrap_or_default(),
+            ack_bytes: metadata.map(|it| it.ack_bytes).unwrap_or_default(),
+            reason,
+        })
+    }
+}
+
+/// A payload along with where to send it.
+pub type WebhookDelivery = (WebhookConfig, WebhookPayload);
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Requests are signed with it if set
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookUrl {
+    pub host: String,
+    pub port: u16,
+    /// For the `Host` header
+    pub authority: String,
+    pub path: String,
+}
+
+impl WebhookUrl {
+    pub fn parse(url: &str) -> anyhow::Result<Self> {
+        let rest = url
+            .trim()
+            .strip_prefix("http://")
+            .context("Only http:// URLs are supported")?;
+        // Fragments aren't sent
+        let rest = rest.split_once('#').map_or(rest, |(rest, _)| rest);
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(index) if rest[index..].starts_with('?') => {
+                (&rest[..index], format!("/{}", &rest[index..]))
+            }
+            Some(index) => (&rest[..index], rest[index..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+
+        let (host, port) = match authority.strip_prefix('[') {
+            // IPv6
+            Some(rest) => {
+                let (host, rest) = rest.split_once(']').context("Unclosed IPv6 address")?;
+                if !host
+                    .chars()
+                    .all(|it| it.is_ascii_hexdigit() || it == ':' || it == '.')
+                {
+                    bail!("Invalid IPv6 address");
+                }
+                let port = match rest {
+                    "" => None,
+                    rest => Some(rest.strip_prefix(':').context("Invalid port")?),
+                };
+                (host, port)
+            }
+            None => {
+                let (host, port) = match authority.rsplit_once(':') {
+                    Some((host, port)) => (host, Some(port)),
+                    None => (authority, None),
+                };
+                if !host.bytes().all(is_host_char) {
+                    bail!("Invalid host {host:?}");
+                }
+                (host, port)
+            }
+        };
+        if host.is_empty() {
+            bail!("URL has no host");
+        }
+        let port = match port {
+            Some(port) if !port.is_empty() && port.bytes().all(|it| it.is_ascii_digit()) => {
+                port.parse::<u16>().context("Invalid port")?
+            }
+            Some(_) => bail!("Invalid port"),
+            None => 80,
+        };
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            authority: authority.to_string(),
+            path: encode_path(&path),
+        })
+    }
+}
+
+/// Whether the byte can be in a host name as is, as per RFC 3986.
+fn is_host_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=".contains(&byte)
+}
+
+/// Percent-encodes whatever isn't allowed in the path and query of a URL as
+/// per RFC 3986, leaving what's already encoded alone.
+fn encode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut encoded = String::with_capacity(path.len());
+    for (index, &byte) in bytes.iter().enumerate() {
+        let is_escape = byte == b'%'
+            && bytes
+                .get(index + 1..index + 3)
+                .is_some_and(|it| it.iter().all(u8::is_ascii_hexdigit));
+        if is_escape || byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@/?".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    encoded
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of the body.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    format!(
+        "sha256={}",
+        glib::compute_hmac_for_data(glib::ChecksumType::Sha256, secret.as_bytes(), body)
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct DeliveryLogEntry {
+    /// Unix timestamp
+    pub timestamp: i64,
+    pub event: WebhookEvent,
+    pub device: String,
+    pub attempts: u32,
+    /// The response status, or why it couldn't be delivered
+    pub result: Result<u16, String>,
+}
+
+/// Sends the payload, trying again a few times if it couldn't connect, timed
+/// out or got a server error.
+pub async fn deliver(config: &WebhookConfig, payload: &WebhookPayload) -> DeliveryLogEntry {
+    let entry = |attempts, result| DeliveryLogEntry {
+        timestamp: deferred::now(),
+        event: payload.event,
+        device: payload.device.clone(),
+        attempts,
+        result,
+    };
+
+    let body = match serde_json::to_vec(payload) {
+        Ok(it) => it,
+        Err(err) => return entry(0, Err(format!("{err:#}"))),
+    };
+
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        // Only what might go through on another try is retried, i.e. not
+        // client errors, or a URL that can't be parsed
+        let (result, can_retry) =
+            match tokio::time::timeout(ATTEMPT_TIMEOUT, post(config, payload.event, &body)).await {
+                Ok(Ok(status)) if (200..300).contains(&status) => (Ok(status), false),
+                Ok(Ok(status)) => (Err(format!("HTTP {status}")), status >= 500),
+                // Couldn't connect, or the connection was lost
+                Ok(Err(err)) => (
+                    Err(format!("{err:#}")),
+                    err.downcast_ref::<std::io::Error>().is_some(),
+                ),
+                Err(_) => (Err("Timed out".to_string()), true),
+            };
+
+        match result {
+            Ok(status) => {
+                tracing::info!(event = payload.event.as_str(), status, "Delivered webhook");
+                return entry(attempt, Ok(status));
+            }
+            Err(err) if !can_retry || attempt >= MAX_ATTEMPTS => {
+                tracing::warn!(
+                    event = payload.event.as_str(),
+                    attempt,
+                    "Giving up on webhook: {err}"
+                );
+                return entry(attempt, Err(err));
+            }
+            Err(err) => {
+                tracing::info!(
+                    event = payload.event.as_str(),
+                    attempt,
+                    "Webhook failed, retrying: {err}"
+                );
+            }
+        }
+
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Returns the status code of the response.
+async fn post(config: &WebhookConfig, event: WebhookEvent, body: &[u8]) -> anyhow::Result<u16> {
+    let url = WebhookUrl::parse(&config.url)?;
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         User-Agent: Packet/{VERSION}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         X-Packet-Event: {}\r\n\
+         Connection: close\r\n",
+        url.path,
+        url.authority,
+        body.len(),
+        event.as_str()
+    );
+    if let Some(secret) = config.secret.as_deref().filter(|it| !it.is_empty()) {
+        request.push_str(&format!(
+            "{SIGNATURE_HEADER}: {}\r\n",
+            signature(secret, body)
+        ));
+    }
+    request.push_str("\r\n");
+
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port))
+        .await
+        .with_context(|| format!("Couldn't connect to {}", url.authority))?;
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(body).await?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line).await?;
+
+    // e.g. `HTTP/1.1 204 No Content`
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|it| it.parse::<u16>().ok())
+        .with_context(|| format!("Invalid response {:?}", status_line.trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rqs_lib::channel::{Message, MessageClient, TransferKind};
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    use super::*;
+
+    fn message(state: RqsState) -> ChannelMessage {
+        ChannelMessage {
+            id: "transfer".into(),
+            msg: Message::Client(MessageClient {
+                kind: TransferKind::Inbound,
+                state: Some(state),
+                metadata: Some(rqs_lib::hdl::info::TransferMetadata {
+                    id: "transfer".into(),
+                    payload: Some(TransferPayload::Files(vec!["a.jpg".into()])),
+                    total_bytes: 100,
+                    ..Default::default()
+                }),
+            }),
+        }
+    }
+
+    fn payload() -> WebhookPayload {
+        WebhookTracker::default()
+            .payload_for(&message(RqsState::Finished), "Pixel")
+            .unwrap()
+    }
+
+    /// A request as the stand-in server saw it.
+    struct Request {
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// Answers each request with the next status, and hands the requests
+    /// back once they're all answered.
+    async fn serve(statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<Vec<Request>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let mut requests = vec![];
+            for status in statuses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        headers.insert(name.to_lowercase(), value.to_string());
+                    }
+                }
+
+                let mut body = vec![0; headers["content-length"].parse().unwrap()];
+                reader.read_exact(&mut body).await.unwrap();
+                reader
+                    .into_inner()
+                    .write_all(format!("HTTP/1.1 {status} Whatever\r\n\r\n").as_bytes())
+                    .await
+                    .unwrap();
+
+                requests.push(Request { headers, body });
+            }
+
+            requests
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn parses_urls() {
+        assert_eq!(
+            WebhookUrl::parse("http://192.168.1.2:8123/api/webhook/packet").unwrap(),
+            WebhookUrl {
+                host: "192.168.1.2".into(),
+                port: 8123,
+                authority: "192.168.1.2:8123".into(),
+                path: "/api/webhook/packet".into(),
+            }
+        );
+        assert_eq!(WebhookUrl::parse("http://localhost").unwrap().path, "/");
+        assert_eq!(WebhookUrl::parse("http://[::1]:80/").unwrap().host, "::1");
+        assert!(WebhookUrl::parse("https://example.com").is_err());
+        assert!(WebhookUrl::parse("http://:80").is_err());
+    }
+
+    #[test]
+    fn encodes_paths() {
+        assert_eq!(
+            WebhookUrl::parse("http://localhost/a b/ü?q=\"x\"&r=%20#fragment")
+                .unwrap()
+                .path,
+            "/a%20b/%C3%BC?q=%22x%22&r=%20"
+        );
+        assert_eq!(
+            WebhookUrl::parse("http://localhost/hook\r\nX-Injected: 1")
+                .unwrap()
+                .path,
+            "/hook%0D%0AX-Injected:%201"
+        );
+        assert_eq!(
+            WebhookUrl::parse("http://localhost/100%").unwrap().path,
+            "/100%25"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_authorities() {
+        assert!(WebhookUrl::parse("http://user@localhost/").is_err());
+        assert!(WebhookUrl::parse("http://local host/").is_err());
+        assert!(WebhookUrl::parse("http://localhost\r\nX-Injected: 1/").is_err());
+        assert!(WebhookUrl::parse("http://localhost:+80/").is_err());
+        assert!(WebhookUrl::parse("http://localhost:/").is_err());
+        assert!(WebhookUrl::parse("http://[::1]x/").is_err());
+        assert!(WebhookUrl::parse("http://[::1%eth0]/").is_err());
+    }
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        assert_eq!(
+            signature("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn reports_start_once() {
+        let mut tracker = WebhookTracker::default();
+
+        let started = tracker
+            .payload_for(&message(RqsState::ReceivingFiles), "Pixel")
+            .unwrap();
+        assert_eq!(started.event, WebhookEvent::Started);
+        assert!(
+            tracker
+                .payload_for(&message(RqsState::ReceivingFiles), "Pixel")
+                .is_none()
+        );
+
+        let failed = tracker
+            .payload_for(&message(RqsState::Disconnected), "Pixel")
+            .unwrap();
+        assert_eq!(failed.event, WebhookEvent::Failed);
+        assert_eq!(failed.reason, Some("disconnected"));
+        assert!(
+            tracker
+                .payload_for(&message(RqsState::WaitingForUserConsent), "Pixel")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_payload() {
+        let (url, server) = serve(vec![204]).await;
+        let config = WebhookConfig {
+            url,
+            secret: Some("secret".into()),
+        };
+
+        let entry = deliver(&config, &payload()).await;
+        assert_eq!(entry.result, Ok(204));
+        assert_eq!(entry.attempts, 1);
+
+        let requests = server.await.unwrap();
+        let request = &requests[0];
+        assert_eq!(
+            request.headers[&SIGNATURE_HEADER.to_lowercase()],
+            signature("secret", &request.body)
+        );
+        assert_eq!(request.headers["x-packet-event"], "transfer_finished");
+
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["event"], "transfer_finished");
+        assert_eq!(body["device"], "Pixel");
+        assert_eq!(body["files"][0], "a.jpg");
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries() {
+        let (url, server) = serve(vec![500, 200]).await;
+        let config = WebhookConfig { url, secret: None };
+
+        let entry = deliver(&config, &payload()).await;
+        assert_eq!(entry.result, Ok(200));
+        assert_eq!(entry.attempts, 2);
+
+        let requests = server.await.unwrap();
+        assert!(
+            !requests[1]
+                .headers
+                .contains_key(&SIGNATURE_HEADER.to_lowercase())
+        );
+    }
+
+    #[tokio::test]
+    async fn doesnt_retry_client_errors() {
+        let (url, server) = serve(vec![404]).await;
+        let config = WebhookConfig { url, secret: None };
+
+        let entry = deliver(&config, &payload()).await;
+        assert_eq!(entry.result, Err("HTTP 404".into()));
+        assert_eq!(entry.attempts, 1);
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+}
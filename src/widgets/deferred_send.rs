@@ -0,0 +1,799 @@
+use std::{path::Path, time::Duration};
+
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use ashpd::desktop::notification::{Notification, Priority};
+use formatx::formatx;
+use gettextrs::{gettext, ngettext};
+use gtk::glib::{self, clone};
+
+use crate::{
+    application::PacketApplication,
+    deferred::{DeferredSend, DeferredSends, KnownDevice},
+    service::ServiceCommand,
+    utils::{format_unix_timestamp, spawn_notification},
+    window::PacketApplicationWindow,
+};
+
+/// How often expired sends are looked for, and sends that couldn't be started
+/// earlier are retried.
+const DEFERRED_SENDS_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Loads the deferred sends saved by a previous run, and keeps an eye on them
+/// from here on.
+pub fn setup_deferred_sends(win: &PacketApplicationWindow) {
+    let imp = win.imp();
+
+    imp.deferred_sends.replace(DeferredSends::load());
+    tracing::info!(
+        count = imp.deferred_sends.borrow().sends.len(),
+        "Loaded deferred sends"
+    );
+    expire_deferred_sends(win);
+    publish_devices(win);
+
+    glib::timeout_add_local(
+        DEFERRED_SENDS_CHECK_INTERVAL,
+        clone!(
+            #[weak]
+            win,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                expire_deferred_sends(&win);
+                try_deferred_sends(&win);
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+}
+
+pub fn has_deferred_sends(win: &PacketApplicationWindow) -> bool {
+    !win.imp().deferred_sends.borrow().sends.is_empty()
+}
+
+/// Whether a deferred send is being sent right now.
+pub fn is_deferred_send_active(win: &PacketApplicationWindow) -> bool {
+    win.imp().active_deferred_send.borrow().is_some()
+}
+
+/// Keeps discovery going in the background, for as long as there are sends
+/// waiting on a device.
+pub fn resume_deferred_sends(win: &PacketApplicationWindow) {
+    if has_deferred_sends(win) {
+        tracing::info!("Looking for devices with deferred sends");
+        win.start_mdns_discovery(None);
+    }
+}
+
+/// Remembers the device for later, and starts any send waiting on it.
+pub fn handle_deferred_endpoint(
+    win: &PacketApplicationWindow,
+    endpoint_info: &rqs_lib::EndpointInfo,
+) {
+    let imp = win.imp();
+
+    if endpoint_info.present.is_none() {
+        if imp
+            .nearby_endpoints
+            .borrow_mut()
+            .remove(&endpoint_info.id)
+            .is_some()
+        {
+            publish_devices(win);
+        }
+        return;
+    }
+
+    if let Some(name) = &endpoint_info.name {
+        let is_changed = imp
+            .deferred_sends
+            .borrow_mut()
+            .remember_device(&endpoint_info.id, name);
+        if is_changed {
+            save(win);
+        }
+    }
+
+    let is_new = imp
+        .nearby_endpoints
+        .borrow_mut()
+        .insert(endpoint_info.id.clone(), endpoint_info.clone())
+        .is_none();
+    if is_new {
+        publish_devices(win);
+    }
+    try_deferred_sends(win);
+}
+
+/// Lets the file manager plugins know which devices files can be sent to
+/// directly, the nearby ones first and then the ones seen recently.
+pub fn publish_devices(win: &PacketApplicationWindow) {
+    let imp = win.imp();
+    let Some(app) = win.application().and_downcast::<PacketApplication>() else {
+        return;
+    };
+
+    let mut devices = imp
+        .nearby_endpoints
+        .borrow()
+        .values()
+        .filter_map(|it| Some((it.id.clone(), it.name.clone()?, true)))
+        .collect::<Vec<_>>();
+    devices.sort_by(|a, b| a.1.cmp(&b.1));
+    for device in imp.deferred_sends.borrow().devices_by_last_seen() {
+        let is_listed = devices.iter().any(|(id, _, _)| *id == device.id);
+        if !is_listed {
+            devices.push((device.id, device.name, false));
+        }
+    }
+
+    app.set_devices(devices);
+}
+
+/// Sends the files to a nearby or recently seen device without going through
+/// the recipients dialog, for the file manager plugins.
+pub fn send_files_to_device(win: &PacketApplicationWindow, endpoint_id: &str, files: Vec<String>) {
+    let imp = win.imp();
+
+    let device = imp
+        .nearby_endpoints
+        .borrow()
+        .get(endpoint_id)
+        .and_then(|it| {
+            Some(KnownDevice {
+                id: it.id.clone(),
+                name: it.name.clone()?,
+                last_seen: crate::deferred::now(),
+            })
+        })
+        .or_else(|| {
+            imp.deferred_sends
+                .borrow()
+                .devices
+                .iter()
+                .find(|it| it.id == endpoint_id)
+                .cloned()
+        });
+    let Some(device) = device else {
+        tracing::warn!(endpoint_id, "Asked to send files to an unknown device");
+        spawn_notification(
+            "send-to-device".into(),
+            Notification::new(&gettext("Couldn't Send Files"))
+                .body(gettext("The device is no longer known to Packet").as_str())
+                .priority(Priority::Normal)
+                .default_action(None),
+        );
+        return;
+    };
+
+    let files = files
+        .into_iter()
+        .filter(|it| Path::new(it).is_file())
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        return;
+    }
+
+    let is_nearby = imp.nearby_endpoints.borrow().contains_key(&device.id);
+    tracing::info!(
+        device = device.name,
+        is_nearby,
+        count = files.len(),
+        "Sending files to device directly"
+    );
+    defer_files(win, &device, files);
+
+    if !is_nearby {
+        spawn_notification(
+            "send-to-device".into(),
+            Notification::new(&device.name)
+                .body(gettext("The files will be sent once the device is nearby").as_str())
+                .priority(Priority::Normal)
+                .default_action(None),
+        );
+    }
+}
+
+/// Starts the oldest deferred send whose device is nearby, unless something
+/// else is being sent already.
+fn try_deferred_sends(win: &PacketApplicationWindow) {
+    let imp = win.imp();
+
+    if is_deferred_send_active(win)
+        || !imp.send_queue.is_idle()
+        || !win.is_no_file_being_send()
+        || imp.service.borrow().is_none()
+    {
+        return;
+    }
+
+    let next = {
+        let deferred_sends = imp.deferred_sends.borrow();
+        let nearby_endpoints = imp.nearby_endpoints.borrow();
+        deferred_sends.sends.iter().find_map(|send| {
+            nearby_endpoints
+                .values()
+                .find(|it| send.is_for(it) && it.ip.is_some() && it.port.is_some())
+                .map(|endpoint_info| (send.clone(), endpoint_info.clone()))
+        })
+    };
+    let Some((send, endpoint_info)) = next else {
+        ask_to_confirm_devices(win);
+        return;
+    };
+
+    let files = send
+        .files
+        .iter()
+        .filter(|it| std::path::Path::new(it).exists())
+        .cloned()
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        tracing::warn!(id = send.id, "Files of the deferred send are gone");
+        finish_deferred_send(
+            win,
+            &send.id,
+            false,
+            &gettext("The files to send no longer exist"),
+        );
+        return;
+    }
+
+    tracing::info!(
+        id = send.id,
+        endpoint_id = endpoint_info.id,
+        "Device appeared, starting deferred send"
+    );
+
+    imp.active_deferred_send
+        .replace(Some((endpoint_info.id.clone(), send.id.clone())));
+    imp.active_deferred_send_progress.set(None);
+    win.send_service_command(ServiceCommand::Send(rqs_lib::SendInfo {
+        id: endpoint_info.id.clone(),
+        name: send.device_name.clone(),
+        addr: format!(
+            "{}:{}",
+            endpoint_info.ip.unwrap_or_default(),
+            endpoint_info.port.unwrap_or_default()
+        ),
+        ob: rqs_lib::OutboundPayload::Files(files),
+    }));
+}
+
+/// Asks whether a nearby device that goes by the name of a device with sends
+/// waiting on it is the same one, under a new endpoint id. Nothing's sent to it
+/// unless the user says so.
+fn ask_to_confirm_devices(win: &PacketApplicationWindow) {
+    let imp = win.imp();
+
+    let candidates = {
+        let deferred_sends = imp.deferred_sends.borrow();
+        let nearby_endpoints = imp.nearby_endpoints.borrow();
+        deferred_sends
+            .sends
+            .iter()
+            .flat_map(|send| {
+                nearby_endpoints
+                    .values()
+                    .filter(|it| send.is_maybe_for(it))
+                    .map(|endpoint_info| (send.device_id.clone(), endpoint_info.clone()))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    for (old_id, endpoint_info) in candidates {
+        // Asked only once per endpoint
+        if imp
+            .device_confirmations
+            .borrow()
+            .contains_key(&endpoint_info.id)
+        {
+            continue;
+        }
+        imp.device_confirmations
+            .borrow_mut()
+            .insert(endpoint_info.id.clone(), old_id);
+
+        let name = endpoint_info.name.clone().unwrap_or_default();
+        tracing::info!(
+            endpoint_id = endpoint_info.id,
+            "Device with deferred sends may have a new id, asking to confirm"
+        );
+        spawn_notification(
+            format!("deferred-device-{}", endpoint_info.id),
+            Notification::new(&gettext("Send to This Device?"))
+                .body(
+                    formatx!(
+                        gettext(
+                            "{} is nearby under a new identity, only send the waiting files if it's the same device"
+                        ),
+                        &name
+                    )
+                    .unwrap_or_else(|_| "badly formatted locale string".into())
+                    .as_str(),
+                )
+                .priority(Priority::High)
+                .default_action(None)
+                .button(ashpd::desktop::notification::Button::new(
+                    &gettext("Don't Send"),
+                    "deferred-device-ignore",
+                ))
+                .button(
+                    ashpd::desktop::notification::Button::new(
+                        &gettext("Send"),
+                        "deferred-device-confirm",
+                    )
+                    .target(endpoint_info.id.as_str()),
+                ),
+        );
+    }
+}
+
+/// The user confirmed that the endpoint is the device that sends were waiting
+/// on, so they're sent to it from now on.
+pub fn confirm_deferred_device(win: &PacketApplicationWindow, endpoint_id: &str) {
+    let imp = win.imp();
+
+    let Some(old_id) = imp.device_confirmations.borrow().get(endpoint_id).cloned() else {
+        return;
+    };
+    let Some(device) = imp
+        .nearby_endpoints
+        .borrow()
+        .get(endpoint_id)
+        .and_then(|it| {
+            Some(KnownDevice {
+                id: it.id.clone(),
+                name: it.name.clone()?,
+                last_seen: crate::deferred::now(),
+            })
+        })
+    else {
+        return;
+    };
+
+    tracing::info!(
+        endpoint_id,
+        "Confirmed new id of device with deferred sends"
+    );
+    imp.deferred_sends
+        .borrow_mut()
+        .move_device(&old_id, &device);
+    save(win);
+    super::move_watch_folders_device(win, &old_id, &device);
+    publish_devices(win);
+
+    try_deferred_sends(win);
+}
+
+/// Follows the outbound transfer of the active deferred send, if that's what
+/// the event is for.
+pub fn handle_deferred_send_event(
+    win: &PacketApplicationWindow,
+    channel_message: &rqs_lib::channel::ChannelMessage,
+) {
+    let imp = win.imp();
+
+    let send_id = match imp.active_deferred_send.borrow().as_ref() {
+        Some((transfer_id, send_id)) if *transfer_id == channel_message.id => send_id.clone(),
+        _ => return,
+    };
+    let Some(state) = channel_message
+        .msg
+        .as_client()
+        .and_then(|it| it.state.as_ref())
+    else {
+        return;
+    };
+
+    use rqs_lib::TransferState;
+    let (is_sent, body) = match state {
+        TransferState::SendingFiles => {
+            notify_progress(win, &send_id, channel_message);
+            return;
+        }
+        TransferState::Finished => (true, gettext("Files were sent")),
+        TransferState::Rejected => (false, gettext("The device declined the files")),
+        TransferState::Cancelled => (false, gettext("The transfer was cancelled")),
+        TransferState::Disconnected => (false, gettext("The device disconnected")),
+        _ => return,
+    };
+
+    imp.active_deferred_send.replace(None);
+    finish_deferred_send(win, &send_id, is_sent, &body);
+
+    // Let whatever else was waiting go ahead
+    glib::idle_add_local_once(clone!(
+        #[weak]
+        win,
+        move || {
+            super::resume_send_queue(&win);
+            try_deferred_sends(&win);
+        }
+    ));
+}
+
+fn finish_deferred_send(win: &PacketApplicationWindow, send_id: &str, is_sent: bool, body: &str) {
+    let Some(send) = win.imp().deferred_sends.borrow_mut().remove(send_id) else {
+        return;
+    };
+    save(win);
+
+    tracing::info!(id = send.id, is_sent, body, "Deferred send finished");
+    // Sends from a watch folder are tracked in its log instead, only failures
+    // are worth a notification for those
+    if send.watch_folder.is_none() || !is_sent {
+        notify(&send, body);
+    }
+    super::handle_watch_folder_send_finished(win, &send, is_sent);
+}
+
+fn expire_deferred_sends(win: &PacketApplicationWindow) {
+    let expired = {
+        let mut deferred_sends = win.imp().deferred_sends.borrow_mut();
+        let active_id = win
+            .imp()
+            .active_deferred_send
+            .borrow()
+            .as_ref()
+            .map(|(_, send_id)| send_id.clone());
+
+        let mut expired = deferred_sends.take_expired();
+        // The active one is let to finish
+        if let Some(pos) = expired
+            .iter()
+            .position(|it| Some(&it.id) == active_id.as_ref())
+        {
+            deferred_sends.add(expired.remove(pos));
+        }
+
+        expired
+    };
+    if expired.is_empty() {
+        return;
+    }
+
+    save(win);
+    for send in &expired {
+        tracing::info!(id = send.id, "Deferred send expired");
+        notify(
+            send,
+            &gettext("The device didn't show up in time, the files weren't sent"),
+        );
+        super::handle_watch_folder_send_finished(win, send, false);
+    }
+}
+
+/// Keeps the notification of the send updated, in steps so that it isn't
+/// replaced on every chunk.
+fn notify_progress(
+    win: &PacketApplicationWindow,
+    send_id: &str,
+    channel_message: &rqs_lib::channel::ChannelMessage,
+) {
+    const PROGRESS_STEP: u64 = 10;
+
+    let imp = win.imp();
+    let Some(send) = imp
+        .deferred_sends
+        .borrow()
+        .sends
+        .iter()
+        .find(|it| it.id == send_id)
+        .cloned()
+    else {
+        return;
+    };
+    // Watch folders keep their own log
+    if send.watch_folder.is_some() {
+        return;
+    }
+
+    let percent = channel_message
+        .msg
+        .as_client()
+        .and_then(|it| it.metadata.as_ref())
+        .filter(|it| it.total_bytes > 0)
+        .map(|it| it.ack_bytes * 100 / it.total_bytes)
+        .unwrap_or_default();
+    let percent = percent - percent % PROGRESS_STEP;
+    if imp.active_deferred_send_progress.replace(Some(percent)) == Some(percent) {
+        return;
+    }
+
+    notify(
+        &send,
+        &formatx!(gettext("Sending files… {}%"), percent)
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+    );
+}
+
+fn notify(send: &DeferredSend, body: &str) {
+    spawn_notification(
+        format!("deferred-send-{}", send.id),
+        Notification::new(&send.device_name)
+            .body(body)
+            .priority(Priority::Normal)
+            .default_action(None),
+    );
+}
+
+fn save(win: &PacketApplicationWindow) {
+    if let Err(err) = win.imp().deferred_sends.borrow().save() {
+        tracing::warn!(?err, "Failed to save deferred sends");
+    }
+}
+
+fn add_deferred_send(win: &PacketApplicationWindow, device: &KnownDevice) {
+    let files = win
+        .imp()
+        .manage_files_model
+        .iter::<gtk::gio::File>()
+        .filter_map(|it| it.ok())
+        .filter_map(|it| it.path())
+        .map(|it| it.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+
+    defer_files(win, device, files);
+    win.add_toast(
+        &formatx!(
+            gettext("Files will be sent once {} is nearby"),
+            &device.name
+        )
+        .unwrap_or_else(|_| "badly formatted locale string".into()),
+    );
+}
+
+/// Sends the files to the device, now if it's nearby or once it shows up.
+pub fn defer_files(win: &PacketApplicationWindow, device: &KnownDevice, files: Vec<String>) {
+    let imp = win.imp();
+
+    let send = DeferredSend::new(
+        device,
+        files,
+        imp.settings.uint("deferred-send-expiry-hours"),
+    );
+    tracing::info!(id = send.id, device = device.name, "Added deferred send");
+    imp.deferred_sends.borrow_mut().add(send);
+    save(win);
+
+    win.start_mdns_discovery(None);
+    try_deferred_sends(win);
+}
+
+/// Keeps track of the device files were last sent to.
+pub fn remember_last_used_device(
+    win: &PacketApplicationWindow,
+    endpoint_info: &rqs_lib::EndpointInfo,
+) {
+    let Some(name) = &endpoint_info.name else {
+        return;
+    };
+
+    win.imp().deferred_sends.borrow_mut().last_used_device = Some(KnownDevice {
+        id: endpoint_info.id.clone(),
+        name: name.clone(),
+        last_seen: crate::deferred::now(),
+    });
+    save(win);
+}
+
+pub fn last_used_device(win: &PacketApplicationWindow) -> Option<KnownDevice> {
+    win.imp().deferred_sends.borrow().last_used_device.clone()
+}
+
+/// Sends a file from a watch folder to the folder's device, now if it's nearby
+/// or once it shows up. Files that come in before the send is started go along
+/// with it. Returns the id of the deferred send the file was added to.
+pub fn defer_watched_file(
+    win: &PacketApplicationWindow,
+    watch_folder_id: &str,
+    device: &KnownDevice,
+    path: &Path,
+) -> String {
+    let imp = win.imp();
+
+    let file = path.to_string_lossy().into_owned();
+    let active_id = imp
+        .active_deferred_send
+        .borrow()
+        .as_ref()
+        .map(|(_, send_id)| send_id.clone());
+
+    let send_id = {
+        let mut deferred_sends = imp.deferred_sends.borrow_mut();
+        match deferred_sends.pending_for_watch_folder(watch_folder_id, active_id.as_deref()) {
+            Some(send) => {
+                send.files.push(file);
+                send.id.clone()
+            }
+            None => {
+                let mut send = DeferredSend::new(
+                    device,
+                    vec![file],
+                    imp.settings.uint("deferred-send-expiry-hours"),
+                );
+                send.watch_folder = Some(watch_folder_id.to_string());
+                let send_id = send.id.clone();
+                deferred_sends.add(send);
+                send_id
+            }
+        }
+    };
+    tracing::info!(id = send_id, ?path, "Deferred file from watch folder");
+    save(win);
+
+    win.start_mdns_discovery(None);
+    try_deferred_sends(win);
+
+    send_id
+}
+
+fn cancel_deferred_send(win: &PacketApplicationWindow, send_id: &str) {
+    let imp = win.imp();
+
+    let active_transfer_id = imp
+        .active_deferred_send
+        .borrow()
+        .as_ref()
+        .filter(|(_, id)| id == send_id)
+        .map(|(transfer_id, _)| transfer_id.clone());
+    if let Some(transfer_id) = active_transfer_id {
+        win.send_service_command(ServiceCommand::TransferAction {
+            id: transfer_id,
+            action: rqs_lib::channel::TransferAction::TransferCancel,
+        });
+    }
+
+    let send = imp.deferred_sends.borrow_mut().remove(send_id);
+    if let Some(send) = send {
+        tracing::info!(id = send_id, "Cancelled deferred send");
+        save(win);
+        super::handle_watch_folder_send_finished(win, &send, false);
+    }
+}
+
+fn fill_device_rows(
+    win: &PacketApplicationWindow,
+    dialog: &adw::Dialog,
+    devices_listbox: &gtk::ListBox,
+) {
+    devices_listbox.remove_all();
+
+    for device in win.imp().deferred_sends.borrow().devices_by_last_seen() {
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&device.name))
+            .subtitle(
+                formatx!(
+                    gettext("Last seen {}"),
+                    format_unix_timestamp(device.last_seen)
+                )
+                .unwrap_or_else(|_| "badly formatted locale string".into()),
+            )
+            .activatable(true)
+            .build();
+        row.add_suffix(&gtk::Image::from_icon_name("go-next-symbolic"));
+        row.connect_activated(clone!(
+            #[weak]
+            win,
+            #[weak]
+            dialog,
+            move |_| {
+                add_deferred_send(&win, &device);
+                dialog.close();
+                win.close_recipients_dialog();
+            }
+        ));
+        devices_listbox.append(&row);
+    }
+}
+
+fn fill_send_rows(
+    win: &PacketApplicationWindow,
+    sends_group: &adw::PreferencesGroup,
+    sends_listbox: &gtk::ListBox,
+) {
+    sends_listbox.remove_all();
+    sends_group.set_visible(has_deferred_sends(win));
+
+    for send in win.imp().deferred_sends.borrow().sends.iter() {
+        let files_count = formatx!(
+            ngettext("{} file", "{} files", send.files.len() as u32),
+            send.files.len()
+        )
+        .unwrap_or_else(|_| "badly formatted locale string".into());
+        let expires_at = formatx!(
+            gettext("expires {}"),
+            format_unix_timestamp(send.expires_at)
+        )
+        .unwrap_or_else(|_| "badly formatted locale string".into());
+
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&send.device_name))
+            .subtitle(format!("{files_count} · {expires_at}"))
+            .build();
+        let cancel_button = gtk::Button::builder()
+            .valign(gtk::Align::Center)
+            .icon_name("cross-large-symbolic")
+            .tooltip_text(gettext("Cancel"))
+            .css_classes(["flat"])
+            .build();
+        row.add_suffix(&cancel_button);
+
+        let send_id = send.id.clone();
+        cancel_button.connect_clicked(clone!(
+            #[weak]
+            win,
+            #[weak]
+            sends_group,
+            #[weak]
+            sends_listbox,
+            move |_| {
+                cancel_deferred_send(&win, &send_id);
+                fill_send_rows(&win, &sends_group, &sends_listbox);
+            }
+        ));
+        sends_listbox.append(&row);
+    }
+}
+
+/// Lets the user pick a device seen before, to send the files to whenever it
+/// shows up next.
+pub fn present_deferred_send_dialog(win: &PacketApplicationWindow) {
+    let dialog = adw::Dialog::builder()
+        .title(gettext("Send Later"))
+        .content_width(400)
+        .content_height(480)
+        .build();
+
+    let toolbar_view = adw::ToolbarView::builder().build();
+    dialog.set_child(Some(&toolbar_view));
+    toolbar_view.add_top_bar(&adw::HeaderBar::builder().build());
+
+    let stack = gtk::Stack::new();
+    toolbar_view.set_content(Some(&stack));
+
+    let empty_page = adw::StatusPage::builder()
+        .icon_name("alarm-symbolic")
+        .title(gettext("No Known Devices"))
+        .description(gettext(
+            "Devices that were nearby before will show up here, to send files to once they're back",
+        ))
+        .build();
+    stack.add_named(&empty_page, Some("empty"));
+
+    let page = adw::PreferencesPage::new();
+    stack.add_named(&page, Some("devices"));
+
+    let devices_group = adw::PreferencesGroup::builder()
+        .title(gettext("Known Devices"))
+        .description(gettext(
+            "The files will be sent as soon as the device is nearby",
+        ))
+        .build();
+    page.add(&devices_group);
+    let devices_listbox = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    devices_group.add(&devices_listbox);
+
+    let sends_group = adw::PreferencesGroup::builder()
+        .title(gettext("Waiting to Send"))
+        .build();
+    page.add(&sends_group);
+    let sends_listbox = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    sends_group.add(&sends_listbox);
+
+    fill_device_rows(win, &dialog, &devices_listbox);
+    fill_send_rows(win, &sends_group, &sends_listbox);
+
+    let is_empty = devices_listbox.first_child().is_none() && !has_deferred_sends(win);
+    stack.set_visible_child_name(if is_empty { "empty" } else { "devices" });
+
+    dialog.present(Some(win));
+}
@@ -0,0 +1,187 @@
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gettextrs::gettext;
+use gtk::glib::{self, clone};
+
+use crate::{
+    diagnostics::{self, Check, CheckResult, CheckStatus},
+    tokio_runtime,
+    window::PacketApplicationWindow,
+};
+
+#[derive(Debug, Clone)]
+struct CheckRow {
+    check: Check,
+    row: adw::ActionRow,
+    status_bin: adw::Bin,
+}
+
+impl CheckRow {
+    fn set_running(&self) {
+        self.row.set_subtitle(&gettext("Checking…"));
+        self.status_bin.set_child(Some(&adw::Spinner::new()));
+    }
+
+    fn set_result(&self, result: &CheckResult) {
+        let subtitle = match &result.fix {
+            Some(fix) => format!("{}\n{}", result.summary, fix),
+            None => result.summary.clone(),
+        };
+        self.row.set_subtitle(&glib::markup_escape_text(&subtitle));
+
+        let (icon_name, css_class, tooltip) = match result.status {
+            CheckStatus::Passed => ("emblem-ok-symbolic", "success", gettext("Passed")),
+            CheckStatus::Warning => ("dialog-warning-symbolic", "warning", gettext("Warning")),
+            CheckStatus::Failed => ("dialog-error-symbolic", "error", gettext("Failed")),
+        };
+        let image = gtk::Image::builder()
+            .icon_name(icon_name)
+            .tooltip_text(tooltip)
+            .css_classes([css_class])
+            .build();
+        self.status_bin.set_child(Some(&image));
+    }
+}
+
+pub fn present_diagnostics_dialog(win: &PacketApplicationWindow) {
+    let dialog = adw::Dialog::builder()
+        .title(gettext("Connection Diagnostics"))
+        .content_width(480)
+        .content_height(560)
+        .build();
+
+    let toolbar_view = adw::ToolbarView::builder().build();
+    dialog.set_child(Some(&toolbar_view));
+
+    let header_bar = adw::HeaderBar::builder().build();
+    toolbar_view.add_top_bar(&header_bar);
+
+    let rerun_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("view-refresh-symbolic")
+        .tooltip_text(gettext("Run Again"))
+        .css_classes(["flat"])
+        .build();
+    header_bar.pack_start(&rerun_button);
+
+    let page = adw::PreferencesPage::new();
+    toolbar_view.set_content(Some(&page));
+
+    let group = adw::PreferencesGroup::builder()
+        .description(gettext(
+            "Checks for the common reasons why other devices can't see this device",
+        ))
+        .build();
+    page.add(&group);
+
+    let rows = Check::ALL
+        .into_iter()
+        .map(|check| {
+            let status_bin = adw::Bin::builder().valign(gtk::Align::Center).build();
+            let row = adw::ActionRow::builder()
+                .title(check.title())
+                .subtitle_selectable(true)
+                .build();
+            row.add_suffix(&status_bin);
+            group.add(&row);
+
+            CheckRow {
+                check,
+                row,
+                status_bin,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    rerun_button.connect_clicked(clone!(
+        #[weak]
+        win,
+        #[strong]
+        rows,
+        move |button| {
+            run_checks(&win, button, &rows);
+        }
+    ));
+
+    run_checks(win, &rerun_button, &rows);
+
+    dialog.present(Some(win));
+}
+
+fn run_checks(win: &PacketApplicationWindow, rerun_button: &gtk::Button, rows: &[CheckRow]) {
+    rerun_button.set_sensitive(false);
+    for row in rows {
+        row.set_running();
+    }
+
+    let rows = rows.to_vec();
+    glib::spawn_future_local(clone!(
+        #[weak]
+        win,
+        #[weak]
+        rerun_button,
+        async move {
+            let imp = win.imp();
+
+            let dbus_system_conn = imp.dbus_system_conn.borrow().clone();
+            let is_visible = imp.device_visibility_switch.is_active();
+            let service_port = win.service_port();
+
+            let set_result = |check: Check, result: &CheckResult| {
+                tracing::info!(?check, ?result, "Diagnostics check finished");
+                if let Some(row) = rows.iter().find(|it| it.check == check) {
+                    row.set_result(result);
+                }
+            };
+
+            set_result(
+                Check::Bluetooth,
+                &diagnostics::check_bluetooth(dbus_system_conn.clone()).await,
+            );
+            set_result(
+                Check::Network,
+                &diagnostics::check_network(&imp.network_monitor),
+            );
+
+            let mdns_check = finished(
+                tokio_runtime()
+                    .spawn_blocking(move || {
+                        diagnostics::check_mdns_advertisement(is_visible, service_port)
+                    })
+                    .await,
+            );
+            set_result(
+                Check::MdnsAdvertisement,
+                &mdns_check
+                    .as_ref()
+                    .map(|it| it.result.clone())
+                    .unwrap_or_else(CheckResult::incomplete),
+            );
+
+            let port = service_port.or(mdns_check.and_then(|it| it.advertised_port));
+            set_result(
+                Check::PortReachable,
+                &finished(
+                    tokio_runtime()
+                        .spawn(diagnostics::check_port_reachable(port))
+                        .await,
+                )
+                .unwrap_or_else(CheckResult::incomplete),
+            );
+
+            set_result(
+                Check::Firewall,
+                &diagnostics::check_firewall(dbus_system_conn, port).await,
+            );
+
+            rerun_button.set_sensitive(true);
+        }
+    ));
+}
+
+/// Returns `None` if the check task didn't run to completion.
+fn finished<T>(result: Result<T, tokio::task::JoinError>) -> Option<T> {
+    result
+        .inspect_err(|err| tracing::error!(err = format!("{err:#}"), "Diagnostics check"))
+        .ok()
+}
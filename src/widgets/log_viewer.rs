@@ -0,0 +1,134 @@
+use adw::prelude::*;
+use gettextrs::gettext;
+use gtk::glib::{self, clone};
+use tracing::Level;
+
+use crate::{logging, window::PacketApplicationWindow};
+
+/// Levels in the order they're listed in the filter dropdown.
+const FILTER_LEVELS: [Level; 5] = [
+    Level::ERROR,
+    Level::WARN,
+    Level::INFO,
+    Level::DEBUG,
+    Level::TRACE,
+];
+
+pub fn present_log_viewer(win: &PacketApplicationWindow) {
+    let dialog = adw::Dialog::builder()
+        .title(gettext("Logs"))
+        .content_width(640)
+        .content_height(480)
+        .build();
+
+    let toolbar_view = adw::ToolbarView::builder()
+        .top_bar_style(adw::ToolbarStyle::Flat)
+        .build();
+    dialog.set_child(Some(&toolbar_view));
+
+    let header_bar = adw::HeaderBar::builder().build();
+    toolbar_view.add_top_bar(&header_bar);
+
+    let level_names = [
+        gettext("Errors"),
+        gettext("Warnings"),
+        gettext("Info"),
+        gettext("Debug"),
+        gettext("Trace"),
+    ];
+    let level_dropdown =
+        gtk::DropDown::from_strings(&level_names.iter().map(|it| it.as_str()).collect::<Vec<_>>());
+    level_dropdown.set_valign(gtk::Align::Center);
+    level_dropdown.set_tooltip_text(Some(&gettext("Minimum log level")));
+    // Info
+    level_dropdown.set_selected(2);
+    header_bar.pack_start(&level_dropdown);
+
+    let refresh_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("view-refresh-symbolic")
+        .tooltip_text(gettext("Refresh"))
+        .css_classes(["flat"])
+        .build();
+    header_bar.pack_start(&refresh_button);
+
+    let export_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("document-save-symbolic")
+        .tooltip_text(gettext("Export logs"))
+        .action_name("win.export-logs")
+        .css_classes(["flat"])
+        .build();
+    header_bar.pack_end(&export_button);
+
+    let text_view = gtk::TextView::builder()
+        .top_margin(12)
+        .bottom_margin(12)
+        .left_margin(12)
+        .right_margin(12)
+        .editable(false)
+        .cursor_visible(false)
+        .monospace(true)
+        .wrap_mode(gtk::WrapMode::WordChar)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .vexpand(true)
+        .hexpand(true)
+        .child(&text_view)
+        .build();
+    toolbar_view.set_content(Some(&scrolled_window));
+
+    fn reload_logs(
+        text_view: &gtk::TextView,
+        scrolled_window: &gtk::ScrolledWindow,
+        level_dropdown: &gtk::DropDown,
+    ) {
+        let max_level = FILTER_LEVELS
+            .get(level_dropdown.selected() as usize)
+            .copied()
+            .unwrap_or(Level::INFO);
+
+        let text = logging::log_store()
+            .records(max_level)
+            .iter()
+            .map(|it| it.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        text_view.buffer().set_text(&text);
+
+        // Scroll to the latest record once the new text has been laid out
+        glib::idle_add_local_once(clone!(
+            #[weak]
+            scrolled_window,
+            move || {
+                let adjustment = scrolled_window.vadjustment();
+                adjustment.set_value(adjustment.upper());
+            }
+        ));
+    }
+
+    level_dropdown.connect_selected_notify(clone!(
+        #[weak]
+        text_view,
+        #[weak]
+        scrolled_window,
+        move |level_dropdown| {
+            reload_logs(&text_view, &scrolled_window, level_dropdown);
+        }
+    ));
+    refresh_button.connect_clicked(clone!(
+        #[weak]
+        text_view,
+        #[weak]
+        scrolled_window,
+        #[weak]
+        level_dropdown,
+        move |_| {
+            reload_logs(&text_view, &scrolled_window, &level_dropdown);
+        }
+    ));
+
+    reload_logs(&text_view, &scrolled_window, &level_dropdown);
+
+    dialog.present(Some(win));
+}
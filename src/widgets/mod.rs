@@ -1,7 +1,21 @@
+mod deferred_send;
+mod diagnostics;
 mod file_card;
+mod log_viewer;
+mod plugins;
 mod receive_transfer;
 mod recipient_card;
+mod send_queue;
+mod watch_folders;
+mod webhooks;
 
+pub use deferred_send::*;
+pub use diagnostics::*;
 pub use file_card::*;
+pub use log_viewer::*;
+pub use plugins::*;
 pub use receive_transfer::*;
 pub use recipient_card::*;
+pub use send_queue::*;
+pub use watch_folders::*;
+pub use webhooks::*;
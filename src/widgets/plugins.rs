@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use formatx::formatx;
+use gettextrs::{gettext, ngettext};
+use gtk::glib::{self, clone};
+
+use crate::{
+    plugins::{FileManager, PluginStatus},
+    tokio_runtime,
+    utils::with_signals_blocked,
+    window::PacketApplicationWindow,
+};
+
+/// Lists a switch for each of the file managers that are around, along with
+/// the ones whose plugin is turned on, and keeps the enabled plugins updated.
+pub fn setup_plugins_preferences(win: &PacketApplicationWindow) {
+    let imp = win.imp();
+
+    let mut has_rows = false;
+    for file_manager in FileManager::ALL {
+        let is_enabled = imp.settings.boolean(file_manager.settings_key());
+        // Enabled ones are still listed, so that they can be turned off
+        if !is_enabled && !file_manager.is_detected() {
+            continue;
+        }
+        has_rows = true;
+
+        let row = adw::SwitchRow::builder()
+            .title(file_manager.name())
+            .subtitle_selectable(true)
+            .build();
+        imp.plugins_group.add(&row);
+        imp.settings
+            .bind(file_manager.settings_key(), &row, "active")
+            .build();
+
+        if is_enabled {
+            // Update plugin
+            // This takes care of cases of applying updates to the plugin files
+            // as well as reinstalling them if they got removed for some reason.
+            glib::spawn_future_local(clone!(
+                #[weak]
+                win,
+                #[weak]
+                row,
+                async move {
+                    let success = set_plugin_installed(file_manager, true).await;
+                    if !success {
+                        win.add_toast(
+                            &formatx!(
+                                gettext("Couldn't update the {} plugin"),
+                                file_manager.name()
+                            )
+                            .unwrap_or_else(|_| "badly formatted locale string".into()),
+                        );
+                    }
+
+                    update_row_subtitle(&row, file_manager).await;
+                }
+            ));
+        } else {
+            glib::spawn_future_local(clone!(
+                #[weak]
+                row,
+                async move {
+                    update_row_subtitle(&row, file_manager).await;
+                }
+            ));
+        }
+
+        let handler_id = Rc::new(RefCell::new(None));
+        let _signal_handle = row.connect_active_notify(clone!(
+            #[weak]
+            win,
+            #[strong]
+            handler_id,
+            move |row| {
+                glib::spawn_future_local(clone!(
+                    #[weak]
+                    win,
+                    #[weak]
+                    row,
+                    #[strong]
+                    handler_id,
+                    async move {
+                        row.set_sensitive(false);
+
+                        let enable_plugin = row.is_active();
+                        tracing::info!(
+                            file_manager = file_manager.name(),
+                            enable_plugin,
+                            "Setting plugin state"
+                        );
+
+                        let success = set_plugin_installed(file_manager, enable_plugin).await;
+                        let status = update_row_subtitle(&row, file_manager).await;
+                        if enable_plugin {
+                            if !success {
+                                win.present_plugin_error_dialog(file_manager.help_install_dir());
+                                with_signals_blocked(
+                                    &[(&row, handler_id.borrow().as_ref())],
+                                    || {
+                                        row.set_active(false);
+                                    },
+                                );
+                            } else if file_manager == FileManager::Nautilus
+                                && status.missing_dependencies.is_none_or(|it| !it.is_empty())
+                            {
+                                // Lists the packages the extension needs
+                                win.present_plugin_success_dialog();
+                            } else {
+                                win.add_toast(
+                                    &formatx!(gettext("{} plugin installed"), file_manager.name())
+                                        .unwrap_or_else(|_| "badly formatted locale string".into()),
+                                );
+                            }
+                        }
+
+                        row.set_sensitive(true);
+                    }
+                ));
+            }
+        ));
+        handler_id.replace(Some(_signal_handle));
+    }
+
+    if !has_rows {
+        imp.plugins_group
+            .set_description(Some(&gettext("No supported file managers were found")));
+    }
+}
+
+/// Returns whether the plugin was installed or uninstalled successfully.
+async fn set_plugin_installed(file_manager: FileManager, is_installed: bool) -> bool {
+    let plugin = file_manager.plugin();
+    tokio_runtime()
+        .spawn_blocking(move || {
+            if is_installed {
+                plugin.install_plugin()
+            } else {
+                plugin.uninstall_plugin()
+            }
+        })
+        .await
+        .map_err(|err| anyhow::anyhow!(err))
+        .and_then(|it| it)
+        .inspect_err(|err| tracing::error!("{err:#}"))
+        .is_ok()
+}
+
+/// Shows how the installed plugin is doing, going from the most pressing issue
+/// to the least.
+async fn update_row_subtitle(row: &adw::SwitchRow, file_manager: FileManager) -> PluginStatus {
+    let plugin = file_manager.plugin();
+    let status = tokio_runtime()
+        .spawn_blocking(move || plugin.plugin_status())
+        .await
+        .unwrap_or_default();
+    tracing::debug!(file_manager = file_manager.name(), ?status, "Plugin status");
+
+    let missing_dependencies = status.missing_dependencies.clone().unwrap_or_default();
+    let missing_line = (!missing_dependencies.is_empty()).then(|| {
+        formatx!(
+            ngettext(
+                "Missing dependency {}",
+                "Missing dependencies {}",
+                missing_dependencies.len() as u32
+            ),
+            missing_dependencies
+                .iter()
+                .map(|it| it.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .unwrap_or_else(|_| "badly formatted locale string".into())
+    });
+
+    let mut lines = vec![];
+    if !status.is_installed {
+        lines.push(gettext("Not installed"));
+    } else if status.is_outdated() {
+        lines.push(
+            formatx!(
+                gettext("Outdated, version {} is installed while {} is bundled"),
+                status
+                    .installed_version
+                    .as_deref()
+                    .unwrap_or(&gettext("unknown")),
+                status
+                    .bundled_version
+                    .as_deref()
+                    .unwrap_or(&gettext("unknown"))
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+        );
+    } else if status.is_stale {
+        lines.push(gettext(
+            "Installed files don't match this version of Packet, turn it off and on again to reinstall",
+        ));
+    } else if let Some(missing_line) = missing_line.clone() {
+        lines.push(missing_line);
+    } else if status.is_loaded == Some(false) {
+        lines.push(
+            formatx!(
+                gettext("Installed, restart {} to load it"),
+                file_manager.name()
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+        );
+    } else {
+        lines.push(gettext("Installed"));
+    }
+
+    if status.is_installed {
+        // Not shown above, since it's not the most pressing issue
+        if status.is_outdated() || status.is_stale {
+            lines.extend(missing_line);
+        }
+        for hint in missing_dependencies
+            .iter()
+            .filter_map(|it| it.install_hint())
+        {
+            lines.push(hint);
+        }
+        lines.push(
+            formatx!(gettext("Installed to {}"), file_manager.help_install_dir())
+                .unwrap_or_else(|_| "badly formatted locale string".into()),
+        );
+    }
+
+    row.set_subtitle(&glib::markup_escape_text(&lines.join("\n")));
+
+    status
+}
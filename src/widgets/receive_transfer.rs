@@ -1,4 +1,9 @@
-use std::{cell::Cell, rc::Rc, time::Duration};
+use std::{
+    cell::{Cell, RefCell},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use adw::prelude::*;
 use adw::subclass::prelude::*;
@@ -10,14 +15,23 @@ use gtk::{
     glib::{self, clone},
 };
 use rqs_lib::hdl::TextPayloadType;
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
 use crate::{
     objects::{self, UserAction},
-    utils::{remove_notification, spawn_notification},
+    partial_files,
+    received_files::ReceivedFiles,
+    service::ServiceCommand,
+    tokio_runtime,
+    transfer_flow::{ConsentRequest, ReceiveEffect, ReceiveFlow, ReceiveNotice},
+    utils::{self, remove_notification, spawn_notification},
     window::PacketApplicationWindow,
 };
 
+/// How often free space is checked on while receiving files.
+const SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
 pub fn display_text_type(value: &TextPayloadType) -> String {
     match value {
         TextPayloadType::Url => gettext("Link"),
@@ -55,11 +69,9 @@ pub fn present_receive_transfer_ui(
     notification_id: String,
     auto_decline_ctk: CancellationToken,
 ) {
-    let init_id = receive_state.event().unwrap().id.clone();
-    let win = win.clone();
+    let event_msg = receive_state.event().unwrap();
 
     // Progress dialog
-    let is_user_cancelled = Rc::new(Cell::new(false));
     let progress_dialog = adw::AlertDialog::builder()
         .heading(&gettext("Receiving"))
         .width_request(200)
@@ -94,7 +106,7 @@ pub fn present_receive_transfer_ui(
         .build();
     progress_stack.add_named(&progress_files_box, Some("progress_files"));
 
-    let device_name = receive_state.event().unwrap().device_name();
+    let device_name = event_msg.device_name();
     let device_name_box = create_device_name_box(&device_name);
     device_name_box.set_margin_bottom(4);
     progress_files_box.append(&device_name_box);
@@ -123,74 +135,196 @@ pub fn present_receive_transfer_ui(
 
     progress_dialog.set_extra_child(Some(&progress_stack));
 
-    fn create_device_name_box(device_name: &str) -> gtk::Box {
-        let device_name_box = gtk::Box::builder()
-            .halign(gtk::Align::Center)
-            .spacing(8)
-            .build();
-        let avatar = adw::Avatar::builder()
-            .text(device_name)
-            .show_initials(true)
-            .size(32)
-            .build();
-        device_name_box.append(&avatar);
-        let device_label = gtk::Label::builder()
-            .label(device_name)
-            .halign(gtk::Align::Center)
-            .css_classes(["title-4"])
-            .build();
-        device_name_box.append(&device_label);
-
-        device_name_box
-    }
-
     let consent_dialog = adw::AlertDialog::builder()
         .heading(&gettext("Incoming Transfer"))
         .width_request(200)
         .build();
+    consent_dialog.connect_response(
+        None,
+        clone!(
+            #[weak]
+            receive_state,
+            move |_, response_id| {
+                match response_id {
+                    "accept" => {
+                        receive_state.set_user_action(Some(UserAction::ConsentAccept));
+                    }
+                    "decline" => {
+                        receive_state.set_user_action(Some(UserAction::ConsentDecline));
+                    }
+                    "close" => {
+                        // Incase close is called by us after receiving consent state
+                        // from notification
+                        if receive_state.user_action().is_none() {
+                            receive_state.set_user_action(Some(UserAction::ConsentDecline));
+                        }
+                    }
+                    _ => {
+                        unreachable!()
+                    }
+                };
+            }
+        ),
+    );
 
-    receive_state.connect_user_action_notify(clone!(
-        #[weak]
-        win,
-        #[weak]
-        progress_dialog,
-        #[weak]
+    let ui = ReceiveUi {
+        win: win.downgrade(),
+        receive_state: receive_state.downgrade(),
+        flow: Rc::new(RefCell::new(ReceiveFlow::new(
+            event_msg.id.clone(),
+            win.imp().settings.boolean("decline-if-low-on-space"),
+        ))),
+        notification_id,
+        auto_decline_ctk,
         consent_dialog,
-        #[weak]
-        is_user_cancelled,
+        progress_dialog,
+        progress_stack,
+        progress_bar,
+        eta_label,
+        last_space_check: Default::default(),
+    };
+
+    receive_state.connect_user_action_notify(clone!(
         #[strong]
-        auto_decline_ctk,
+        ui,
+        move |receive_state| {
+            if let Some(action) = receive_state.user_action() {
+                ui.handle_user_action(action);
+            }
+        }
+    ));
+    receive_state.connect_event_notify(clone!(
         #[strong]
-        notification_id,
+        ui,
         move |receive_state| {
-            // Cancel auto-decline
-            if !auto_decline_ctk.is_cancelled() {
-                auto_decline_ctk.cancel();
+            ui.handle_event(
+                &receive_state
+                    .event()
+                    .expect("Property setter isn't nullable"),
+            );
+        }
+    ));
+    receive_state.notify_event();
+}
+
+fn create_device_name_box(device_name: &str) -> gtk::Box {
+    let device_name_box = gtk::Box::builder()
+        .halign(gtk::Align::Center)
+        .spacing(8)
+        .build();
+    let avatar = adw::Avatar::builder()
+        .text(device_name)
+        .show_initials(true)
+        .size(32)
+        .build();
+    device_name_box.append(&avatar);
+    let device_label = gtk::Label::builder()
+        .label(device_name)
+        .halign(gtk::Align::Center)
+        .css_classes(["title-4"])
+        .build();
+    device_name_box.append(&device_label);
+
+    device_name_box
+}
+
+/// The widgets of a receive, that the [`ReceiveFlow`]'s effects are applied
+/// to.
+#[derive(Clone)]
+struct ReceiveUi {
+    win: glib::WeakRef<PacketApplicationWindow>,
+    receive_state: glib::WeakRef<objects::ReceiveTransferState>,
+    flow: Rc<RefCell<ReceiveFlow>>,
+    notification_id: String,
+    auto_decline_ctk: CancellationToken,
+    consent_dialog: adw::AlertDialog,
+    progress_dialog: adw::AlertDialog,
+    progress_stack: gtk::Stack,
+    progress_bar: gtk::ProgressBar,
+    eta_label: gtk::Label,
+    last_space_check: Rc<Cell<Option<Instant>>>,
+}
+
+impl ReceiveUi {
+    fn handle_user_action(&self, action: UserAction) {
+        // Cancel auto-decline
+        if !self.auto_decline_ctk.is_cancelled() {
+            self.auto_decline_ctk.cancel();
+        }
+
+        let effects = self.flow.borrow_mut().handle_user_action(action.into());
+        self.apply(effects);
+    }
+
+    fn handle_event(&self, event_msg: &objects::ChannelMessage) {
+        use rqs_lib::TransferState;
+
+        let Some(win) = self.win.upgrade() else {
+            return;
+        };
+
+        let download_folder = win.imp().settings.string("download-folder");
+        let free_bytes = match event_msg.msg.as_client().and_then(|it| it.state.clone()) {
+            Some(TransferState::WaitingForUserConsent) if event_msg.files().is_some() => {
+                utils::free_space(download_folder.as_str())
+            }
+            Some(TransferState::ReceivingFiles)
+                if self.flow.borrow().is_receiving()
+                    && self
+                        .last_space_check
+                        .get()
+                        .is_none_or(|it| it.elapsed() >= SPACE_CHECK_INTERVAL) =>
+            {
+                self.last_space_check.set(Some(Instant::now()));
+                utils::free_space(download_folder.as_str())
             }
+            _ => None,
+        };
 
-            let event = receive_state.event().unwrap();
-            match receive_state.user_action() {
-                Some(UserAction::ConsentAccept) => {
-                    consent_dialog.close();
+        let effects = self.flow.borrow_mut().handle_event(event_msg, free_bytes);
+        self.apply(effects);
+    }
 
-                    win.imp()
-                        .rqs
-                        .blocking_lock()
-                        .as_mut()
-                        .unwrap()
-                        .message_sender
-                        .send(rqs_lib::channel::ChannelMessage {
-                            id: event.id.to_string(),
-                            msg: rqs_lib::channel::Message::Lib {
-                                action: rqs_lib::channel::TransferAction::ConsentAccept,
-                            },
-                        })
-                        .unwrap();
+    fn apply(&self, effects: Vec<ReceiveEffect>) {
+        let (Some(win), Some(receive_state)) = (self.win.upgrade(), self.receive_state.upgrade())
+        else {
+            return;
+        };
+        let event_msg = receive_state
+            .event()
+            .expect("Property setter isn't nullable");
+
+        for effect in effects {
+            match effect {
+                ReceiveEffect::PresentConsent(request) => {
+                    self.present_consent(&win, &receive_state, &event_msg, request);
+                }
+                ReceiveEffect::CloseConsent => {
+                    self.consent_dialog.close();
+                }
+                ReceiveEffect::SendAction(action) => {
+                    win.send_service_command(ServiceCommand::TransferAction {
+                        id: event_msg.id.to_string(),
+                        action,
+                    });
+                }
+                ReceiveEffect::PresentProgress => {
+                    if let Some(files) = event_msg.files() {
+                        let download_folder = win.imp().settings.string("download-folder");
+                        let files = files.clone();
+                        let snapshot = tokio_runtime().spawn_blocking(move || {
+                            ReceivedFiles::new(Path::new(download_folder.as_str()), &files)
+                        });
+                        win.imp()
+                            .received_files
+                            .borrow_mut()
+                            .insert(event_msg.id.clone(), snapshot);
+                    }
 
                     // Update the notification
                     spawn_notification(
-                        notification_id.clone(),
-                        Notification::new(&event.device_name())
+                        self.notification_id.clone(),
+                        Notification::new(&event_msg.device_name())
                             .body(gettext("Receiving...").as_str())
                             .priority(Priority::High)
                             .display_hint([DisplayHint::Persistent])
@@ -201,624 +335,646 @@ pub fn present_receive_transfer_ui(
                             )),
                     );
 
-                    // Spawn progress dialog
-                    progress_dialog.present(Some(&win));
+                    self.progress_dialog.present(Some(&win));
                 }
-                Some(UserAction::ConsentDecline) => {
-                    consent_dialog.close();
-                    remove_notification(notification_id.clone());
-
-                    win.imp()
-                        .rqs
-                        .blocking_lock()
-                        .as_mut()
-                        .unwrap()
-                        .message_sender
-                        .send(rqs_lib::channel::ChannelMessage {
-                            id: event.id.to_string(),
-                            msg: rqs_lib::channel::Message::Lib {
-                                action: rqs_lib::channel::TransferAction::ConsentDecline,
-                            },
-                        })
-                        .unwrap();
+                ReceiveEffect::CloseProgress => {
+                    self.progress_dialog.set_can_close(true);
+                    self.progress_dialog.close();
                 }
-                Some(UserAction::TransferCancel) => {
-                    progress_dialog.set_can_close(true);
-                    progress_dialog.close();
-                    remove_notification(notification_id.clone());
-
-                    is_user_cancelled.replace(true);
+                ReceiveEffect::Progress {
+                    ack_bytes,
+                    total_bytes,
+                } => {
+                    receive_state
+                        .imp()
+                        .eta
+                        .borrow_mut()
+                        .step_with(ack_bytes as usize);
+                    if total_bytes > 0 {
+                        self.progress_bar
+                            .set_fraction(ack_bytes as f64 / total_bytes as f64);
+                    }
 
-                    win.imp()
-                        .rqs
-                        .blocking_lock()
-                        .as_mut()
-                        .unwrap()
-                        .message_sender
-                        .send(rqs_lib::channel::ChannelMessage {
-                            id: event.id.to_string(),
-                            msg: rqs_lib::channel::Message::Lib {
-                                action: rqs_lib::channel::TransferAction::TransferCancel,
-                            },
-                        })
-                        .unwrap();
+                    let eta_text = formatx!(
+                        gettext(
+                            // Translators: {} will be replaced with an estimated remaining time string
+                            // e.g. "About 4 minutes 32 seconds left"
+                            "About {} left"
+                        ),
+                        receive_state
+                            .imp()
+                            .eta
+                            .borrow()
+                            .get_estimate_string()
+                            // Why does the estimate string has a random whitespace in the front
+                            .trim()
+                    )
+                    .unwrap_or_else(|_| "badly formatted locale string".into());
+                    self.eta_label.set_label(&eta_text);
                 }
-                None => {}
-            };
+                ReceiveEffect::RemoveNotification => {
+                    remove_notification(self.notification_id.clone());
+                }
+                ReceiveEffect::Notify(notice) => {
+                    self.notify(&win, &event_msg, notice);
+                }
+                ReceiveEffect::CleanUpPartialFiles => {
+                    let files = win.imp().received_files.borrow_mut().remove(&event_msg.id);
+                    clean_up_partial_files(&win, files);
+                }
+                ReceiveEffect::PresentReceived => {
+                    present_received(&win, &event_msg, &self.notification_id);
+                }
+            }
         }
-    ));
+    }
 
-    receive_state.connect_event_notify(clone!(
-        #[weak]
-        win,
-        #[strong]
-        notification_id,
-        move |receive_state| {
-            use rqs_lib::TransferState;
-
-            let event_msg = receive_state.event().expect("Property setter isn't nullable");
-            let client_msg = event_msg.msg.as_client().unwrap();
-
-            match client_msg.state.clone().unwrap_or(TransferState::Initial) {
-                TransferState::Initial => {}
-                TransferState::ReceivedConnectionRequest => {}
-                TransferState::SentUkeyServerInit => {}
-                TransferState::SentUkeyClientInit => {}
-                TransferState::SentUkeyClientFinish => {}
-                TransferState::SentPairedKeyEncryption => {}
-                TransferState::ReceivedUkeyClientFinish => {}
-                TransferState::SentConnectionResponse => {}
-                TransferState::SentPairedKeyResult => {}
-                TransferState::SentIntroduction => {}
-                TransferState::ReceivedPairedKeyResult => {}
-                TransferState::WaitingForUserConsent => {
-                    consent_dialog.add_responses(&[
-                        ("decline", &gettext("Decline")),
-                        ("accept", &gettext("Accept")),
-                    ]);
-                    consent_dialog
-                        .set_response_appearance("accept", adw::ResponseAppearance::Suggested);
-
-                    consent_dialog.set_default_response(Some("decline"));
-                    consent_dialog.set_close_response("close");
-
-                    let info_box = gtk::Box::builder()
-                        .orientation(gtk::Orientation::Vertical)
-                        .halign(gtk::Align::Center)
-                        .spacing(8)
-                        .build();
-                    consent_dialog.set_extra_child(Some(&info_box));
-
-                    let device_name = event_msg.device_name();
-
-                    let device_name_box = create_device_name_box(&device_name);
-                    info_box.append(&device_name_box);
-
-                    let total_bytes = client_msg.metadata.as_ref().unwrap().total_bytes;
-                    let transfer_size = human_bytes::human_bytes(total_bytes as f64);
+    fn present_consent(
+        &self,
+        win: &PacketApplicationWindow,
+        receive_state: &objects::ReceiveTransferState,
+        event_msg: &objects::ChannelMessage,
+        request: ConsentRequest,
+    ) {
+        let consent_dialog = &self.consent_dialog;
+        let client_msg = event_msg.msg.as_client().unwrap();
+
+        consent_dialog.add_responses(&[
+            ("decline", &gettext("Decline")),
+            ("accept", &gettext("Accept")),
+        ]);
+        consent_dialog.set_response_appearance(
+            "accept",
+            if request.short_on_space.is_some() {
+                adw::ResponseAppearance::Destructive
+            } else {
+                adw::ResponseAppearance::Suggested
+            },
+        );
+
+        consent_dialog.set_default_response(Some("decline"));
+        consent_dialog.set_close_response("close");
+
+        let info_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .halign(gtk::Align::Center)
+            .spacing(8)
+            .build();
+        consent_dialog.set_extra_child(Some(&info_box));
 
-                    if let Some(files) = event_msg.files() {
-                        let file_count = files.len();
-
-                        let files_label = gtk::Label::builder()
-                            .label(
-                                formatx!(
-                                    ngettext(
-                                        // Translators: An e.g. "6 Files (42.3MB)"
-                                        "{} file ({})",
-                                        "{} files ({})",
-                                        file_count as u32,
-                                    ),
-                                    file_count,
-                                    transfer_size
-                                )
-                                .unwrap_or_else(|_| "badly formatted locale string".into()),
-                            )
-                            .halign(gtk::Align::Center)
-                            .css_classes(["dimmed", "heading"])
-                            .build();
-                        info_box.append(&files_label);
-                    } else {
-                        let text_info_label = gtk::Label::builder()
-                            .ellipsize(gtk::pango::EllipsizeMode::End)
-                            .max_width_chars(36)
-                            .label(
-                                formatx!(
-                                    gettext("Preview ({})"),
-                                    clean_preview_text_payload(&event_msg.text_preview().unwrap())
-                                )
-                                .unwrap_or_else(|_| "badly formatted locale string".into()),
-                            )
-                            .halign(gtk::Align::Center)
-                            .css_classes(["dimmed"])
-                            .build();
-                        info_box.append(&text_info_label);
-                    }
+        let device_name_box = create_device_name_box(&event_msg.device_name());
+        info_box.append(&device_name_box);
 
-                    let pincode_label = gtk::Label::builder()
-                        .label(
-                            formatx!(
-                                gettext(
-                                    // Translators: This is the pin-code for the transfer
-                                    "Code: {}"
-                                ),
-                                client_msg
-                                    .metadata
-                                    .as_ref()
-                                    .unwrap()
-                                    .pin_code
-                                    .clone()
-                                    .unwrap_or_default()
-                            )
-                            .unwrap_or_else(|_| "badly formatted locale string".into()),
-                        )
-                        .halign(gtk::Align::Center)
-                        .css_classes(["dimmed", "monospace"])
-                        .build();
-                    info_box.append(&pincode_label);
-
-                    consent_dialog.connect_response(
-                        None,
-                        clone!(
-                            #[weak]
-                            receive_state,
-                            move |_, response_id| {
-                                match response_id {
-                                    "accept" => {
-                                        receive_state.set_user_action(Some(UserAction::ConsentAccept));
-                                    }
-                                    "decline" => {
-                                        receive_state.set_user_action(Some(UserAction::ConsentDecline));
-                                    }
-                                    "close" => {
-                                        // Incase close is called by us after receiving consent state
-                                        // from notification
-                                        if receive_state.user_action().is_none() {
-                                            receive_state.set_user_action(Some(UserAction::ConsentDecline));
-                                        }
-                                    }
-                                    _ => {
-                                        unreachable!()
-                                    }
-                                };
-                            }
-                        ),
-                    );
+        let total_bytes = client_msg.metadata.as_ref().unwrap().total_bytes;
+        let transfer_size = human_bytes::human_bytes(total_bytes as f64);
 
-                    // Timeout: auto-decline after 10 seconds
-                    // Since we can't know if the user has simply closed the notification,
-                    // we can't use it as a decline response unfortunately. The solution is
-                    // to have a 10s timeout for incoming requests.
-                    glib::spawn_future_local(clone!(
-                        #[weak]
-                        win,
-                        #[strong]
-                        receive_state,
-                        #[strong]
-                        auto_decline_ctk,
-                        async move {
-                            tokio::select! {
-                                _ = futures_timer::Delay::new(Duration::from_secs(10)) => {
-                                    if receive_state.user_action().is_none() {
-                                        receive_state.set_user_action(Some(UserAction::ConsentDecline));
-                                        win.imp().toast_overlay.add_toast(adw::Toast::new(&gettext("Request timed out")));
-                                    }
-                                }
-                                _ = auto_decline_ctk.cancelled() => {}
-                            }
-                        }
-                    ));
+        if let Some(files) = event_msg.files() {
+            let file_count = files.len();
 
-                    let body = formatx!(
-                        gettext(
-                            // Translators: This is when some device is sharing files or text
-                            // e.g. (Someone's Phone wants to share 4 files)
-                            // e.g. (Someone's Phone wants to share "lorem ipsum ...")
-                            "{} wants to share {}"
+            let files_label = gtk::Label::builder()
+                .label(
+                    formatx!(
+                        ngettext(
+                            // Translators: An e.g. "6 Files (42.3MB)"
+                            "{} file ({})",
+                            "{} files ({})",
+                            file_count as u32,
                         ),
-                        event_msg.device_name(),
-                        if let Some(files) = event_msg.files() {
-                            formatx!(
-                                ngettext("{} File", "{} Files", files.len() as u32),
-                                files.len()
-                            )
-                            .unwrap_or_default()
-                        } else {
-                            format!(
-                                "\"{}\"",
-                                clean_preview_text_payload(
-                                    &event_msg.text_preview().unwrap(),
-                                )
-                            )
-                        }
+                        file_count,
+                        transfer_size
                     )
-                    .unwrap_or_default();
-
-                    // Use a static id, like the app id
-                    // There will only be one request at a time anyways
-                    // And, we'll also need to close the notification on exit
-                    // or it'll persist otherwise
-                    spawn_notification(
-                        notification_id.clone(),
-                        Notification::new(&gettext("Incoming Transfer"))
-                            .default_action("accept")
-                            .body(body.as_str())
-                            .priority(Priority::High)
-                            // Persistent doesn't work (the close button is still there), atleast with gnome portal
-                            .display_hint([DisplayHint::Persistent])
-                            .button(ashpd::desktop::notification::Button::new(
-                                &gettext("Decline"),
-                                "consent-decline",
-                            ))
-                            .button(ashpd::desktop::notification::Button::new(
-                                &gettext("Accept"),
-                                "consent-accept",
-                            )),
-                    );
-
-                    consent_dialog.present(Some(&win));
+                    .unwrap_or_else(|_| "badly formatted locale string".into()),
+                )
+                .halign(gtk::Align::Center)
+                .css_classes(["dimmed", "heading"])
+                .build();
+            info_box.append(&files_label);
+
+            if let Some(free_bytes) = request.short_on_space {
+                let space_label = gtk::Label::builder()
+                    .label(
+                        formatx!(
+                            gettext(
+                                // Translators: {} is the free space left, e.g. "Not enough space, only 1.2 GB free"
+                                "Not enough space, only {} free"
+                            ),
+                            human_bytes::human_bytes(free_bytes as f64)
+                        )
+                        .unwrap_or_else(|_| "badly formatted locale string".into()),
+                    )
+                    .halign(gtk::Align::Center)
+                    .wrap(true)
+                    .justify(gtk::Justification::Center)
+                    .css_classes(["error", "caption-heading"])
+                    .build();
+                info_box.append(&space_label);
+            }
+        } else {
+            let text_info_label = gtk::Label::builder()
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .max_width_chars(36)
+                .label(
+                    formatx!(
+                        gettext("Preview ({})"),
+                        clean_preview_text_payload(&event_msg.text_preview().unwrap())
+                    )
+                    .unwrap_or_else(|_| "badly formatted locale string".into()),
+                )
+                .halign(gtk::Align::Center)
+                .css_classes(["dimmed"])
+                .build();
+            info_box.append(&text_info_label);
+        }
 
-                    // TODO: show a progress dialog for both but with a delay?
-                    // Create Progress bar dialog
-                    let total_bytes = client_msg.metadata.as_ref().unwrap().total_bytes;
-                    receive_state
-                        .imp()
-                        .eta
-                        .borrow_mut()
-                        .prepare_for_new_transfer(Some(total_bytes as usize));
-                    if event_msg.is_text_type() {
-                        progress_stack.set_visible_child_name("progress_text");
-                    }
-                }
-                TransferState::ReceivingFiles => {
-                    if !event_msg.is_text_type() {
-                        let eta_text = {
-                            if let Some(meta) = &client_msg.metadata {
-                                receive_state
-                                    .imp()
-                                    .eta
-                                    .borrow_mut()
-                                    .step_with(meta.ack_bytes as usize);
-
-                                if meta.total_bytes > 0 {
-                                    progress_bar.set_fraction(
-                                        meta.ack_bytes as f64 / meta.total_bytes as f64,
-                                    );
-                                }
-                            }
-
-                            formatx!(
-                                gettext(
-                                    // Translators: {} will be replaced with an estimated remaining time string
-                                    // e.g. "About 4 minutes 32 seconds left"
-                                    "About {} left"
-                                ),
-                                receive_state
-                                    .imp()
-                                    .eta
-                                    .borrow()
-                                    .get_estimate_string()
-                                    // Why does the estimate string has a random whitespace in the front
-                                    .trim()
-                            )
-                            .unwrap_or_else(|_| "badly formatted locale string".into())
-                        };
-                        eta_label.set_label(&eta_text);
+        let pincode_label = gtk::Label::builder()
+            .label(
+                formatx!(
+                    gettext(
+                        // Translators: This is the pin-code for the transfer
+                        "Code: {}"
+                    ),
+                    client_msg
+                        .metadata
+                        .as_ref()
+                        .unwrap()
+                        .pin_code
+                        .clone()
+                        .unwrap_or_default()
+                )
+                .unwrap_or_else(|_| "badly formatted locale string".into()),
+            )
+            .halign(gtk::Align::Center)
+            .css_classes(["dimmed", "monospace"])
+            .build();
+        info_box.append(&pincode_label);
+
+        // Timeout: auto-decline after 10 seconds
+        // Since we can't know if the user has simply closed the notification,
+        // we can't use it as a decline response unfortunately. The solution is
+        // to have a 10s timeout for incoming requests.
+        let auto_decline_ctk = self.auto_decline_ctk.clone();
+        glib::spawn_future_local(clone!(
+            #[strong(rename_to = ui)]
+            self,
+            async move {
+                tokio::select! {
+                    _ = futures_timer::Delay::new(Duration::from_secs(10)) => {
+                        let effects = ui.flow.borrow_mut().consent_timed_out();
+                        ui.apply(effects);
                     }
+                    _ = auto_decline_ctk.cancelled() => {}
                 }
-                TransferState::SendingFiles => {}
-                TransferState::Disconnected => {
-                    if event_msg.id == init_id {
-                        progress_dialog.set_can_close(true);
-                        if let Some(UserAction::ConsentAccept) = receive_state.user_action() {
-                            progress_dialog.close();
-                        } else {
-                            consent_dialog.close();
-                        }
+            }
+        ));
+
+        let body = formatx!(
+            gettext(
+                // Translators: This is when some device is sharing files or text
+                // e.g. (Someone's Phone wants to share 4 files)
+                // e.g. (Someone's Phone wants to share "lorem ipsum ...")
+                "{} wants to share {}"
+            ),
+            event_msg.device_name(),
+            if let Some(files) = event_msg.files() {
+                formatx!(
+                    ngettext("{} File", "{} Files", files.len() as u32),
+                    files.len()
+                )
+                .unwrap_or_default()
+            } else {
+                format!(
+                    "\"{}\"",
+                    clean_preview_text_payload(&event_msg.text_preview().unwrap(),)
+                )
+            }
+        )
+        .unwrap_or_default();
+
+        // Use a static id, like the app id
+        // There will only be one request at a time anyways
+        // And, we'll also need to close the notification on exit
+        // or it'll persist otherwise
+        spawn_notification(
+            self.notification_id.clone(),
+            Notification::new(&gettext("Incoming Transfer"))
+                .default_action("accept")
+                .body(body.as_str())
+                .priority(Priority::High)
+                // Persistent doesn't work (the close button is still there), atleast with gnome portal
+                .display_hint([DisplayHint::Persistent])
+                .button(ashpd::desktop::notification::Button::new(
+                    &gettext("Decline"),
+                    "consent-decline",
+                ))
+                .button(ashpd::desktop::notification::Button::new(
+                    &gettext("Accept"),
+                    "consent-accept",
+                )),
+        );
+
+        consent_dialog.present(Some(win));
+
+        // TODO: show a progress dialog for both but with a delay?
+        // Create Progress bar dialog
+        receive_state
+            .imp()
+            .eta
+            .borrow_mut()
+            .prepare_for_new_transfer(Some(total_bytes as usize));
+        if event_msg.is_text_type() {
+            self.progress_stack.set_visible_child_name("progress_text");
+        }
+    }
 
-                        let body = gettext("Unexpected dissconnection");
-
-                        spawn_notification(
-                            notification_id.clone(),
-                            Notification::new(&event_msg.device_name())
-                                .body(body.as_str())
-                                .priority(Priority::High)
-                                .default_action(None)
-                        );
-
-                        win.imp().toast_overlay.add_toast(
-                            adw::Toast::builder()
-                                .title(&body)
-                                .priority(adw::ToastPriority::High)
-                                .build(),
-                        );
-
-                        // FIXME: If ReceivingFiles is not received within 5~10 seconds of an Accept,
-                        // reject request and show this error, it's usually because the sender
-                        // disconnected from the network
-                    }
-                }
-                TransferState::Rejected => {}
-                TransferState::Cancelled => {
-                    progress_dialog.set_can_close(true);
-                    if let Some(UserAction::ConsentAccept) = receive_state.user_action() {
-                        progress_dialog.close();
-                    } else {
-                        consent_dialog.close();
-                    }
+    fn notify(
+        &self,
+        win: &PacketApplicationWindow,
+        event_msg: &objects::ChannelMessage,
+        notice: ReceiveNotice,
+    ) {
+        match notice {
+            ReceiveNotice::DeclinedLowOnSpace => {
+                let body = formatx!(
+                    gettext("Declined files from {} since there isn't enough free space"),
+                    event_msg.device_name()
+                )
+                .unwrap_or_else(|_| "badly formatted locale string".into());
+                // Not the request's id, since removing that notification races with this
+                spawn_notification(
+                    glib::uuid_string_random().to_string(),
+                    Notification::new(&gettext("Not Enough Space"))
+                        .body(body.as_str())
+                        .priority(Priority::High)
+                        .default_action(None),
+                );
+                win.add_toast(&body);
+            }
+            ReceiveNotice::TimedOut => {
+                win.add_toast(&gettext("Request timed out"));
+            }
+            ReceiveNotice::OutOfSpace
+            | ReceiveNotice::Disconnected
+            | ReceiveNotice::CancelledBySender => {
+                let (notification_id, body) = match notice {
+                    ReceiveNotice::OutOfSpace => (
+                        // Same as above
+                        glib::uuid_string_random().to_string(),
+                        gettext("Transfer cancelled, the download folder ran out of space"),
+                    ),
+                    ReceiveNotice::Disconnected => (
+                        self.notification_id.clone(),
+                        gettext("Unexpected dissconnection"),
+                    ),
+                    _ => (
+                        self.notification_id.clone(),
+                        gettext("Transfer cancelled by sender"),
+                    ),
+                };
+
+                spawn_notification(
+                    notification_id,
+                    Notification::new(&event_msg.device_name())
+                        .body(body.as_str())
+                        .priority(Priority::High)
+                        .default_action(None),
+                );
+                win.imp().toast_overlay.add_toast(
+                    adw::Toast::builder()
+                        .title(&body)
+                        .priority(adw::ToastPriority::High)
+                        .build(),
+                );
+            }
+        }
+    }
+}
 
-                    // Since Cancelled also triggers on cancellation from the user
-                    if !is_user_cancelled.get() {
-                        let body = gettext("Transfer cancelled by sender");
-
-                        spawn_notification(
-                            notification_id.clone(),
-                            Notification::new(&event_msg.device_name())
-                                .body(body.as_str())
-                                .priority(Priority::High)
-                                .default_action(None)
-                        );
-
-                        win.imp().toast_overlay.add_toast(
-                            adw::Toast::builder()
-                                .title(&body)
-                                .priority(adw::ToastPriority::High)
-                                .build(),
-                        );
-                    }
-                }
-                TransferState::Finished => {
-                    progress_dialog.set_can_close(true);
-                    if let Some(UserAction::ConsentAccept) = receive_state.user_action() {
-                        progress_dialog.close();
-                    } else {
-                        consent_dialog.close();
-                    }
+fn present_received(
+    win: &PacketApplicationWindow,
+    event_msg: &objects::ChannelMessage,
+    notification_id: &str,
+) {
+    if let Some(text_data) = event_msg.transferred_text_data() {
+        let text_type = text_data.1;
+
+        let dialog = adw::Dialog::builder()
+            .content_width(400)
+            .content_height(200)
+            .title(display_text_type(&text_type))
+            .build();
+
+        let toolbar_view = adw::ToolbarView::builder()
+            .top_bar_style(adw::ToolbarStyle::Flat)
+            .build();
+        dialog.set_child(Some(&toolbar_view));
+
+        let header_bar = adw::HeaderBar::builder().build();
+        toolbar_view.add_top_bar(&header_bar);
+
+        let copy_text_button = gtk::Button::builder()
+            .valign(gtk::Align::Center)
+            .hexpand(true)
+            .icon_name("edit-copy-symbolic")
+            .tooltip_text(&gettext("Copy to clipboard"))
+            .css_classes(["circular", "flat"])
+            .build();
+        let save_text_button = gtk::Button::builder()
+            .visible(false)
+            .valign(gtk::Align::Center)
+            .hexpand(true)
+            .icon_name("document-save-symbolic")
+            .tooltip_text(&gettext("Save text as file"))
+            .css_classes(["circular", "flat"])
+            .build();
+        header_bar.pack_start(&copy_text_button);
+        header_bar.pack_start(&save_text_button);
 
-                    if let Some(text_data) = event_msg.transferred_text_data() {
-                        let text_type = text_data.1;
-
-                        let dialog = adw::Dialog::builder()
-                            .content_width(400)
-                            .content_height(200)
-                            .title(display_text_type(&text_type))
-                            .build();
-
-                        let toolbar_view = adw::ToolbarView::builder()
-                            .top_bar_style(adw::ToolbarStyle::Flat)
-                            .build();
-                        dialog.set_child(Some(&toolbar_view));
-
-                        let header_bar = adw::HeaderBar::builder().build();
-                        toolbar_view.add_top_bar(&header_bar);
-
-                        let copy_text_button = gtk::Button::builder()
-                            .valign(gtk::Align::Center)
-                            .hexpand(true)
-                            .icon_name("edit-copy-symbolic")
-                            .tooltip_text(&gettext("Copy to clipboard"))
-                            .css_classes(["circular", "flat"])
-                            .build();
-                        let save_text_button = gtk::Button::builder()
-                            .visible(false)
-                            .valign(gtk::Align::Center)
-                            .hexpand(true)
-                            .icon_name("document-save-symbolic")
-                            .tooltip_text(&gettext("Save text as file"))
-                            .css_classes(["circular", "flat"])
-                            .build();
-                        header_bar.pack_start(&copy_text_button);
-                        header_bar.pack_start(&save_text_button);
-
-                        let clamp = adw::Clamp::builder()
-                            .maximum_size(550)
-                            .hexpand(true)
-                            .vexpand(true)
-                            .build();
-                        toolbar_view.set_content(Some(&clamp));
-
-                        let root_box = gtk::Box::builder()
-                            .orientation(gtk::Orientation::Vertical)
-                            .hexpand(true)
-                            .margin_top(6)
-                            .margin_bottom(18)
-                            .margin_start(18)
-                            .margin_end(18)
-                            .spacing(18)
-                            .build();
-                        clamp.set_child(Some(&root_box));
-
-                        let caption_label = gtk::Label::builder()
-                            .use_markup(true)
-                            .wrap(true)
-                            .visible(false)
-                            .build();
-                        root_box.append(&caption_label);
-
-                        let text_view = gtk::TextView::builder()
-                            .top_margin(12)
-                            .bottom_margin(12)
-                            .left_margin(12)
-                            .right_margin(12)
-                            .editable(false)
-                            .cursor_visible(false)
-                            .monospace(true)
-                            .wrap_mode(gtk::WrapMode::Word)
-                            .build();
-
-                        let text_view_frame = gtk::Frame::builder()
-                            .vexpand(true)
-                            .child(
-                                &gtk::ScrolledWindow::builder()
-                                    .vexpand(true)
-                                    .child(&text_view)
-                                    .build(),
-                            )
-                            .build();
-                        root_box.append(&text_view_frame);
-
-                        let open_uri_button = gtk::Button::builder()
-                            .halign(gtk::Align::Center)
-                            .valign(gtk::Align::Center)
-                            .height_request(50)
-                            .label(&gettext("Open"))
-                            .css_classes(["pill", "suggested-action"])
-                            .build();
-                        root_box.append(&open_uri_button);
-                        if text_type.clone() as u32 == TextPayloadType::Url as u32 {
-                            open_uri_button.set_visible(true);
+        let clamp = adw::Clamp::builder()
+            .maximum_size(550)
+            .hexpand(true)
+            .vexpand(true)
+            .build();
+        toolbar_view.set_content(Some(&clamp));
+
+        let root_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .hexpand(true)
+            .margin_top(6)
+            .margin_bottom(18)
+            .margin_start(18)
+            .margin_end(18)
+            .spacing(18)
+            .build();
+        clamp.set_child(Some(&root_box));
+
+        let caption_label = gtk::Label::builder()
+            .use_markup(true)
+            .wrap(true)
+            .visible(false)
+            .build();
+        root_box.append(&caption_label);
+
+        let text_view = gtk::TextView::builder()
+            .top_margin(12)
+            .bottom_margin(12)
+            .left_margin(12)
+            .right_margin(12)
+            .editable(false)
+            .cursor_visible(false)
+            .monospace(true)
+            .wrap_mode(gtk::WrapMode::Word)
+            .build();
+
+        let text_view_frame = gtk::Frame::builder()
+            .vexpand(true)
+            .child(
+                &gtk::ScrolledWindow::builder()
+                    .vexpand(true)
+                    .child(&text_view)
+                    .build(),
+            )
+            .build();
+        root_box.append(&text_view_frame);
+
+        let open_uri_button = gtk::Button::builder()
+            .halign(gtk::Align::Center)
+            .valign(gtk::Align::Center)
+            .height_request(50)
+            .label(&gettext("Open"))
+            .css_classes(["pill", "suggested-action"])
+            .build();
+        root_box.append(&open_uri_button);
+        if text_type.clone() as u32 == TextPayloadType::Url as u32 {
+            open_uri_button.set_visible(true);
+        } else {
+            open_uri_button.set_visible(false);
+        }
+
+        save_text_button.connect_clicked(clone!(
+            #[weak]
+            win,
+            #[weak]
+            text_view,
+            move |_| {
+                let text = text_view.buffer().text(
+                    &text_view.buffer().start_iter(),
+                    &text_view.buffer().end_iter(),
+                    false,
+                );
+
+                glib::spawn_future_local(async move {
+                    let file = gtk::FileDialog::new()
+                        .save_text_file_future(Some(&win))
+                        .await
+                        .unwrap()
+                        .0
+                        .unwrap();
+
+                    let text_bytes = text.into_bytes();
+                    file.create_readwrite_future(
+                        gio::FileCreateFlags::REPLACE_DESTINATION,
+                        Default::default(),
+                    )
+                    .await
+                    .unwrap()
+                    .output_stream()
+                    .write_all_future(text_bytes, Default::default())
+                    .await
+                    .unwrap();
+                });
+            }
+        ));
+
+        let clipboard = win.clipboard();
+        copy_text_button.connect_clicked(clone!(
+            #[weak]
+            text_view,
+            #[strong]
+            clipboard,
+            move |_| {
+                let text = text_view.buffer().text(
+                    &text_view.buffer().start_iter(),
+                    &text_view.buffer().end_iter(),
+                    false,
+                );
+                clipboard.set_text(&text);
+            }
+        ));
+
+        open_uri_button.connect_clicked(clone!(
+            #[weak]
+            win,
+            #[weak]
+            text_view,
+            move |_| {
+                let url = text_view.buffer().text(
+                    &text_view.buffer().start_iter(),
+                    &text_view.buffer().end_iter(),
+                    false,
+                );
+
+                gtk::UriLauncher::new(&url).launch(
+                    win.root().and_downcast_ref::<adw::ApplicationWindow>(),
+                    None::<gio::Cancellable>.as_ref(),
+                    |_err| {},
+                );
+            }
+        ));
+
+        let raw_text = text_data.0;
+        let text = if text_type.clone() as u32 == TextPayloadType::Text as u32 {
+            save_text_button.set_visible(true);
+            clean_text_payload(&raw_text)
+        } else {
+            &raw_text
+        };
+        text_view.set_buffer(Some(&gtk::TextBuffer::builder().text(text).build()));
+
+        spawn_notification(
+            notification_id.to_string(),
+            Notification::new(&event_msg.device_name())
+                .body(
+                    formatx!(
+                        gettext("Received \"{}\""),
+                        if text.len() > 48 {
+                            format!("{}{}", &text[..48], "...")
                         } else {
-                            open_uri_button.set_visible(false);
+                            text.into()
                         }
+                    )
+                    .unwrap_or_default()
+                    .as_str(),
+                )
+                .priority(Priority::High)
+                .display_hint([DisplayHint::ShowAsNew])
+                .default_action("copy-text")
+                .default_action_target(text)
+                .button(
+                    ashpd::desktop::notification::Button::new(&gettext("Copy"), "copy-text")
+                        .target(text),
+                ),
+        );
+
+        // FIXME: Redo the Wi-Fi view when we've more info such as the Wi-Fi security type
+        // and payload (password) available separately
+
+        dialog.present(Some(win));
+    } else {
+        // Received Files
+        let file_count = event_msg.files().unwrap().len();
+
+        let body = formatx!(
+            ngettext("{} file received", "{} files received", file_count as u32),
+            file_count
+        )
+        .unwrap_or_else(|_| "badly formatted locale string".into());
+
+        let target = win.imp().settings.string("download-folder");
+        spawn_notification(
+            notification_id.to_string(),
+            Notification::new(&event_msg.device_name())
+                .body(body.as_str())
+                .priority(Priority::High)
+                .display_hint([DisplayHint::ShowAsNew])
+                .default_action("open-folder")
+                .default_action_target(target.as_str())
+                .button(
+                    ashpd::desktop::notification::Button::new(&gettext("Open"), "open-folder")
+                        .target(target.as_str()),
+                ),
+        );
+        let toast = adw::Toast::builder()
+            .title(&body)
+            .button_label(&gettext("Open"))
+            .action_name("win.received-files")
+            .priority(adw::ToastPriority::High)
+            .build();
+        win.imp().toast_overlay.add_toast(toast);
+    }
+}
 
-                        save_text_button.connect_clicked(clone!(
-                            #[weak]
-                            win,
-                            #[weak]
-                            text_view,
-                            move |_| {
-                                let text = text_view.buffer().text(
-                                    &text_view.buffer().start_iter(),
-                                    &text_view.buffer().end_iter(),
-                                    false,
-                                );
-
-                                glib::spawn_future_local(async move {
-                                    let file = gtk::FileDialog::new()
-                                        .save_text_file_future(Some(&win))
-                                        .await
-                                        .unwrap()
-                                        .0
-                                        .unwrap();
-
-                                    let text_bytes = text.into_bytes();
-                                    file.create_readwrite_future(
-                                        gio::FileCreateFlags::REPLACE_DESTINATION,
-                                        Default::default(),
-                                    )
-                                    .await
-                                    .unwrap()
-                                    .output_stream()
-                                    .write_all_future(text_bytes, Default::default())
-                                    .await
-                                    .unwrap();
-                                });
-                            }
-                        ));
-
-                        let clipboard = win.clipboard();
-                        copy_text_button.connect_clicked(clone!(
-                            #[weak]
-                            text_view,
-                            #[strong]
-                            clipboard,
-                            move |_| {
-                                let text = text_view.buffer().text(
-                                    &text_view.buffer().start_iter(),
-                                    &text_view.buffer().end_iter(),
-                                    false,
-                                );
-                                clipboard.set_text(&text);
-                            }
-                        ));
-
-                        open_uri_button.connect_clicked(clone!(
-                            #[weak]
-                            win,
-                            #[weak]
-                            text_view,
-                            move |_| {
-                                let url = text_view.buffer().text(
-                                    &text_view.buffer().start_iter(),
-                                    &text_view.buffer().end_iter(),
-                                    false,
-                                );
-
-                                gtk::UriLauncher::new(&url).launch(
-                                    win.root().and_downcast_ref::<adw::ApplicationWindow>(),
-                                    None::<gio::Cancellable>.as_ref(),
-                                    |_err| {},
-                                );
-                            }
-                        ));
-
-                        let raw_text = text_data.0;
-                        let text = if text_type.clone() as u32 == TextPayloadType::Text as u32 {
-                            save_text_button.set_visible(true);
-                            clean_text_payload(&raw_text)
-                        } else {
-                            &raw_text
-                        };
-                        text_view.set_buffer(Some(&gtk::TextBuffer::builder().text(text).build()));
-
-                        spawn_notification(
-                            notification_id.clone(),
-                            Notification::new(&event_msg.device_name())
-                                .body(
-                                    formatx!(
-                                        gettext("Received \"{}\""),
-                                        if text.len() > 48 {
-                                            format!("{}{}", &text[..48], "...")
-                                        } else {
-                                            text.into()
-                                        }
-                                    )
-                                    .unwrap_or_default()
-                                    .as_str()
-                                )
-                                .priority(Priority::High)
-                                .display_hint([DisplayHint::ShowAsNew])
-                                .default_action("copy-text")
-                                .default_action_target(text)
-                                .button(
-                                    ashpd::desktop::notification::Button::new(&gettext("Copy"), "copy-text")
-                                        .target(text)
-                                )
-                        );
-
-                        // FIXME: Redo the Wi-Fi view when we've more info such as the Wi-Fi security type
-                        // and payload (password) available separately
-
-                        dialog.present(Some(&win));
-                    } else {
-                        // Received Files
-                        let file_count = event_msg.files().unwrap().len();
-
-                        let body = formatx!(
-                            ngettext(
-                                "{} file received",
-                                "{} files received",
-                                file_count as u32
-                            ),
-                            file_count
-                        )
-                            .unwrap_or_else(|_| "badly formatted locale string".into());
-
-                        let target = win.imp().settings.string("download-folder");
-                        spawn_notification(
-                            notification_id.clone(),
-                            Notification::new(&event_msg.device_name())
-                                .body(body.as_str())
-                                .priority(Priority::High)
-                                .display_hint([DisplayHint::ShowAsNew])
-                                .default_action("open-folder")
-                                .default_action_target(target.as_str())
-                                .button(
-                                    ashpd::desktop::notification::Button::new(&gettext("Open"), "open-folder")
-                                        .target(target.as_str())
-                                )
-                        );
-                        let toast = adw::Toast::builder()
-                            .title(&body)
-                            .button_label(&gettext("Open"))
-                            .action_name("win.received-files")
-                            .priority(adw::ToastPriority::High)
-                            .build();
-                        win.imp().toast_overlay.add_toast(toast);
-                    }
-                }
+/// Removes or keeps whatever a receive that didn't finish wrote to the
+/// download folder, as per the `keep-partial-files` setting.
+fn clean_up_partial_files(win: &PacketApplicationWindow, files: Option<JoinHandle<ReceivedFiles>>) {
+    let Some(files) = files else {
+        return;
+    };
+    let keep = win.imp().settings.boolean("keep-partial-files");
+
+    glib::spawn_future_local(clone!(
+        #[weak]
+        win,
+        async move {
+            let Ok(files) = files.await else {
+                return;
+            };
+            let Ok(report) = tokio_runtime()
+                .spawn_blocking(move || partial_files::clean_up(&files, keep))
+                .await
+            else {
+                return;
+            };
+
+            if !report.kept.is_empty() {
+                present_kept_files_dialog(&win, &report.kept, keep);
+            } else if !report.removed.is_empty() {
+                win.add_toast(
+                    &formatx!(
+                        ngettext(
+                            "Removed {} partially received file",
+                            "Removed {} partially received files",
+                            report.removed.len() as u32
+                        ),
+                        report.removed.len()
+                    )
+                    .unwrap_or_else(|_| "badly formatted locale string".into()),
+                );
             }
         }
     ));
-    receive_state.notify_event();
+}
+
+fn present_kept_files_dialog(win: &PacketApplicationWindow, kept: &[PathBuf], keep: bool) {
+    let body = if keep {
+        ngettext(
+            "This file was only partially received, and was kept with .partial added to its name",
+            "These files were only partially received, and were kept with .partial added to their names",
+            kept.len() as u32,
+        )
+    } else {
+        ngettext(
+            "This partially received file couldn't be removed",
+            "These partially received files couldn't be removed",
+            kept.len() as u32,
+        )
+    };
+    let file_list = kept
+        .iter()
+        .map(|it| it.file_name().unwrap_or_default().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let dialog = adw::AlertDialog::builder()
+        .heading(gettext("Partial Files Kept"))
+        .body(format!("{body}\n\n{file_list}"))
+        .build();
+    dialog.add_responses(&[
+        ("close", &gettext("Close")),
+        ("open", &gettext("Open Folder")),
+    ]);
+    dialog.set_response_appearance("open", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("open"));
+    dialog.set_close_response("close");
+    dialog.connect_response(
+        Some("open"),
+        clone!(
+            #[weak]
+            win,
+            move |_, _| {
+                _ = WidgetExt::activate_action(&win, "win.received-files", None);
+            }
+        ),
+    );
+
+    dialog.present(Some(win));
 }
@@ -1,6 +1,7 @@
 use crate::{
     objects::{self, TransferState, send_transfer::SendRequestState},
-    tokio_runtime,
+    transfer_flow::{CardResult, RecipientCardView},
+    widgets::{cancel_send, enqueue_send},
     window::PacketApplicationWindow,
 };
 
@@ -9,7 +10,6 @@ use adw::subclass::prelude::*;
 use formatx::formatx;
 use gettextrs::{gettext, ngettext};
 use gtk::{gio, glib, glib::clone};
-use rqs_lib::channel::{ChannelMessage, MessageClient};
 
 fn get_model_item_from_listbox<T>(
     model: &gio::ListStore,
@@ -64,61 +64,17 @@ pub fn handle_recipient_card_clicked(
         get_model_item_from_listbox::<SendRequestState>(&imp.recipient_model, list_box, row)
             .unwrap();
 
-    emit_send_files(win, &model_item);
+    if imp.recipients_selection_button.is_active() {
+        model_item.set_selected(!model_item.selected());
+        return;
+    }
+
+    enqueue_send(win, &model_item);
 
     // Only reset this on Cancelled
     row.set_activatable(false);
 }
 
-fn emit_send_files(win: &PacketApplicationWindow, model_item: &SendRequestState) {
-    let imp = win.imp();
-
-    let endpoint_info = model_item.endpoint_info();
-    let files_to_send = model_item.imp().files.borrow().clone();
-
-    // Only one transfer at a time is supported by the protocol
-    // Whether it be receiving or sending
-    let will_be_queued = imp
-        .recipient_model
-        .iter::<SendRequestState>()
-        .filter_map(|it| it.ok())
-        .find(|it| match it.transfer_state() {
-            TransferState::RequestedForConsent | TransferState::OngoingTransfer => true,
-            _ => false,
-        })
-        .is_some();
-    if will_be_queued {
-        model_item.set_transfer_state(TransferState::Queued);
-    }
-
-    tokio_runtime().spawn(clone!(
-        #[weak(rename_to = file_sender)]
-        imp.file_sender,
-        async move {
-            file_sender
-                .lock()
-                .await
-                .as_mut()
-                .unwrap()
-                .send(rqs_lib::SendInfo {
-                    id: endpoint_info.id.clone(),
-                    name: endpoint_info
-                        .name
-                        .clone()
-                        .unwrap_or(gettext("Unknown device")),
-                    addr: format!(
-                        "{}:{}",
-                        endpoint_info.ip.clone().unwrap_or_default(),
-                        endpoint_info.port.clone().unwrap_or_default()
-                    ),
-                    ob: rqs_lib::OutboundPayload::Files(files_to_send),
-                })
-                .await
-                .unwrap();
-        }
-    ));
-}
-
 pub fn create_recipient_card(
     win: &PacketApplicationWindow,
     _model: &gio::ListStore,
@@ -183,6 +139,28 @@ pub fn create_recipient_card(
         .build();
     let root_bin = adw::Bin::builder().child(&root_box).build();
 
+    let select_check_button = gtk::CheckButton::builder()
+        .valign(gtk::Align::Center)
+        .css_classes(["selection-mode"])
+        .build();
+    imp.recipients_selection_button
+        .bind_property("active", &select_check_button, "visible")
+        .sync_create()
+        .build();
+    model_item
+        .bind_property("selected", &select_check_button, "active")
+        .sync_create()
+        .bidirectional()
+        .build();
+    model_item.connect_selected_notify(clone!(
+        #[weak]
+        win,
+        move |_| {
+            win.update_send_to_selected_button();
+        }
+    ));
+    root_box.append(&select_check_button);
+
     let device_avatar = adw::Avatar::builder().show_initials(true).size(48).build();
     model_item
         .bind_property("device-name", &device_avatar, "text")
@@ -233,41 +211,6 @@ pub fn create_recipient_card(
     main_box.append(&unavailibility_label);
     main_box.append(&pincode_label);
 
-    model_item.connect_transfer_state_notify(clone!(
-        #[weak]
-        imp,
-        #[weak]
-        result_label,
-        move |model_item| {
-            if model_item.transfer_state() == TransferState::Queued {
-                result_label.set_visible(true);
-                result_label.set_label(&gettext("Queued"));
-                result_label.set_css_classes(&[]);
-            };
-
-            // Prevent exiting the recipients view until all transfers
-            // are settled
-            let is_transfer_active = imp
-                .recipient_model
-                .iter::<SendRequestState>()
-                .filter_map(|it| it.ok())
-                .find(|it| match it.transfer_state() {
-                    TransferState::Queued
-                    | TransferState::RequestedForConsent
-                    | TransferState::OngoingTransfer => true,
-                    TransferState::AwaitingConsentOrIdle
-                    | TransferState::Failed
-                    | TransferState::Done => false,
-                })
-                .is_some();
-            if is_transfer_active {
-                imp.select_recipients_dialog.set_can_close(false);
-            } else {
-                imp.select_recipients_dialog.set_can_close(true);
-            }
-        }
-    ));
-
     let progress_bar = gtk::ProgressBar::builder().visible(false).build();
     main_box.append(&progress_bar);
 
@@ -279,8 +222,6 @@ pub fn create_recipient_card(
         .build();
     main_box.append(&eta_label);
 
-    let id = model_item.endpoint_info().id.clone();
-
     root_box.append(&adw::Bin::builder().hexpand(true).build());
 
     let retry_button = gtk::Button::builder()
@@ -298,7 +239,7 @@ pub fn create_recipient_card(
         #[weak]
         model_item,
         move |_button| {
-            emit_send_files(&imp.obj(), &model_item);
+            enqueue_send(&imp.obj(), &model_item);
         }
     ));
 
@@ -313,33 +254,91 @@ pub fn create_recipient_card(
     root_box.append(&cancel_transfer_button);
 
     cancel_transfer_button.connect_clicked(clone!(
-        #[weak(rename_to = rqs)]
-        imp.rqs,
-        #[strong]
-        id,
+        #[weak]
+        win,
+        #[weak]
+        model_item,
         move |_button| {
-            let mut guard = rqs.blocking_lock();
-            if let Some(rqs) = guard.as_mut() {
-                _ = rqs
-                    .message_sender
-                    .send(ChannelMessage {
-                        id: id.clone(),
-                        msg: rqs_lib::channel::Message::Lib {
-                            action: rqs_lib::channel::TransferAction::TransferCancel,
-                        },
-                    })
-                    .inspect_err(|err| tracing::error!(%err));
-            }
+            cancel_send(&win, &model_item);
         }
     ));
 
-    fn set_progress_bar_fraction(progress_bar: &gtk::ProgressBar, client_msg: &MessageClient) {
-        if let Some(metadata) = &client_msg.metadata {
-            if metadata.total_bytes > 0 {
-                progress_bar.set_fraction(metadata.ack_bytes as f64 / metadata.total_bytes as f64);
+    model_item.connect_transfer_state_notify(clone!(
+        #[weak]
+        imp,
+        #[weak]
+        result_label,
+        #[weak]
+        retry_button,
+        #[weak]
+        cancel_transfer_button,
+        move |model_item| {
+            match model_item.transfer_state() {
+                TransferState::Queued => {
+                    result_label.set_visible(true);
+                    result_label.set_label(&gettext("Queued"));
+                    result_label.set_css_classes(&[]);
+
+                    retry_button.set_visible(false);
+                    cancel_transfer_button.set_sensitive(true);
+                    cancel_transfer_button.set_visible(true);
+                }
+                TransferState::Retrying => {
+                    result_label.set_visible(true);
+                    result_label.set_label(&gettext("Connection lost, retrying soon"));
+                    result_label.set_css_classes(&["warning"]);
+
+                    retry_button.set_visible(false);
+                    cancel_transfer_button.set_sensitive(true);
+                    cancel_transfer_button.set_visible(true);
+                }
+                TransferState::RequestedForConsent => {
+                    result_label.set_visible(true);
+                    result_label.set_label(&gettext("Requested"));
+                    result_label.set_css_classes(&["accent"]);
+
+                    retry_button.set_visible(false);
+                    cancel_transfer_button.set_sensitive(true);
+                    cancel_transfer_button.set_visible(true);
+                }
+                TransferState::AwaitingConsentOrIdle if model_item.event().is_none() => {
+                    // Removed from the queue before it was ever sent
+                    result_label.set_visible(false);
+                    cancel_transfer_button.set_visible(false);
+
+                    let listbox_row = get_listbox_row_from_model_item::<SendRequestState>(
+                        &imp.recipient_model,
+                        &imp.recipient_listbox,
+                        model_item,
+                    );
+                    set_row_activatable(model_item, listbox_row.as_ref(), true);
+                }
+                _ => {}
+            };
+
+            // Prevent exiting the recipients view until all transfers
+            // are settled
+            let is_transfer_active = imp
+                .recipient_model
+                .iter::<SendRequestState>()
+                .filter_map(|it| it.ok())
+                .find(|it| match it.transfer_state() {
+                    TransferState::Queued
+                    | TransferState::Retrying
+                    | TransferState::RequestedForConsent
+                    | TransferState::OngoingTransfer => true,
+                    TransferState::AwaitingConsentOrIdle
+                    | TransferState::Failed
+                    | TransferState::Done => false,
+                })
+                .is_some();
+            if is_transfer_active {
+                imp.select_recipients_dialog.set_can_close(false);
+            } else {
+                imp.select_recipients_dialog.set_can_close(true);
             }
         }
-    }
+    ));
 
     fn set_row_activatable(
         model_item: &SendRequestState,
@@ -362,6 +361,8 @@ pub fn create_recipient_card(
         retry_button,
         #[weak]
         unavailibility_label,
+        #[weak]
+        select_check_button,
         move |model_item| {
             let imp = win.imp();
             let is_idle_card = model_item.transfer_state() == TransferState::AwaitingConsentOrIdle;
@@ -377,8 +378,12 @@ pub fn create_recipient_card(
             if endpoint_info.present.is_none() {
                 retry_button.set_sensitive(false);
                 unavailibility_label.set_visible(is_idle_card);
+
+                model_item.set_selected(false);
+                select_check_button.set_sensitive(false);
             } else {
                 retry_button.set_sensitive(true);
+                select_check_button.set_sensitive(true);
                 unavailibility_label.set_visible(false);
 
                 // Update device name on re-connection
@@ -395,161 +400,106 @@ pub fn create_recipient_card(
         #[weak]
         imp,
         move |model_item| {
-            use rqs_lib::TransferState as RqsState;
-
             let eta_estimator = model_item.imp().eta.as_ref();
 
             if let Some(event_msg) = model_item.event() {
                 let client_msg = event_msg.msg.as_client().unwrap();
-                let state = client_msg.state.as_ref().unwrap_or(&RqsState::Initial);
-
-                match state {
-                    RqsState::Initial => {}
-                    RqsState::ReceivedConnectionRequest => {}
-                    RqsState::SentUkeyServerInit => {}
-                    RqsState::SentPairedKeyEncryption => {}
-                    RqsState::ReceivedUkeyClientFinish => {}
-                    RqsState::SentConnectionResponse => {}
-                    RqsState::SentPairedKeyResult => {}
-                    RqsState::ReceivedPairedKeyResult => {}
-                    RqsState::WaitingForUserConsent => {}
-                    RqsState::ReceivingFiles => {}
-                    RqsState::SentUkeyClientInit
-                    | RqsState::SentUkeyClientFinish
-                    | RqsState::SentIntroduction => {
-                        model_item.set_transfer_state(TransferState::RequestedForConsent);
-
-                        let listbox_row = get_listbox_row_from_model_item::<SendRequestState>(
-                            &imp.recipient_model,
-                            &imp.recipient_listbox,
-                            model_item,
-                        );
-                        set_row_activatable(model_item, listbox_row.as_ref(), false);
 
-                        unavailibility_label.set_visible(false);
-                        retry_button.set_visible(false);
+                model_item.update_from_outbound(client_msg);
 
-                        cancel_transfer_button.set_sensitive(true);
-                        cancel_transfer_button.set_visible(true);
+                let Some(view) = RecipientCardView::from_outbound(
+                    client_msg,
+                    model_item.endpoint_info().present.is_some(),
+                ) else {
+                    return;
+                };
 
+                if let Some(activatable) = view.is_activatable {
+                    let listbox_row = get_listbox_row_from_model_item::<SendRequestState>(
+                        &imp.recipient_model,
+                        &imp.recipient_listbox,
+                        model_item,
+                    );
+                    set_row_activatable(model_item, listbox_row.as_ref(), activatable);
+                }
+
+                if view.is_new_transfer {
+                    eta_estimator.borrow_mut().prepare_for_new_transfer(None);
+                }
+
+                unavailibility_label.set_visible(view.is_unavailable);
+                retry_button.set_visible(view.can_retry);
+                cancel_transfer_button.set_sensitive(view.can_cancel);
+                cancel_transfer_button.set_visible(view.can_cancel);
+
+                match view.result {
+                    Some(result) => {
+                        let (label, css_class) = match result {
+                            CardResult::Requested => (gettext("Requested"), "accent"),
+                            CardResult::Failed => (gettext("Failed"), "error"),
+                            CardResult::Sent => {
+                                let file_count = model_item.imp().files.borrow().len();
+                                (
+                                    formatx!(
+                                        ngettext(
+                                            "Sent {} file",
+                                            "Sent {} files",
+                                            file_count as u32
+                                        ),
+                                        file_count
+                                    )
+                                    .unwrap_or_else(|_| "badly formatted locale string".into()),
+                                    "accent",
+                                )
+                            }
+                        };
                         result_label.set_visible(true);
-                        result_label.set_label(&gettext("Requested"));
-                        result_label.set_css_classes(&["accent"]);
+                        result_label.set_label(&label);
+                        result_label.set_css_classes(&[css_class]);
+                    }
+                    None => result_label.set_visible(false),
+                }
 
+                match &view.pin_code {
+                    Some(pin_code) => {
                         pincode_label.set_visible(true);
                         pincode_label.set_label(
-                            &formatx!(
-                                gettext("Code: {}"),
-                                client_msg
-                                    .metadata
-                                    .as_ref()
-                                    .unwrap()
-                                    .pin_code
-                                    .clone()
-                                    .unwrap()
-                            )
-                            .unwrap_or_else(|_| "badly formatted locale string".into()),
+                            &formatx!(gettext("Code: {}"), pin_code)
+                                .unwrap_or_else(|_| "badly formatted locale string".into()),
                         );
-
-                        eta_estimator.borrow_mut().prepare_for_new_transfer(None);
                     }
-                    RqsState::SendingFiles => {
-                        model_item.set_transfer_state(TransferState::OngoingTransfer);
-
-                        cancel_transfer_button.set_visible(true);
-                        result_label.set_visible(false);
-                        unavailibility_label.set_visible(false);
-                        pincode_label.set_visible(false);
-                        retry_button.set_visible(false);
-
-                        let eta_text = {
-                            if let Some(metadata) = &client_msg.metadata {
-                                eta_estimator
-                                    .borrow_mut()
-                                    .step_with(metadata.ack_bytes as usize);
-                            }
-
-                            formatx!(
-                                gettext("About {} left"),
-                                eta_estimator.borrow().get_estimate_string().trim()
-                            )
-                            .unwrap_or_else(|_| "badly formatted locale string".into())
-                        };
+                    None => pincode_label.set_visible(false),
+                }
+
+                match view.progress {
+                    Some(fraction) => {
+                        if let Some(metadata) = &client_msg.metadata {
+                            eta_estimator
+                                .borrow_mut()
+                                .step_with(metadata.ack_bytes as usize);
+                        }
+                        let eta_text = formatx!(
+                            gettext("About {} left"),
+                            eta_estimator.borrow().get_estimate_string().trim()
+                        )
+                        .unwrap_or_else(|_| "badly formatted locale string".into());
                         eta_label.set_visible(true);
                         eta_label.set_label(&eta_text);
 
                         progress_bar.set_visible(true);
-                        set_progress_bar_fraction(&progress_bar, &client_msg);
+                        if let Some(fraction) = fraction {
+                            progress_bar.set_fraction(fraction);
+                        }
                     }
-                    RqsState::Disconnected => {
-                        model_item.set_transfer_state(TransferState::Failed);
-                        // FIXME: Wait for 5~10 seconds after a send and timeout
-                        // if did not receive SendingFiles within that timeframe
-                        // This is how google does it in their client
-
-                        progress_bar.set_visible(false);
-                        cancel_transfer_button.set_visible(false);
+                    None => {
                         eta_label.set_visible(false);
-                        unavailibility_label.set_visible(false);
-                        pincode_label.set_visible(false);
-
-                        retry_button.set_visible(true);
-
-                        result_label.set_visible(true);
-                        result_label.set_label(&gettext("Failed"));
-                        result_label.set_css_classes(&["error"]);
-                    }
-                    RqsState::Rejected => {
-                        model_item.set_transfer_state(TransferState::Failed);
-                        // Outbound(Reject) is not handled on lib side
-                        // rqs_lib::hdl::outbound: Cannot process: consent denied: Reject
-                    }
-                    RqsState::Cancelled => {
-                        model_item.set_transfer_state(TransferState::AwaitingConsentOrIdle);
-
-                        let listbox_row = get_listbox_row_from_model_item::<SendRequestState>(
-                            &imp.recipient_model,
-                            &imp.recipient_listbox,
-                            model_item,
-                        );
-                        set_row_activatable(model_item, listbox_row.as_ref(), true);
-
                         progress_bar.set_visible(false);
-                        cancel_transfer_button.set_visible(false);
-                        eta_label.set_visible(false);
-                        result_label.set_visible(false);
-                        retry_button.set_visible(false);
-                        pincode_label.set_visible(false);
-
-                        unavailibility_label
-                            .set_visible(model_item.endpoint_info().present.is_none());
-
-                        model_item.set_event(None::<objects::ChannelMessage>);
                     }
-                    RqsState::Finished => {
-                        model_item.set_transfer_state(TransferState::Done);
+                }
 
-                        cancel_transfer_button.set_visible(false);
-                        progress_bar.set_visible(false);
-                        eta_label.set_visible(false);
-                        retry_button.set_visible(false);
-                        unavailibility_label.set_visible(false);
-                        pincode_label.set_visible(false);
-
-                        let finished_text = {
-                            let file_count = model_item.imp().files.borrow().len();
-                            formatx!(
-                                ngettext("Sent {} file", "Sent {} files", file_count as u32),
-                                file_count
-                            )
-                            .unwrap_or_else(|_| "badly formatted locale string".into())
-                        };
-
-                        result_label.set_visible(true);
-                        result_label.set_label(&finished_text);
-                        result_label.set_css_classes(&["accent"]);
-                    }
-                };
+                if view.is_reset {
+                    model_item.set_event(None::<objects::ChannelMessage>);
+                }
             }
         }
     ));
@@ -0,0 +1,497 @@
+use std::cell::RefCell;
+
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use formatx::formatx;
+use gettextrs::{gettext, ngettext};
+use gtk::glib::{self, clone};
+
+use crate::{
+    objects::{
+        self, MAX_RETRY_ATTEMPTS, SendOutcome, SendRequestState, TransferState, retry_delay,
+    },
+    service::ServiceCommand,
+    window::PacketApplicationWindow,
+};
+
+/// Queues the recipient card's files to be sent. They're sent right away if
+/// nothing else is being sent.
+pub fn enqueue_send(win: &PacketApplicationWindow, model_item: &SendRequestState) {
+    // Started by the user, so it gets a fresh set of retries
+    win.imp().send_queue.reset_retries(model_item);
+    push(win, model_item);
+}
+
+/// Queues the files to be sent to each of the recipients, one after the other,
+/// and summarizes how it went once they're all done.
+pub fn enqueue_batch_send(win: &PacketApplicationWindow, model_items: Vec<SendRequestState>) {
+    tracing::info!(count = model_items.len(), "Sending to multiple recipients");
+
+    for model_item in &model_items {
+        enqueue_send(win, model_item);
+    }
+    win.imp().send_queue.add_batch(model_items);
+}
+
+/// Cancels a send, whether it's still waiting in the queue or already active.
+pub fn cancel_send(win: &PacketApplicationWindow, model_item: &SendRequestState) {
+    let queue = &win.imp().send_queue;
+
+    if queue.remove_pending(model_item) {
+        tracing::info!(endpoint_info = %model_item.endpoint_info(), "Removed send from queue");
+        // Same as what's done on a cancelled transfer, for the recipient card
+        model_item.set_event(None::<objects::ChannelMessage>);
+        model_item.set_transfer_state(TransferState::AwaitingConsentOrIdle);
+    } else {
+        win.send_service_command(ServiceCommand::TransferAction {
+            id: model_item.endpoint_info().id.clone(),
+            action: rqs_lib::channel::TransferAction::TransferCancel,
+        });
+    }
+}
+
+fn push(win: &PacketApplicationWindow, model_item: &SendRequestState) {
+    let queue = &win.imp().send_queue;
+    if queue.is_active(model_item) {
+        tracing::debug!(endpoint_info = %model_item.endpoint_info(), "Send is already active");
+        return;
+    }
+
+    if !queue.state_handlers.borrow().contains_key(model_item) {
+        let handler_id = model_item.connect_transfer_state_notify(clone!(
+            #[weak]
+            win,
+            move |model_item| {
+                // Let the recipient card finish handling the event first
+                glib::idle_add_local_once(clone!(
+                    #[weak]
+                    win,
+                    #[weak]
+                    model_item,
+                    move || handle_state_change(&win, &model_item)
+                ));
+            }
+        ));
+        queue
+            .state_handlers
+            .borrow_mut()
+            .insert(model_item.clone(), handler_id);
+    }
+
+    queue.push(model_item);
+    start_next(win);
+}
+
+/// Starts the next queued send, e.g. once a deferred send is done.
+pub fn resume_send_queue(win: &PacketApplicationWindow) {
+    start_next(win);
+}
+
+fn start_next(win: &PacketApplicationWindow) {
+    // Only one transfer at a time is supported by the protocol
+    if super::is_deferred_send_active(win) {
+        return;
+    }
+
+    if let Some(model_item) = win.imp().send_queue.start_next() {
+        tracing::info!(endpoint_info = %model_item.endpoint_info(), "Starting queued send");
+
+        model_item.imp().outbound_progress.take();
+        model_item.set_transfer_state(TransferState::RequestedForConsent);
+        super::remember_last_used_device(win, &model_item.endpoint_info());
+        win.send_service_command(ServiceCommand::Send(model_item.send_info()));
+    }
+}
+
+fn handle_state_change(win: &PacketApplicationWindow, model_item: &SendRequestState) {
+    let queue = &win.imp().send_queue;
+    if !queue.contains(model_item) {
+        return;
+    }
+
+    match model_item.transfer_state() {
+        TransferState::Queued
+        | TransferState::Retrying
+        | TransferState::RequestedForConsent
+        | TransferState::OngoingTransfer => return,
+        TransferState::Done => {
+            queue.reset_retries(model_item);
+            queue.finish_active(model_item);
+        }
+        TransferState::Failed => {
+            queue.finish_active(model_item);
+
+            // Not after a decline, which also shows up as a disconnect
+            if model_item
+                .imp()
+                .outbound_progress
+                .get()
+                .is_connection_lost()
+            {
+                schedule_retry(win, model_item);
+            }
+        }
+        TransferState::AwaitingConsentOrIdle => {
+            queue.finish_active(model_item);
+        }
+    };
+
+    start_next(win);
+
+    for batch in queue.take_finished_batches() {
+        present_batch_summary(win, &batch);
+    }
+}
+
+fn present_batch_summary(win: &PacketApplicationWindow, model_items: &[SendRequestState]) {
+    let outcomes = model_items
+        .iter()
+        .filter_map(|it| SendOutcome::of(it).map(|outcome| (it, outcome)))
+        .collect::<Vec<_>>();
+    let sent_count = outcomes
+        .iter()
+        .filter(|(_, outcome)| *outcome == SendOutcome::Sent)
+        .count();
+
+    let heading = if sent_count == outcomes.len() {
+        formatx!(
+            ngettext("Sent to {} Device", "Sent to {} Devices", sent_count as u32),
+            sent_count
+        )
+    } else {
+        formatx!(
+            ngettext(
+                // Translators: e.g. "Sent to 3 of 5 Devices"
+                "Sent to {} of {} Device",
+                "Sent to {} of {} Devices",
+                outcomes.len() as u32
+            ),
+            sent_count,
+            outcomes.len()
+        )
+    }
+    .unwrap_or_else(|_| "badly formatted locale string".into());
+
+    let dialog = adw::AlertDialog::builder()
+        .heading(&heading)
+        .default_response("close")
+        .build();
+    dialog.add_response("close", &gettext("Close"));
+    dialog.set_response_appearance("close", adw::ResponseAppearance::Suggested);
+
+    let listbox = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    dialog.set_extra_child(Some(&listbox));
+
+    for (model_item, outcome) in outcomes {
+        let (subtitle, icon_name, css_class) = match outcome {
+            SendOutcome::Sent => (gettext("Sent"), "emblem-ok-symbolic", "success"),
+            SendOutcome::Declined => (
+                gettext("Declined"),
+                "action-unavailable-symbolic",
+                "warning",
+            ),
+            SendOutcome::Failed => (gettext("Failed"), "dialog-error-symbolic", "error"),
+            SendOutcome::Cancelled => (
+                gettext("Cancelled"),
+                "action-unavailable-symbolic",
+                "dimmed",
+            ),
+        };
+
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&model_item.device_name()))
+            .subtitle(subtitle)
+            .build();
+        row.add_suffix(
+            &gtk::Image::builder()
+                .icon_name(icon_name)
+                .css_classes([css_class])
+                .build(),
+        );
+        listbox.append(&row);
+    }
+
+    dialog.present(Some(win));
+}
+
+fn schedule_retry(win: &PacketApplicationWindow, model_item: &SendRequestState) {
+    let queue = &win.imp().send_queue;
+
+    let attempt = queue.retry_attempts(model_item) + 1;
+    if attempt > MAX_RETRY_ATTEMPTS {
+        tracing::info!(
+            endpoint_info = %model_item.endpoint_info(),
+            "Giving up on send after {MAX_RETRY_ATTEMPTS} retries"
+        );
+        return;
+    }
+
+    let delay = retry_delay(attempt);
+    tracing::info!(
+        endpoint_info = %model_item.endpoint_info(),
+        attempt,
+        ?delay,
+        "Send was disconnected, retrying"
+    );
+
+    model_item.set_transfer_state(TransferState::Retrying);
+    let source_id = glib::timeout_add_local_once(
+        delay,
+        clone!(
+            #[weak]
+            win,
+            #[weak]
+            model_item,
+            move || {
+                win.imp().send_queue.take_retry_source(&model_item);
+                if model_item.transfer_state() == TransferState::Retrying {
+                    push(&win, &model_item);
+                }
+            }
+        ),
+    );
+    queue.set_retry(model_item, source_id);
+}
+
+fn state_description(win: &PacketApplicationWindow, model_item: &SendRequestState) -> String {
+    match model_item.transfer_state() {
+        TransferState::Queued => gettext("Queued"),
+        TransferState::Retrying => formatx!(
+            gettext(
+                // Translators: e.g. "Connection lost, retrying (1 of 3)"
+                "Connection lost, retrying ({} of {})"
+            ),
+            win.imp().send_queue.retry_attempts(model_item),
+            MAX_RETRY_ATTEMPTS
+        )
+        .unwrap_or_else(|_| "badly formatted locale string".into()),
+        TransferState::RequestedForConsent => gettext("Waiting for the recipient to accept"),
+        TransferState::OngoingTransfer => gettext("Sending"),
+        TransferState::Failed => gettext("Failed"),
+        TransferState::Done => gettext("Sent"),
+        TransferState::AwaitingConsentOrIdle => gettext("Cancelled"),
+    }
+}
+
+/// Disconnects the handler once the widget is gone, since the model items
+/// outlive the dialog.
+fn disconnect_on_destroy(
+    widget: &impl IsA<gtk::Widget>,
+    obj: &glib::Object,
+    handler_id: glib::SignalHandlerId,
+) {
+    let handler_id = RefCell::new(Some(handler_id));
+    widget.connect_destroy(clone!(
+        #[weak]
+        obj,
+        move |_| {
+            if let Some(handler_id) = handler_id.take() {
+                obj.disconnect(handler_id);
+            }
+        }
+    ));
+}
+
+fn create_queue_row(
+    win: &PacketApplicationWindow,
+    model_item: &SendRequestState,
+) -> adw::ActionRow {
+    let queue = &win.imp().send_queue;
+
+    let row = adw::ActionRow::builder().build();
+    model_item
+        .bind_property("device-name", &row, "title")
+        .sync_create()
+        .build();
+
+    let move_up_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("go-up-symbolic")
+        .tooltip_text(gettext("Move Up"))
+        .css_classes(["flat"])
+        .build();
+    let move_down_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("go-down-symbolic")
+        .tooltip_text(gettext("Move Down"))
+        .css_classes(["flat"])
+        .build();
+    let cancel_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("cross-large-symbolic")
+        .tooltip_text(gettext("Cancel"))
+        .css_classes(["flat"])
+        .build();
+    row.add_suffix(&move_up_button);
+    row.add_suffix(&move_down_button);
+    row.add_suffix(&cancel_button);
+
+    for (button, offset) in [(&move_up_button, -1), (&move_down_button, 1)] {
+        button.connect_clicked(clone!(
+            #[weak]
+            win,
+            #[weak]
+            model_item,
+            move |_| {
+                win.imp().send_queue.move_pending(&model_item, offset);
+            }
+        ));
+    }
+    cancel_button.connect_clicked(clone!(
+        #[weak]
+        win,
+        #[weak]
+        model_item,
+        move |_| {
+            cancel_send(&win, &model_item);
+        }
+    ));
+
+    let update_row = clone!(
+        #[weak]
+        win,
+        #[weak]
+        row,
+        #[weak]
+        move_up_button,
+        #[weak]
+        move_down_button,
+        #[weak]
+        cancel_button,
+        move |model_item: &SendRequestState| {
+            let queue = &win.imp().send_queue;
+
+            row.set_subtitle(&state_description(&win, model_item));
+
+            let is_pending = queue.is_pending(model_item);
+            move_up_button.set_visible(is_pending);
+            move_up_button.set_sensitive(queue.can_move(model_item, -1));
+            move_down_button.set_visible(is_pending);
+            move_down_button.set_sensitive(queue.can_move(model_item, 1));
+
+            cancel_button.set_visible(matches!(
+                model_item.transfer_state(),
+                TransferState::Queued
+                    | TransferState::Retrying
+                    | TransferState::RequestedForConsent
+                    | TransferState::OngoingTransfer
+            ));
+        }
+    );
+    update_row(model_item);
+
+    let handler_id = model_item.connect_transfer_state_notify(clone!(
+        #[strong]
+        update_row,
+        move |model_item| update_row(model_item)
+    ));
+    disconnect_on_destroy(&row, model_item.upcast_ref(), handler_id);
+
+    // Neighbours can be moved around without this row being recreated
+    let handler_id = queue.model.connect_items_changed(clone!(
+        #[weak]
+        model_item,
+        move |_, _, _, _| update_row(&model_item)
+    ));
+    disconnect_on_destroy(&row, queue.model.upcast_ref(), handler_id);
+
+    row
+}
+
+pub fn present_send_queue_dialog(win: &PacketApplicationWindow) {
+    let queue = &win.imp().send_queue;
+
+    let dialog = adw::Dialog::builder()
+        .title(gettext("Send Queue"))
+        .content_width(400)
+        .content_height(480)
+        .build();
+
+    let toolbar_view = adw::ToolbarView::builder().build();
+    dialog.set_child(Some(&toolbar_view));
+
+    let header_bar = adw::HeaderBar::builder().build();
+    toolbar_view.add_top_bar(&header_bar);
+
+    let clear_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("edit-clear-all-symbolic")
+        .tooltip_text(gettext("Clear Finished"))
+        .css_classes(["flat"])
+        .build();
+    header_bar.pack_start(&clear_button);
+    clear_button.connect_clicked(clone!(
+        #[weak]
+        win,
+        move |_| {
+            win.imp().send_queue.clear_finished();
+        }
+    ));
+
+    let stack = gtk::Stack::new();
+    toolbar_view.set_content(Some(&stack));
+
+    let empty_page = adw::StatusPage::builder()
+        .icon_name("view-list-bullet-symbolic")
+        .title(gettext("Nothing Queued"))
+        .description(gettext(
+            "Files you send to nearby devices will show up here",
+        ))
+        .build();
+    stack.add_named(&empty_page, Some("empty"));
+
+    let listbox = gtk::ListBox::builder()
+        .valign(gtk::Align::Start)
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    let clamp = adw::Clamp::builder()
+        .maximum_size(550)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .child(&listbox)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .child(&clamp)
+        .build();
+    stack.add_named(&scrolled_window, Some("queue"));
+
+    listbox.bind_model(
+        Some(&queue.model),
+        clone!(
+            #[weak]
+            win,
+            #[upgrade_or]
+            adw::Bin::new().into(),
+            move |obj| {
+                let model_item = obj.downcast_ref::<SendRequestState>().unwrap();
+                create_queue_row(&win, model_item).into()
+            }
+        ),
+    );
+
+    let update_page = clone!(
+        #[weak]
+        stack,
+        #[weak]
+        clear_button,
+        move |model: &gtk::gio::ListStore| {
+            let is_empty = model.n_items() == 0;
+            stack.set_visible_child_name(if is_empty { "empty" } else { "queue" });
+            clear_button.set_sensitive(!is_empty);
+        }
+    );
+    update_page(&queue.model);
+    let handler_id = queue
+        .model
+        .connect_items_changed(move |model, _, _, _| update_page(model));
+    disconnect_on_destroy(&stack, queue.model.upcast_ref(), handler_id);
+
+    dialog.present(Some(win));
+}
@@ -0,0 +1,580 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use formatx::formatx;
+use gettextrs::gettext;
+use gtk::{
+    gio,
+    glib::{self, clone},
+};
+
+use crate::{
+    deferred::{DeferredSend, KnownDevice},
+    utils::{format_unix_timestamp, strip_user_home_prefix},
+    watch_folders::{SendLogStatus, SettlingFile, WatchFolder, WatchFolders, is_ignored_file},
+    window::PacketApplicationWindow,
+};
+
+/// How long a file has to go without changing before it's considered
+/// completely written.
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// Loads the watch folders and starts watching the ones that aren't paused.
+pub fn setup_watch_folders(win: &PacketApplicationWindow) {
+    let state = &win.imp().watch_folders;
+
+    state.folders.replace(WatchFolders::load());
+
+    let ids = state
+        .folders
+        .borrow()
+        .folders
+        .iter()
+        .filter(|it| !it.is_paused)
+        .map(|it| it.id.clone())
+        .collect::<Vec<_>>();
+    for id in ids {
+        start_watching(win, &id);
+    }
+}
+
+fn save(win: &PacketApplicationWindow) {
+    if let Err(err) = win.imp().watch_folders.folders.borrow().save() {
+        tracing::warn!(?err, "Failed to save watch folders");
+    }
+}
+
+fn start_watching(win: &PacketApplicationWindow, id: &str) {
+    let state = &win.imp().watch_folders;
+
+    let Some(path) = state.folders.borrow().get(id).map(|it| it.path.clone()) else {
+        return;
+    };
+
+    match gio::File::for_path(&path).monitor_directory(
+        gio::FileMonitorFlags::WATCH_MOVES,
+        None::<&gio::Cancellable>,
+    ) {
+        Ok(monitor) => {
+            tracing::info!(?path, "Watching folder");
+
+            let id = id.to_string();
+            monitor.connect_changed(clone!(
+                #[weak]
+                win,
+                #[strong]
+                id,
+                move |_, file, other_file, event| {
+                    handle_monitor_event(&win, &id, file, other_file, event);
+                }
+            ));
+            if let Some(old_monitor) = state.monitors.borrow_mut().insert(id, monitor) {
+                old_monitor.cancel();
+            }
+        }
+        Err(err) => {
+            tracing::warn!(?err, ?path, "Failed to watch folder");
+        }
+    }
+}
+
+fn stop_watching(win: &PacketApplicationWindow, id: &str) {
+    let state = &win.imp().watch_folders;
+
+    if let Some(monitor) = state.monitors.borrow_mut().remove(id) {
+        tracing::info!(id, "Stopped watching folder");
+        monitor.cancel();
+    }
+
+    // Files that haven't settled yet won't be sent either
+    let mut settling_files = state.settling_files.borrow_mut();
+    let paths = settling_files
+        .iter()
+        .filter(|(_, it)| it.watch_folder_id == id)
+        .map(|(path, _)| path.clone())
+        .collect::<Vec<_>>();
+    for path in paths {
+        if let Some(settling_file) = settling_files.remove(&path) {
+            settling_file.source_id.remove();
+        }
+    }
+}
+
+fn handle_monitor_event(
+    win: &PacketApplicationWindow,
+    id: &str,
+    file: &gio::File,
+    other_file: Option<&gio::File>,
+    event: gio::FileMonitorEvent,
+) {
+    let state = &win.imp().watch_folders;
+
+    let (path, is_new) = match event {
+        gio::FileMonitorEvent::Created | gio::FileMonitorEvent::MovedIn => (file.path(), true),
+        // e.g. `photo.jpg.part` being renamed to `photo.jpg` once downloaded
+        gio::FileMonitorEvent::Renamed => (other_file.and_then(|it| it.path()), true),
+        gio::FileMonitorEvent::Changed | gio::FileMonitorEvent::ChangesDoneHint => {
+            (file.path(), false)
+        }
+        _ => return,
+    };
+    let Some(path) = path else {
+        return;
+    };
+    if is_ignored_file(&path) {
+        return;
+    }
+
+    // Only new files are sent, changes just delay the ones that are still
+    // being written
+    let settling_file = state.settling_files.borrow_mut().remove(&path);
+    match settling_file {
+        Some(settling_file) => {
+            settling_file.source_id.remove();
+            schedule_settle_check(win, id, path, settling_file.size);
+        }
+        None if is_new => {
+            tracing::debug!(?path, "New file in watch folder");
+            schedule_settle_check(win, id, path, None);
+        }
+        None => {}
+    }
+}
+
+fn schedule_settle_check(
+    win: &PacketApplicationWindow,
+    id: &str,
+    path: PathBuf,
+    size: Option<i64>,
+) {
+    let source_id = glib::timeout_add_local_once(
+        SETTLE_DELAY,
+        clone!(
+            #[weak]
+            win,
+            #[strong]
+            path,
+            move || check_settled(&win, &path)
+        ),
+    );
+    win.imp().watch_folders.settling_files.borrow_mut().insert(
+        path,
+        SettlingFile {
+            watch_folder_id: id.to_string(),
+            source_id,
+            size,
+        },
+    );
+}
+
+/// Sends the file once its size has stopped changing between two checks.
+fn check_settled(win: &PacketApplicationWindow, path: &Path) {
+    // The source has already fired, so it's not to be removed
+    let Some(settling_file) = win
+        .imp()
+        .watch_folders
+        .settling_files
+        .borrow_mut()
+        .remove(path)
+    else {
+        return;
+    };
+
+    let info = match gio::File::for_path(path).query_info(
+        &format!(
+            "{},{}",
+            gio::FILE_ATTRIBUTE_STANDARD_TYPE,
+            gio::FILE_ATTRIBUTE_STANDARD_SIZE
+        ),
+        gio::FileQueryInfoFlags::NONE,
+        None::<&gio::Cancellable>,
+    ) {
+        Ok(info) => info,
+        Err(err) => {
+            // Most likely a temporary file that's gone already
+            tracing::debug!(?err, ?path, "Skipping file from watch folder");
+            return;
+        }
+    };
+    if info.file_type() != gio::FileType::Regular {
+        return;
+    }
+
+    let size = info.size();
+    if settling_file.size != Some(size) {
+        schedule_settle_check(win, &settling_file.watch_folder_id, path.into(), Some(size));
+        return;
+    }
+    if size == 0 {
+        tracing::debug!(?path, "Skipping empty file from watch folder");
+        return;
+    }
+
+    send_watched_file(win, &settling_file.watch_folder_id, path);
+}
+
+fn send_watched_file(win: &PacketApplicationWindow, id: &str, path: &Path) {
+    let state = &win.imp().watch_folders;
+
+    let device = match state.folders.borrow().get(id) {
+        Some(folder) if !folder.is_paused => folder.device.clone(),
+        _ => return,
+    };
+
+    let send_id = super::defer_watched_file(win, id, &device, path);
+    if let Some(folder) = state.folders.borrow_mut().get_mut(id) {
+        folder.log_file(
+            path.file_name()
+                .map(|it| it.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            send_id,
+        );
+    }
+    save(win);
+    refresh_watch_folders_dialog(win);
+}
+
+/// Updates the send log of the watch folder the deferred send came from.
+pub fn handle_watch_folder_send_finished(
+    win: &PacketApplicationWindow,
+    send: &DeferredSend,
+    is_sent: bool,
+) {
+    let Some(id) = &send.watch_folder else {
+        return;
+    };
+
+    let status = if is_sent {
+        SendLogStatus::Sent
+    } else {
+        SendLogStatus::Failed
+    };
+    if let Some(folder) = win.imp().watch_folders.folders.borrow_mut().get_mut(id) {
+        folder.set_send_status(&send.id, status);
+    }
+    save(win);
+    refresh_watch_folders_dialog(win);
+}
+
+/// Points the watch folders that send to the device at its new endpoint id.
+pub fn move_watch_folders_device(
+    win: &PacketApplicationWindow,
+    old_id: &str,
+    device: &KnownDevice,
+) {
+    {
+        let mut folders = win.imp().watch_folders.folders.borrow_mut();
+        for folder in folders
+            .folders
+            .iter_mut()
+            .filter(|it| it.device.id == old_id)
+        {
+            folder.device = device.clone();
+        }
+    }
+    save(win);
+    refresh_watch_folders_dialog(win);
+}
+
+fn set_paused(win: &PacketApplicationWindow, id: &str, is_paused: bool) {
+    {
+        let mut folders = win.imp().watch_folders.folders.borrow_mut();
+        let Some(folder) = folders.get_mut(id) else {
+            return;
+        };
+        if folder.is_paused == is_paused {
+            return;
+        }
+        folder.is_paused = is_paused;
+    }
+    save(win);
+
+    if is_paused {
+        stop_watching(win, id);
+    } else {
+        start_watching(win, id);
+    }
+    refresh_watch_folders_dialog(win);
+}
+
+fn remove_watch_folder(win: &PacketApplicationWindow, id: &str) {
+    stop_watching(win, id);
+    let folder = win.imp().watch_folders.folders.borrow_mut().remove(id);
+    if let Some(folder) = folder {
+        tracing::info!(path = ?folder.path, "Removed watch folder");
+    }
+    save(win);
+    refresh_watch_folders_dialog(win);
+}
+
+async fn add_watch_folder(win: &PacketApplicationWindow) {
+    let imp = win.imp();
+
+    let devices = imp.deferred_sends.borrow().devices_by_last_seen();
+    if devices.is_empty() {
+        let dialog = adw::AlertDialog::builder()
+            .heading(gettext("No Known Devices"))
+            .body(gettext(
+                "Look for nearby devices at least once, so that there's a device to send the files to",
+            ))
+            .default_response("close")
+            .build();
+        dialog.add_response("close", &gettext("Close"));
+        dialog.present(Some(win));
+        return;
+    }
+
+    let Ok(folder) = gtk::FileDialog::new().select_folder_future(Some(win)).await else {
+        return;
+    };
+    let Some(path) = folder.path() else {
+        return;
+    };
+
+    // Received files would be sent right back
+    let download_folder = PathBuf::from(imp.settings.string("download-folder").as_str());
+    if path.starts_with(&download_folder) {
+        win.add_toast(&gettext("The download folder can't be a watch folder"));
+        return;
+    }
+    if let Some(device) = choose_device(win, &path, &devices).await {
+        let folder = WatchFolder::new(path, device);
+        tracing::info!(path = ?folder.path, device = folder.device.name, "Added watch folder");
+
+        let id = folder.id.clone();
+        imp.watch_folders.folders.borrow_mut().folders.push(folder);
+        save(win);
+        start_watching(win, &id);
+        refresh_watch_folders_dialog(win);
+    }
+}
+
+async fn choose_device(
+    win: &PacketApplicationWindow,
+    path: &Path,
+    devices: &[KnownDevice],
+) -> Option<KnownDevice> {
+    let dialog = adw::AlertDialog::builder()
+        .heading(gettext("Send New Files To"))
+        .body(
+            formatx!(
+                gettext("New files in {} will be sent to this device automatically"),
+                strip_user_home_prefix(path).display().to_string()
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+        )
+        .default_response("add")
+        .close_response("cancel")
+        .build();
+    dialog.add_response("cancel", &gettext("Cancel"));
+    dialog.add_response("add", &gettext("Add"));
+    dialog.set_response_appearance("add", adw::ResponseAppearance::Suggested);
+
+    let device_row = adw::ComboRow::builder()
+        .title(gettext("Device"))
+        .model(&gtk::StringList::new(
+            &devices
+                .iter()
+                .map(|it| it.name.as_str())
+                .collect::<Vec<_>>(),
+        ))
+        .build();
+    let listbox = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    listbox.append(&device_row);
+    dialog.set_extra_child(Some(&listbox));
+
+    if dialog.choose_future(win).await != "add" {
+        return None;
+    }
+
+    devices.get(device_row.selected() as usize).cloned()
+}
+
+fn create_watch_folder_row(
+    win: &PacketApplicationWindow,
+    folder: &WatchFolder,
+) -> adw::ExpanderRow {
+    let row = adw::ExpanderRow::builder()
+        .name(&folder.id)
+        .title(glib::markup_escape_text(
+            &strip_user_home_prefix(&folder.path).display().to_string(),
+        ))
+        .subtitle(if folder.is_paused {
+            gettext("Paused")
+        } else {
+            formatx!(gettext("Sending new files to {}"), &folder.device.name)
+                .unwrap_or_else(|_| "badly formatted locale string".into())
+        })
+        .build();
+
+    let switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(!folder.is_paused)
+        .tooltip_text(gettext("Watch for New Files"))
+        .build();
+    let remove_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("user-trash-symbolic")
+        .tooltip_text(gettext("Remove"))
+        .css_classes(["flat"])
+        .build();
+    row.add_suffix(&switch);
+    row.add_suffix(&remove_button);
+
+    let id = folder.id.clone();
+    switch.connect_active_notify(clone!(
+        #[weak]
+        win,
+        #[strong]
+        id,
+        move |switch| {
+            set_paused(&win, &id, !switch.is_active());
+        }
+    ));
+    remove_button.connect_clicked(clone!(
+        #[weak]
+        win,
+        move |_| {
+            remove_watch_folder(&win, &id);
+        }
+    ));
+
+    if folder.log.is_empty() {
+        row.add_row(
+            &adw::ActionRow::builder()
+                .title(gettext("No files sent yet"))
+                .css_classes(["dimmed"])
+                .build(),
+        );
+    }
+    for entry in &folder.log {
+        let status = match entry.status {
+            SendLogStatus::Waiting => gettext("Waiting for the device"),
+            SendLogStatus::Sent => gettext("Sent"),
+            SendLogStatus::Failed => gettext("Failed"),
+        };
+        row.add_row(
+            &adw::ActionRow::builder()
+                .title(glib::markup_escape_text(&entry.file_name))
+                .subtitle(format!(
+                    "{status} · {}",
+                    format_unix_timestamp(entry.timestamp)
+                ))
+                .build(),
+        );
+    }
+
+    row
+}
+
+/// Rebuilds the rows of the watch folders dialog, if it's open.
+fn refresh_watch_folders_dialog(win: &PacketApplicationWindow) {
+    let state = &win.imp().watch_folders;
+    let Some(listbox) = state.dialog_listbox.upgrade() else {
+        return;
+    };
+
+    // Keep the logs that were open, open
+    let mut expanded_ids = vec![];
+    let mut child = listbox.first_child();
+    while let Some(widget) = child {
+        if let Some(row) = widget.downcast_ref::<adw::ExpanderRow>()
+            && row.is_expanded()
+        {
+            expanded_ids.push(row.widget_name());
+        }
+        child = widget.next_sibling();
+    }
+
+    listbox.remove_all();
+    for folder in state.folders.borrow().folders.iter() {
+        let row = create_watch_folder_row(win, folder);
+        row.set_expanded(expanded_ids.iter().any(|it| *it == folder.id));
+        listbox.append(&row);
+    }
+
+    if let Some(stack) = listbox
+        .ancestor(gtk::Stack::static_type())
+        .and_downcast::<gtk::Stack>()
+    {
+        stack.set_visible_child_name(if listbox.first_child().is_none() {
+            "empty"
+        } else {
+            "folders"
+        });
+    }
+}
+
+pub fn present_watch_folders_dialog(win: &PacketApplicationWindow) {
+    let dialog = adw::Dialog::builder()
+        .title(gettext("Watch Folders"))
+        .content_width(480)
+        .content_height(520)
+        .build();
+
+    let toolbar_view = adw::ToolbarView::builder().build();
+    dialog.set_child(Some(&toolbar_view));
+
+    let header_bar = adw::HeaderBar::builder().build();
+    toolbar_view.add_top_bar(&header_bar);
+
+    let add_button = gtk::Button::builder()
+        .valign(gtk::Align::Center)
+        .icon_name("list-add-symbolic")
+        .tooltip_text(gettext("Add Folder"))
+        .css_classes(["flat"])
+        .build();
+    header_bar.pack_start(&add_button);
+    add_button.connect_clicked(clone!(
+        #[weak]
+        win,
+        move |_| {
+            glib::spawn_future_local(async move {
+                add_watch_folder(&win).await;
+            });
+        }
+    ));
+
+    let stack = gtk::Stack::new();
+    toolbar_view.set_content(Some(&stack));
+
+    let empty_page = adw::StatusPage::builder()
+        .icon_name("folder-symbolic")
+        .title(gettext("No Watch Folders"))
+        .description(gettext(
+            "New files in a watch folder are sent to a device automatically, e.g. from a scanner or of screenshots",
+        ))
+        .build();
+    stack.add_named(&empty_page, Some("empty"));
+
+    let listbox = gtk::ListBox::builder()
+        .valign(gtk::Align::Start)
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    let clamp = adw::Clamp::builder()
+        .maximum_size(550)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .child(&listbox)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .child(&clamp)
+        .build();
+    stack.add_named(&scrolled_window, Some("folders"));
+
+    win.imp().watch_folders.dialog_listbox.set(Some(&listbox));
+    refresh_watch_folders_dialog(win);
+
+    dialog.present(Some(win));
+}
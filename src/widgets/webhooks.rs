@@ -0,0 +1,306 @@
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use formatx::formatx;
+use gettextrs::{gettext, ngettext};
+use gtk::glib::{self, clone};
+
+use crate::{
+    secret, tokio_runtime,
+    utils::format_unix_timestamp,
+    webhooks::{self, WebhookConfig, WebhookEvent, WebhookUrl},
+    window::PacketApplicationWindow,
+};
+
+/// What the secret's called in the keyring.
+const WEBHOOK_SECRET: &str = "webhook-secret";
+
+pub fn setup_webhook_preferences(win: &PacketApplicationWindow) {
+    let imp = win.imp();
+
+    imp.settings
+        .bind(
+            "enable-webhook",
+            &imp.webhook_expander.get(),
+            "enable-expansion",
+        )
+        .build();
+    imp.webhook_url_entry
+        .set_text(&imp.settings.string("webhook-url"));
+    // Not looked up before it's needed, since that can mean having to unlock
+    // the keyring
+    imp.webhook_expander.connect_expanded_notify(clone!(
+        #[weak]
+        win,
+        move |expander| {
+            if !expander.is_expanded() {
+                return;
+            }
+            glib::spawn_future_local(clone!(
+                #[weak]
+                win,
+                async move {
+                    let secret = webhook_secret(&win).await;
+                    let entry = &win.imp().webhook_secret_entry;
+                    if entry.text().is_empty() {
+                        entry.set_text(secret.as_deref().unwrap_or_default());
+                    }
+                }
+            ));
+        }
+    ));
+
+    imp.webhook_url_entry.connect_apply(clone!(
+        #[weak]
+        win,
+        move |entry| {
+            let url = entry.text().trim().to_string();
+            if !url.is_empty()
+                && let Err(err) = WebhookUrl::parse(&url)
+            {
+                tracing::info!(url, "Invalid webhook URL: {err:#}");
+                entry.add_css_class("error");
+                win.add_toast(&gettext(
+                    "Enter a URL that starts with http://, e.g. http://localhost:8123/hook",
+                ));
+                return;
+            }
+
+            tracing::info!(url, "Setting webhook URL");
+            entry.remove_css_class("error");
+            if let Err(err) = win.imp().settings.set_string("webhook-url", &url) {
+                tracing::warn!("Couldn't save the webhook URL: {err:#}");
+            }
+        }
+    ));
+    imp.webhook_url_entry.connect_changed(|entry| {
+        entry.remove_css_class("error");
+    });
+
+    imp.webhook_secret_entry.connect_apply(clone!(
+        #[weak]
+        win,
+        move |entry| {
+            let secret = entry.text().to_string();
+            tracing::info!(is_set = !secret.is_empty(), "Setting webhook secret");
+            glib::spawn_future_local(clone!(
+                #[weak]
+                win,
+                async move {
+                    match secret::store(WEBHOOK_SECRET, &gettext("Packet Webhook Secret"), &secret)
+                        .await
+                    {
+                        Ok(_) => {
+                            win.imp()
+                                .webhook_secret
+                                .replace(Some((!secret.is_empty()).then_some(secret)));
+                        }
+                        Err(err) => {
+                            tracing::warn!("Couldn't save the webhook secret: {err:#}");
+                            win.add_toast(&gettext("Couldn't save the secret to the keyring"));
+                        }
+                    }
+                }
+            ));
+        }
+    ));
+
+    imp.webhook_log_row.connect_activated(clone!(
+        #[weak]
+        win,
+        move |_| {
+            present_webhook_log_dialog(&win);
+        }
+    ));
+    update_webhook_log_row(win);
+
+    // Delivered one at a time, so that the events arrive in order
+    let queue_rx = imp.webhook_queue.1.clone();
+    glib::spawn_future_local(clone!(
+        #[weak]
+        win,
+        async move {
+            while let Ok((mut config, payload)) = queue_rx.recv().await {
+                config.secret = webhook_secret(&win).await;
+                let Ok(entry) = tokio_runtime()
+                    .spawn(async move { webhooks::deliver(&config, &payload).await })
+                    .await
+                else {
+                    continue;
+                };
+
+                {
+                    let mut log = win.imp().webhook_log.borrow_mut();
+                    log.push_front(entry);
+                    log.truncate(webhooks::MAX_LOG_ENTRIES);
+                }
+                update_webhook_log_row(&win);
+            }
+        }
+    ));
+}
+
+/// Queues a webhook for the transfer event, if it's one that gets reported.
+pub fn handle_webhook_event(
+    win: &PacketApplicationWindow,
+    channel_message: &rqs_lib::channel::ChannelMessage,
+    device_name: &str,
+) {
+    let imp = win.imp();
+
+    let url = imp.settings.string("webhook-url");
+    if !imp.settings.boolean("enable-webhook") || url.is_empty() {
+        return;
+    }
+
+    let Some(payload) = imp
+        .webhook_tracker
+        .borrow_mut()
+        .payload_for(channel_message, device_name)
+    else {
+        return;
+    };
+    // The secret's filled in once it's about to be delivered
+    let config = WebhookConfig {
+        url: url.to_string(),
+        secret: None,
+    };
+
+    _ = imp.webhook_queue.0.try_send((config, payload));
+}
+
+async fn webhook_secret(win: &PacketApplicationWindow) -> Option<String> {
+    if let Some(secret) = win.imp().webhook_secret.borrow().clone() {
+        return secret;
+    }
+
+    let secret = secret::lookup(WEBHOOK_SECRET)
+        .await
+        .inspect_err(|err| tracing::warn!("Couldn't look up the webhook secret: {err:#}"))
+        .ok()
+        .flatten();
+    win.imp().webhook_secret.replace(Some(secret.clone()));
+
+    secret
+}
+
+fn event_title(event: WebhookEvent) -> String {
+    match event {
+        WebhookEvent::Started => gettext("Transfer Started"),
+        WebhookEvent::Finished => gettext("Transfer Finished"),
+        WebhookEvent::Failed => gettext("Transfer Failed"),
+        WebhookEvent::Cancelled => gettext("Transfer Cancelled"),
+    }
+}
+
+fn update_webhook_log_row(win: &PacketApplicationWindow) {
+    let imp = win.imp();
+
+    let log = imp.webhook_log.borrow();
+    let subtitle = match log.front() {
+        None => gettext("Nothing sent yet"),
+        Some(entry) => match &entry.result {
+            Ok(_) => formatx!(
+                gettext("Last sent at {}"),
+                format_unix_timestamp(entry.timestamp)
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+            Err(_) => formatx!(
+                gettext("Last delivery failed at {}"),
+                format_unix_timestamp(entry.timestamp)
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+        },
+    };
+    imp.webhook_log_row.set_subtitle(&subtitle);
+}
+
+fn present_webhook_log_dialog(win: &PacketApplicationWindow) {
+    let dialog = adw::Dialog::builder()
+        .title(gettext("Delivery Log"))
+        .content_width(480)
+        .content_height(520)
+        .build();
+
+    let toolbar_view = adw::ToolbarView::builder().build();
+    dialog.set_child(Some(&toolbar_view));
+    toolbar_view.add_top_bar(&adw::HeaderBar::new());
+
+    let log = win.imp().webhook_log.borrow();
+    if log.is_empty() {
+        let empty_page = adw::StatusPage::builder()
+            .icon_name("network-transmit-symbolic")
+            .title(gettext("Nothing Sent Yet"))
+            .description(gettext(
+                "Transfers that start, finish or fail since Packet was started show up here",
+            ))
+            .build();
+        toolbar_view.set_content(Some(&empty_page));
+        dialog.present(Some(win));
+        return;
+    }
+
+    let listbox = gtk::ListBox::builder()
+        .valign(gtk::Align::Start)
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    for entry in log.iter() {
+        let outcome = match &entry.result {
+            Ok(status) => formatx!(
+                ngettext(
+                    "Delivered with status {} after {} attempt",
+                    "Delivered with status {} after {} attempts",
+                    entry.attempts
+                ),
+                status,
+                entry.attempts
+            ),
+            Err(err) => formatx!(
+                ngettext(
+                    "Failed after {} attempt: {}",
+                    "Failed after {} attempts: {}",
+                    entry.attempts
+                ),
+                entry.attempts,
+                err
+            ),
+        }
+        .unwrap_or_else(|_| "badly formatted locale string".into());
+
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&format!(
+                "{} · {}",
+                event_title(entry.event),
+                entry.device
+            )))
+            .subtitle(glib::markup_escape_text(&format!(
+                "{}\n{outcome}",
+                format_unix_timestamp(entry.timestamp)
+            )))
+            .subtitle_selectable(true)
+            .build();
+        let icon = gtk::Image::from_icon_name(if entry.result.is_ok() {
+            "emblem-ok-symbolic"
+        } else {
+            "dialog-warning-symbolic"
+        });
+        row.add_suffix(&icon);
+        listbox.append(&row);
+    }
+
+    let clamp = adw::Clamp::builder()
+        .maximum_size(550)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .child(&listbox)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .child(&clamp)
+        .build();
+    toolbar_view.set_content(Some(&scrolled_window));
+
+    dialog.present(Some(win));
+}
@@ -1,12 +1,16 @@
 use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::Instant;
 
 use adw::prelude::*;
 use adw::subclass::prelude::*;
 use anyhow::{Context, anyhow};
-use ashpd::desktop::background::Background;
-use ashpd::desktop::notification::NotificationProxy;
+use ashpd::desktop::ResponseError;
+use ashpd::desktop::background::{Background, BackgroundProxy};
+use ashpd::desktop::notification::{Notification, NotificationProxy};
+use ashpd::desktop::screenshot::Screenshot;
 use formatx::formatx;
 use futures_lite::StreamExt;
 use gettextrs::{gettext, ngettext};
@@ -17,13 +21,36 @@ use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 
 use crate::application::PacketApplication;
-use crate::config::{APP_ID, PROFILE};
+use crate::backend::{BackendChannels, QuickShareBackend};
+use crate::config::{APP_ID, PROFILE, VERSION};
+use crate::deferred::DeferredSends;
+use crate::errors::{self, StartupErrorAction, StartupErrorKind};
+use crate::event_stream::{self, Event, EventStream};
+use crate::global_shortcuts::{self, GlobalShortcut};
+use crate::hooks;
+use crate::inhibit::TransferInhibitor;
+use crate::logging::{self, Redactor};
 use crate::objects::{self, SendRequestState};
 use crate::objects::{TransferState, UserAction};
-use crate::plugins::{FileBasedPlugin, NautilusPlugin, Plugin};
-use crate::utils::{strip_user_home_prefix, with_signals_blocked, xdg_download_with_fallback};
+use crate::plugins::FileManager;
+use crate::received_files::ReceivedFiles;
+use crate::service::{ServiceCommand, ServiceEvent, ServiceHandle};
+use crate::supervisor::{Recovery, ServiceHealth, Supervisor, SupervisorConfig, TaskError};
+use crate::transfer_flow::ActiveTransfers;
+use crate::tray::{Tray, TrayAction};
+use crate::utils::{
+    spawn_notification, strip_user_home_prefix, with_signals_blocked, xdg_download_with_fallback,
+};
+use crate::watch_folders::WatchFolderState;
+use crate::webhooks::{DeliveryLogEntry, WebhookDelivery, WebhookTracker};
 use crate::{monitors, tokio_runtime, widgets};
 
+// Names of the supervised tasks that forward events from the RQS service
+const TRANSFER_EVENTS_TASK: &str = "transfer-events";
+const DISCOVERY_EVENTS_TASK: &str = "discovery-events";
+const VISIBILITY_EVENTS_TASK: &str = "visibility-events";
+const BLE_EVENTS_TASK: &str = "ble-events";
+
 #[derive(Debug)]
 pub enum LoopingTaskHandle {
     Tokio(tokio::task::JoinHandle<()>),
@@ -67,9 +94,13 @@ mod imp {
         #[template_child]
         pub root_stack: TemplateChild<gtk::Stack>,
 
+        #[template_child]
+        pub rqs_error_status: TemplateChild<adw::StatusPage>,
         #[template_child]
         pub rqs_error_copy_button: TemplateChild<gtk::Button>,
         #[template_child]
+        pub rqs_error_action_button: TemplateChild<gtk::Button>,
+        #[template_child]
         pub rqs_error_retry_button: TemplateChild<gtk::Button>,
         pub rqs_error: Rc<RefCell<Option<anyhow::Error>>>,
 
@@ -101,18 +132,45 @@ mod imp {
         #[template_child]
         pub static_port_entry: TemplateChild<adw::EntryRow>,
         #[template_child]
+        pub webhook_expander: TemplateChild<adw::ExpanderRow>,
+        #[template_child]
+        pub webhook_url_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub webhook_secret_entry: TemplateChild<adw::PasswordEntryRow>,
+        #[template_child]
+        pub webhook_log_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
         pub download_folder_row: TemplateChild<adw::ActionRow>,
         #[template_child]
         pub download_folder_pick_button: TemplateChild<gtk::Button>,
         #[template_child]
+        pub decline_low_space_switch: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub keep_partial_files_switch: TemplateChild<adw::SwitchRow>,
+        #[template_child]
         pub run_in_background_switch: TemplateChild<adw::SwitchRow>,
         pub run_in_background_switch_handler_id: RefCell<Option<glib::SignalHandlerId>>,
         #[template_child]
         pub auto_start_switch: TemplateChild<adw::SwitchRow>,
         pub auto_start_switch_handler_id: RefCell<Option<glib::SignalHandlerId>>,
         #[template_child]
-        pub nautilus_plugin_switch: TemplateChild<adw::SwitchRow>,
-        pub nautilus_plugin_switch_handler_id: RefCell<Option<glib::SignalHandlerId>>,
+        pub tray_icon_switch: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub plugins_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub log_file_switch: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub screenshot_last_device_switch: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub global_shortcuts_expander: TemplateChild<adw::ExpanderRow>,
+        #[template_child]
+        pub global_shortcut_toggle_visibility_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub global_shortcut_send_clipboard_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub global_shortcut_open_send_page_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub global_shortcut_send_screenshot_entry: TemplateChild<adw::EntryRow>,
 
         #[template_child]
         pub main_box: TemplateChild<gtk::Box>,
@@ -144,11 +202,34 @@ mod imp {
         pub loading_recipients_box: TemplateChild<gtk::Box>,
         #[template_child]
         pub recipients_help_button: TemplateChild<gtk::LinkButton>,
+        #[template_child]
+        pub send_queue_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub recipients_selection_button: TemplateChild<gtk::ToggleButton>,
+        #[template_child]
+        pub send_to_selected_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub send_later_button: TemplateChild<gtk::Button>,
         #[default(gio::ListStore::new::<SendRequestState>())]
         pub recipient_model: gio::ListStore,
 
         pub send_transfers_id_cache: Arc<Mutex<HashMap<String, SendRequestState>>>, // id, state
+        pub send_queue: objects::SendQueue,
+        pub deferred_sends: RefCell<DeferredSends>,
+        /// Endpoints that are currently around, for the deferred sends
+        pub nearby_endpoints: RefCell<HashMap<String, rqs_lib::EndpointInfo>>,
+        pub active_deferred_send: RefCell<Option<(String, String)>>, // transfer id, deferred send id
+        /// Last progress of the active deferred send that was notified about
+        pub active_deferred_send_progress: Cell<Option<u64>>,
+        /// Endpoints that were asked about being a device with deferred sends
+        /// under a new id, and the id the sends are waiting on
+        pub device_confirmations: RefCell<HashMap<String, String>>,
+        pub watch_folders: WatchFolderState,
         pub receive_transfer_cache: Arc<Mutex<Option<ReceiveTransferCache>>>,
+        /// Download folder snapshots taken when inbound transfers were
+        /// accepted, by transfer id, to find the files they wrote
+        pub received_files: RefCell<HashMap<String, tokio::task::JoinHandle<ReceivedFiles>>>,
+        pub transfer_inhibitor: TransferInhibitor,
 
         #[default(gio::NetworkMonitor::default())]
         pub network_monitor: gio::NetworkMonitor,
@@ -162,21 +243,39 @@ mod imp {
         pub network_state_sender: Arc<Mutex<Option<tokio::sync::broadcast::Sender<bool>>>>,
 
         // RQS State
-        pub rqs: Arc<Mutex<Option<rqs_lib::RQS>>>,
-        pub file_sender: Arc<Mutex<Option<tokio::sync::mpsc::Sender<rqs_lib::SendInfo>>>>,
+        pub service: RefCell<Option<ServiceHandle>>,
         pub ble_receiver: Arc<Mutex<Option<tokio::sync::broadcast::Receiver<()>>>>,
         pub mdns_discovery_broadcast_tx:
             Arc<Mutex<Option<tokio::sync::broadcast::Sender<rqs_lib::EndpointInfo>>>>,
         pub is_mdns_discovery_on: Rc<Cell<bool>>,
 
         pub looping_async_tasks: RefCell<Vec<LoopingTaskHandle>>,
+        pub event_stream: EventStream,
+        pub webhook_tracker: RefCell<WebhookTracker>,
+        /// Webhook deliveries, sent one at a time so that they arrive in order
+        #[default(async_channel::unbounded())]
+        pub webhook_queue: (
+            async_channel::Sender<WebhookDelivery>,
+            async_channel::Receiver<WebhookDelivery>,
+        ),
+        /// Newest first
+        pub webhook_log: RefCell<VecDeque<DeliveryLogEntry>>,
+        /// Looked up in the keyring the first time it's needed, `Some(None)`
+        /// if there's none
+        pub webhook_secret: RefCell<Option<Option<String>>>,
+        #[default(Supervisor::new(SupervisorConfig::default(), tokio_runtime().handle().clone()))]
+        pub supervisor: Supervisor,
+
+        /// Dropping it unbinds the global shortcuts
+        pub global_shortcuts_stop: RefCell<Option<async_channel::Sender<()>>>,
+        /// Last status message set through the Background portal
+        pub background_status: RefCell<String>,
+        pub tray: RefCell<Option<Tray>>,
 
         pub is_background_allowed: Cell<bool>,
         pub should_quit: Cell<bool>,
 
         pub is_recipients_dialog_opened: Cell<bool>,
-
-        pub nautilus_plugin: NautilusPlugin,
     }
 
     #[glib::object_subclass]
@@ -213,8 +312,14 @@ mod imp {
             obj.setup_ui();
             obj.setup_connection_monitors();
             obj.setup_notification_actions_monitor();
+            widgets::setup_deferred_sends(&obj);
+            widgets::setup_watch_folders(&obj);
+            obj.setup_service_supervisor();
+            obj.setup_event_stream();
             obj.setup_rqs_service();
             obj.request_background();
+            obj.setup_global_shortcuts();
+            obj.setup_tray();
         }
     }
 
@@ -275,24 +380,17 @@ mod imp {
                 }
             }
 
-            let (tx, rx) = async_channel::bounded(1);
-            tokio_runtime().spawn(clone!(
-                #[weak(rename_to = rqs)]
-                self.rqs,
-                async move {
-                    {
-                        tracing::info!("Stopping RQS service");
-                        let mut rqs_guard = rqs.lock().await;
-                        if let Some(rqs) = rqs_guard.as_mut() {
-                            rqs.stop().await;
-                        }
-                    }
+            if let Some(service) = self.service.take() {
+                let (tx, rx) = async_channel::bounded(1);
+                tokio_runtime().spawn(async move {
+                    tracing::info!("Stopping RQS service");
+                    service.stop().await;
 
-                    tx.send(()).await.unwrap();
-                }
-            ));
+                    _ = tx.send(()).await;
+                });
 
-            rx.recv_blocking().unwrap();
+                _ = rx.recv_blocking();
+            }
 
             // Pass close request on to the parent
             self.parent_close_request()
@@ -410,15 +508,50 @@ impl PacketApplicationWindow {
             })
             .build();
 
+        let diagnostics = gio::ActionEntry::builder("diagnostics")
+            .activate(move |win: &Self, _, _| {
+                widgets::present_diagnostics_dialog(win);
+            })
+            .build();
+
+        let watch_folders = gio::ActionEntry::builder("watch-folders")
+            .activate(move |win: &Self, _, _| {
+                widgets::present_watch_folders_dialog(win);
+            })
+            .build();
+
+        let screenshot = gio::ActionEntry::builder("screenshot")
+            .activate(move |win: &Self, _, _| {
+                win.send_screenshot();
+            })
+            .build();
+
+        let show_logs = gio::ActionEntry::builder("show-logs")
+            .activate(move |win: &Self, _, _| {
+                widgets::present_log_viewer(win);
+            })
+            .build();
+
+        let export_logs = gio::ActionEntry::builder("export-logs")
+            .activate(move |win: &Self, _, _| {
+                win.export_logs();
+            })
+            .build();
+
         self.add_action_entries([
             preferences_dialog,
             received_files,
             help_dialog,
             pick_download_folder,
+            diagnostics,
+            watch_folders,
+            screenshot,
+            show_logs,
+            export_logs,
         ]);
     }
 
-    fn add_toast(&self, msg: &str) {
+    pub fn add_toast(&self, msg: &str) {
         self.imp().toast_overlay.add_toast(adw::Toast::new(msg));
     }
 
@@ -442,6 +575,20 @@ impl PacketApplicationWindow {
                 "active",
             )
             .build();
+        imp.settings
+            .bind(
+                "decline-if-low-on-space",
+                &imp.decline_low_space_switch.get(),
+                "active",
+            )
+            .build();
+        imp.settings
+            .bind(
+                "keep-partial-files",
+                &imp.keep_partial_files_switch.get(),
+                "active",
+            )
+            .build();
         imp.settings
             .bind(
                 "run-in-background",
@@ -452,14 +599,103 @@ impl PacketApplicationWindow {
         imp.settings
             .bind("auto-start", &imp.auto_start_switch.get(), "active")
             .build();
+        imp.settings
+            .bind("show-tray-icon", &imp.tray_icon_switch.get(), "active")
+            .build();
+        imp.settings.connect_changed(
+            Some("show-tray-icon"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, _| {
+                    this.setup_tray();
+                }
+            ),
+        );
+        imp.settings
+            .bind("enable-log-file", &imp.log_file_switch.get(), "active")
+            .build();
         imp.settings
             .bind(
-                "enable-nautilus-plugin",
-                &imp.nautilus_plugin_switch.get(),
+                "screenshot-send-to-last-device",
+                &imp.screenshot_last_device_switch.get(),
                 "active",
             )
             .build();
 
+        imp.settings
+            .bind(
+                "enable-global-shortcuts",
+                &imp.global_shortcuts_expander.get(),
+                "enable-expansion",
+            )
+            .build();
+        for (shortcut, entry) in [
+            (
+                GlobalShortcut::ToggleVisibility,
+                &imp.global_shortcut_toggle_visibility_entry,
+            ),
+            (
+                GlobalShortcut::SendClipboard,
+                &imp.global_shortcut_send_clipboard_entry,
+            ),
+            (
+                GlobalShortcut::OpenSendPage,
+                &imp.global_shortcut_open_send_page_entry,
+            ),
+            (
+                GlobalShortcut::SendScreenshot,
+                &imp.global_shortcut_send_screenshot_entry,
+            ),
+        ] {
+            entry.set_title(&shortcut.description());
+            entry.set_text(&imp.settings.string(shortcut.settings_key()));
+            entry.connect_apply(clone!(
+                #[weak]
+                imp,
+                move |entry| {
+                    _ = imp
+                        .settings
+                        .set_string(shortcut.settings_key(), entry.text().trim());
+                }
+            ));
+        }
+        imp.settings.connect_changed(
+            None,
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, key| {
+                    let is_shortcuts_key = key == "enable-global-shortcuts"
+                        || GlobalShortcut::ALL
+                            .iter()
+                            .any(|it| it.settings_key() == key);
+                    if is_shortcuts_key {
+                        this.setup_global_shortcuts();
+                    }
+                }
+            ),
+        );
+        imp.log_file_switch.set_subtitle(
+            &formatx!(
+                gettext("Logs are saved to {}"),
+                strip_user_home_prefix(logging::log_dir()).to_string_lossy()
+            )
+            .unwrap_or_else(|_| "badly formatted locale string".into()),
+        );
+
+        self.apply_log_file_state();
+        imp.settings.connect_changed(
+            Some("enable-log-file"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, _| {
+                    this.apply_log_file_state();
+                }
+            ),
+        );
+
         let device_name = &self.get_device_name_state();
         let device_name_entry = imp.device_name_entry.get();
         {
@@ -475,88 +711,8 @@ impl PacketApplicationWindow {
             }
         }
 
-        if imp.settings.boolean("enable-nautilus-plugin") {
-            // Update plugin
-            // This takes care of cases of applying updates to the python extension
-            // script as well as reinstalling it if it got removed for some reason.
-            let plugin = imp.nautilus_plugin.clone();
-            glib::spawn_future_local(clone!(
-                #[weak]
-                imp,
-                async move {
-                    let success = tokio_runtime()
-                        .spawn_blocking(move || plugin.install_plugin())
-                        .await
-                        .map_err(|err| anyhow::anyhow!(err))
-                        .and_then(|it| it)
-                        .inspect_err(|err| tracing::error!("{err:#}"))
-                        .is_ok();
-
-                    if !success {
-                        imp.obj()
-                            .add_toast(&gettext("Couldn't update the Nautilus plugin"));
-                    }
-                }
-            ));
-        }
-
-        let _signal_handle = imp.nautilus_plugin_switch.connect_active_notify(clone!(
-            #[weak]
-            imp,
-            move |switch| {
-                glib::spawn_future_local(clone!(
-                    #[weak]
-                    imp,
-                    #[weak]
-                    switch,
-                    async move {
-                        switch.set_sensitive(false);
-
-                        let enable_plugin = switch.is_active();
-
-                        tracing::info!(enable_plugin, "Setting Nautilus plugin state");
-
-                        let plugin = imp.nautilus_plugin.clone();
-                        let success = tokio_runtime()
-                            .spawn_blocking(move || {
-                                if enable_plugin {
-                                    plugin.install_plugin()
-                                } else {
-                                    plugin.uninstall_plugin()
-                                }
-                            })
-                            .await
-                            .map_err(|err| anyhow::anyhow!(err))
-                            .and_then(|it| it)
-                            .inspect_err(|err| tracing::error!("{err:#}"))
-                            .is_ok();
-
-                        if enable_plugin {
-                            if success {
-                                imp.obj().present_plugin_success_dialog();
-                            } else {
-                                imp.obj().present_plugin_error_dialog(
-                                        NautilusPlugin::help_install_dir(),
-                                    );
-                                with_signals_blocked(
-                                    &[(
-                                        &switch,
-                                        imp.nautilus_plugin_switch_handler_id.borrow().as_ref(),
-                                    )],
-                                    || {
-                                        switch.set_active(false);
-                                    },
-                                );
-                            }
-                        }
-
-                        switch.set_sensitive(true);
-                    }
-                ));
-            }
-        ));
-        imp.nautilus_plugin_switch_handler_id
-            .replace(Some(_signal_handle));
+        widgets::setup_plugins_preferences(self);
+        widgets::setup_webhook_preferences(self);
 
         let _signal_handle = imp.run_in_background_switch.connect_active_notify(clone!(
             #[weak]
@@ -768,8 +924,7 @@ impl PacketApplicationWindow {
                         async move {
                             let port_number = imp.settings.int("static-port-number");
                             if obj.enables_expansion()
-                                && Some(port_number as u32)
-                                    != imp.rqs.lock().await.as_ref().unwrap().port_number
+                                && Some(port_number as u32) != imp.obj().service_port()
                             {
                                 tracing::info!(port_number, "Setting custom static port");
 
@@ -813,7 +968,7 @@ impl PacketApplicationWindow {
 
                     imp.obj().restart_rqs_service();
                 }
-                else if Some(port_number as u32) == imp.rqs.blocking_lock().as_ref().unwrap().port_number {
+                else if Some(port_number as u32) == imp.obj().service_port() {
                     // Don't do anything if port is already set
                 }
                 else {
@@ -908,6 +1063,151 @@ impl PacketApplicationWindow {
         ));
     }
 
+    fn apply_log_file_state(&self) {
+        let enable_log_file = self.imp().settings.boolean("enable-log-file");
+        tracing::info!(enable_log_file, log_dir = ?logging::log_dir(), "Setting log file state");
+
+        if let Err(err) = logging::log_store().set_file_logging(enable_log_file) {
+            tracing::warn!(%err, "Couldn't open the log file");
+            self.add_toast(&gettext("Couldn't save logs to file"));
+        }
+    }
+
+    /// A plain-text report for bug reports, containing version info, relevant
+    /// settings, connectivity state and the captured logs.
+    ///
+    /// The device name and home paths are redacted.
+    pub fn troubleshooting_report(&self) -> String {
+        let imp = self.imp();
+
+        let redactor = Redactor::new([
+            self.get_device_name_state().to_string(),
+            whoami::devicename(),
+        ]);
+
+        let mut report = String::new();
+
+        report.push_str("# Packet\n");
+        report.push_str(&format!("Version: {VERSION} ({PROFILE})\n"));
+        report.push_str(&format!("App ID: {APP_ID}\n"));
+        report.push_str(&format!(
+            "OS: {}\n",
+            glib::os_info("PRETTY_NAME").unwrap_or_else(|| "Unknown".into())
+        ));
+        report.push_str(&format!(
+            "Desktop: {}\n",
+            std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default()
+        ));
+        report.push_str(&format!(
+            "Flatpak: {}\n",
+            std::path::Path::new("/.flatpak-info").exists()
+        ));
+
+        report.push_str("\n# Settings\n");
+        for key in [
+            "device-visibility",
+            "download-folder",
+            "enable-static-port",
+            "static-port-number",
+            "run-in-background",
+            "auto-start",
+            "enable-log-file",
+        ]
+        .into_iter()
+        .chain(FileManager::ALL.iter().map(|it| it.settings_key()))
+        {
+            report.push_str(&format!("{key}: {}\n", imp.settings.value(key)));
+        }
+
+        report.push_str("\n# State\n");
+        report.push_str(&format!("Network available: {}\n", imp.network_state.get()));
+        report.push_str(&format!(
+            "Network connectivity: {:?}\n",
+            imp.network_monitor.connectivity()
+        ));
+        report.push_str(&format!(
+            "Bluetooth powered: {}\n",
+            imp.bluetooth_state.get()
+        ));
+        report.push_str(&format!(
+            "Service port: {}\n",
+            self.service_port()
+                .map(|it| it.to_string())
+                .unwrap_or_else(|| "Unknown".into())
+        ));
+        report.push_str(&format!(
+            "mDNS discovery on: {}\n",
+            imp.is_mdns_discovery_on.get()
+        ));
+        report.push_str(&format!("Service health: {:?}\n", imp.supervisor.health()));
+        report.push_str(&format!(
+            "Service error kind: {}\n",
+            imp.rqs_error
+                .borrow()
+                .as_ref()
+                .map(|err| format!("{:?}", StartupErrorKind::classify(err)))
+                .unwrap_or_else(|| "None".into())
+        ));
+        report.push_str(&format!(
+            "Service error: {}\n",
+            imp.rqs_error
+                .borrow()
+                .as_ref()
+                .map(|err| format!("{err:#}"))
+                .unwrap_or_else(|| "None".into())
+        ));
+
+        report.push_str("\n# Logs\n");
+        for record in logging::log_store().records(tracing::Level::TRACE) {
+            report.push_str(&record.to_string());
+            report.push('\n');
+        }
+
+        redactor.redact(&report)
+    }
+
+    fn export_logs(&self) {
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                let filename = format!(
+                    "packet-logs-{}.txt",
+                    glib::DateTime::now_local()
+                        .and_then(|it| it.format("%Y%m%d-%H%M%S"))
+                        .map(|it| it.to_string())
+                        .unwrap_or_default()
+                );
+                let Ok(file) = gtk::FileDialog::builder()
+                    .initial_name(filename)
+                    .build()
+                    .save_future(Some(&this))
+                    .await
+                else {
+                    return;
+                };
+                let Some(path) = file.path() else {
+                    return;
+                };
+
+                let report = this.troubleshooting_report();
+                let result = tokio_runtime()
+                    .spawn_blocking(move || fs_err::write(path, report))
+                    .await
+                    .map_err(|err| anyhow!(err))
+                    .and_then(|it| it.map_err(|err| anyhow!(err)));
+
+                match result {
+                    Ok(_) => this.add_toast(&gettext("Logs exported")),
+                    Err(err) => {
+                        tracing::error!("Failed to export logs: {err:#}");
+                        this.add_toast(&gettext("Couldn't export logs"));
+                    }
+                }
+            }
+        ));
+    }
+
     async fn portal_request_background(&self) -> Option<Background> {
         let imp = self.imp();
 
@@ -925,6 +1225,10 @@ impl PacketApplicationWindow {
             Ok(response) => {
                 self.imp().is_background_allowed.replace(true);
 
+                // The status is gone along with the previous instance
+                imp.background_status.replace(String::new());
+                self.update_background_status();
+
                 Some(response)
             }
             Err(err) => {
@@ -955,104 +1259,331 @@ impl PacketApplicationWindow {
         }
     }
 
-    fn request_background(&self) {
+    /// Takes a screenshot through the portal and gets it ready to be sent, or
+    /// sends it right away to the last used device if that's preferred.
+    fn send_screenshot(&self) {
         glib::spawn_future_local(clone!(
             #[weak(rename_to = this)]
             self,
             async move {
-                let is_run_in_background = this.imp().settings.boolean("run-in-background");
-                if let Some(response) = this.portal_request_background().await {
-                    tracing::debug!(?response, "Background request successful");
+                let imp = this.imp();
 
-                    if !response.auto_start() {
-                        if let Some(app) =
-                            this.application().and_downcast_ref::<PacketApplication>()
-                        {
-                            app.imp().start_in_background.replace(false);
-                        }
+                let identifier = match this.native() {
+                    Some(native) => ashpd::WindowIdentifier::from_native(&native).await,
+                    None => None,
+                };
+                let response = Screenshot::request()
+                    .identifier(identifier)
+                    .interactive(true)
+                    .modal(true)
+                    .send()
+                    .await
+                    .and_then(|it| it.response());
+
+                let file = match response {
+                    Ok(response) => gio::File::for_uri(response.uri().as_str()),
+                    Err(ashpd::Error::Response(ResponseError::Cancelled)) => {
+                        tracing::info!("Screenshot was cancelled");
+                        return;
                     }
-                } else if is_run_in_background {
-                    this.add_toast(&gettext("Packet cannot run in the background"));
+                    Err(err) => {
+                        tracing::warn!("Failed to take screenshot: {:#}", err);
+                        this.add_toast(&gettext("Couldn't take a screenshot"));
+                        return;
+                    }
+                };
+                tracing::info!(path = ?file.path(), "Took screenshot");
+
+                if imp.settings.boolean("screenshot-send-to-last-device")
+                    && let Some(device) = widgets::last_used_device(&this)
+                    && let Some(path) = file.path()
+                {
+                    widgets::defer_files(&this, &device, vec![path.to_string_lossy().into_owned()]);
+                    this.add_toast(
+                        &formatx!(gettext("Sending the screenshot to {}"), &device.name)
+                            .unwrap_or_else(|_| "badly formatted locale string".into()),
+                    );
+                    return;
+                }
+
+                this.present();
+                if this.handle_added_files_to_send(&imp.manage_files_model, vec![file]) {
+                    this.present_recipients_dialog();
                 }
             }
         ));
     }
 
-    fn pick_download_folder(&self) {
+    /// Binds the global shortcuts as per the preferences, replacing the ones
+    /// bound before.
+    fn setup_global_shortcuts(&self) {
         let imp = self.imp();
 
-        glib::spawn_future_local(clone!(
-            #[weak]
-            imp,
-            async move {
-                if let Ok(file) = gtk::FileDialog::new()
-                    .select_folder_future(
-                        imp.obj()
-                            .root()
-                            .and_downcast_ref::<PacketApplicationWindow>(),
-                    )
-                    .await
-                {
-                    // TODO: Maybe format the display path in the preferences?
-                    // `Sandbox: Music` or `Music` instead of `/run/user/1000/_/Music` (for mounted paths)
-                    // This would require storing the display string in gschema however
-                    //
-                    // Check whether it's a sandbox path or not by matching the path
-                    // against the xattr host path, if it doesn't match, it's sandbox
-                    //
-                    // Flatpak metadata is available from `/.flatpak-info`, which contains info
-                    // about host filesystem paths being available to the app, and much more.
-
-                    // Path provided is host path if the app has been granted host access to it via
-                    // --filesystem. Otherwise, it's a mounted path.
-                    //
-                    // Now, there's an issue with the vscode-flatpak extension where while running
-                    // the app through it, the path given by FileChooser is always a mounted path.
-                    // Leaving this note here so as to not base our logic on this wrong behaviour.
-                    let folder_path = file.path().unwrap();
-
-                    let display_path = strip_user_home_prefix(&folder_path);
-
-                    tracing::debug!(
-                        ?folder_path,
-                        ?display_path,
-                        "Selected custom downloads folder"
-                    );
+        imp.global_shortcuts_stop.replace(None);
+        if !imp.settings.boolean("enable-global-shortcuts") {
+            return;
+        }
 
-                    imp.download_folder_row
-                        .set_subtitle(&display_path.to_string_lossy());
+        let shortcuts = GlobalShortcut::ALL
+            .into_iter()
+            .map(|it| (it, imp.settings.string(it.settings_key()).to_string()))
+            .collect::<Vec<_>>();
+        let (stop_tx, stop_rx) = async_channel::bounded(1);
+        imp.global_shortcuts_stop.replace(Some(stop_tx));
 
-                    imp.settings
-                        .set_string("download-folder", folder_path.to_str().unwrap())
-                        .unwrap();
-                    imp.rqs
-                        .lock()
-                        .await
-                        .as_mut()
-                        .unwrap()
-                        .set_download_path(Some(folder_path));
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                let identifier = match this.native() {
+                    Some(native) => ashpd::WindowIdentifier::from_native(&native).await,
+                    None => None,
                 };
+
+                let on_activated = clone!(
+                    #[weak]
+                    this,
+                    move |shortcut| this.activate_global_shortcut(shortcut)
+                );
+                if let Err(err) =
+                    global_shortcuts::run(shortcuts, identifier, stop_rx, on_activated).await
+                {
+                    tracing::warn!("Global shortcuts aren't available: {:#}", err);
+                }
             }
         ));
     }
 
-    fn setup_ui(&self) {
-        self.setup_bottom_bar();
-
-        self.setup_status_pages();
-        self.setup_main_page();
-        self.setup_manage_files_page();
-        self.setup_recipient_page();
-    }
-
-    fn present_plugin_success_dialog(&self) {
-        let dialog = adw::AlertDialog::builder()
-            .heading(&gettext("Plugin Installed"))
-            .default_response("done")
-            .build();
-
-        dialog.add_response("done", &gettext("Done"));
-        dialog.set_response_appearance("done", adw::ResponseAppearance::Suggested);
+    fn activate_global_shortcut(&self, shortcut: GlobalShortcut) {
+        let imp = self.imp();
+
+        match shortcut {
+            GlobalShortcut::ToggleVisibility => {
+                let is_visible = !imp.device_visibility_switch.is_active();
+                imp.device_visibility_switch.set_active(is_visible);
+
+                // The window is likely not in sight
+                spawn_notification(
+                    "visibility".into(),
+                    Notification::new(&if is_visible {
+                        gettext("Packet Is Visible")
+                    } else {
+                        gettext("Packet Is Hidden")
+                    })
+                    .body(
+                        if is_visible {
+                            gettext("Nearby devices can see this device")
+                        } else {
+                            gettext("Nearby devices can't see this device")
+                        }
+                        .as_str(),
+                    )
+                    .default_action(None),
+                );
+            }
+            GlobalShortcut::SendClipboard => {
+                glib::spawn_future_local(clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    async move {
+                        this.send_clipboard().await;
+                    }
+                ));
+            }
+            GlobalShortcut::OpenSendPage => {
+                self.present();
+                if imp.manage_files_model.n_items() == 0 {
+                    self.add_files_via_dialog();
+                } else if imp.main_nav_view.visible_page_tag().as_deref()
+                    != Some("manage_files_nav_page")
+                {
+                    imp.main_nav_view.push_by_tag("manage_files_nav_page");
+                }
+            }
+            GlobalShortcut::SendScreenshot => self.send_screenshot(),
+        }
+    }
+
+    /// Gets whatever is in the clipboard ready to be sent. Images and text are
+    /// saved to files first, since only files can be sent.
+    async fn send_clipboard(&self) {
+        let imp = self.imp();
+
+        // Clipboard can only be read while focused on some desktops
+        self.present();
+
+        let clipboard = self.clipboard();
+        let formats = clipboard.formats();
+        let files = if formats.contains_type(gdk::FileList::static_type()) {
+            clipboard
+                .read_value_future(gdk::FileList::static_type(), glib::Priority::DEFAULT)
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|it| Ok(it.get::<gdk::FileList>()?.files()))
+        } else if formats.contains_type(gdk::Texture::static_type()) {
+            async {
+                let texture = clipboard
+                    .read_texture_future()
+                    .await?
+                    .context("No image in clipboard")?;
+                let path = clipboard_file_path("png")?;
+                texture.save_to_png(&path)?;
+                Ok(vec![gio::File::for_path(path)])
+            }
+            .await
+        } else {
+            async {
+                let text = clipboard
+                    .read_text_future()
+                    .await?
+                    .context("Clipboard is empty")?;
+                let path = clipboard_file_path("txt")?;
+                fs_err::write(&path, text.as_str())?;
+                Ok(vec![gio::File::for_path(path)])
+            }
+            .await
+        };
+
+        match files {
+            Ok(files) => {
+                if self.handle_added_files_to_send(&imp.manage_files_model, files) {
+                    self.present_recipients_dialog();
+                }
+            }
+            Err(err) => {
+                tracing::warn!("Failed to read clipboard: {:#}", err);
+                self.add_toast(&gettext("Nothing to send in the clipboard"));
+            }
+        }
+
+        /// A new file to save clipboard contents into, older ones are cleaned
+        /// up along the way.
+        fn clipboard_file_path(extension: &str) -> anyhow::Result<PathBuf> {
+            let dir = glib::user_cache_dir().join("packet").join("clipboard");
+            fs_err::create_dir_all(&dir)?;
+
+            let day_ago =
+                std::time::SystemTime::now() - std::time::Duration::from_secs(24 * 60 * 60);
+            for entry in fs_err::read_dir(&dir)?.filter_map(|it| it.ok()) {
+                let is_old = entry
+                    .metadata()
+                    .and_then(|it| it.modified())
+                    .is_ok_and(|it| it < day_ago);
+                if is_old {
+                    _ = fs_err::remove_file(entry.path());
+                }
+            }
+
+            let timestamp = glib::DateTime::now_local()
+                .and_then(|it| it.format("%Y%m%d-%H%M%S"))
+                .map(|it| it.to_string())
+                .unwrap_or_default();
+            Ok(dir.join(format!("clipboard-{timestamp}.{extension}")))
+        }
+    }
+
+    fn request_background(&self) {
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                let is_run_in_background = this.imp().settings.boolean("run-in-background");
+                if let Some(response) = this.portal_request_background().await {
+                    tracing::debug!(?response, "Background request successful");
+
+                    if !response.auto_start() {
+                        if let Some(app) =
+                            this.application().and_downcast_ref::<PacketApplication>()
+                        {
+                            app.imp().start_in_background.replace(false);
+                        }
+                    }
+                } else if is_run_in_background {
+                    this.add_toast(&gettext("Packet cannot run in the background"));
+                }
+            }
+        ));
+    }
+
+    fn pick_download_folder(&self) {
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                this.select_download_folder().await;
+            }
+        ));
+    }
+
+    /// Returns whether a new download folder was chosen.
+    async fn select_download_folder(&self) -> bool {
+        let imp = self.imp();
+
+        if let Ok(file) = gtk::FileDialog::new()
+            .select_folder_future(
+                imp.obj()
+                    .root()
+                    .and_downcast_ref::<PacketApplicationWindow>(),
+            )
+            .await
+        {
+            // TODO: Maybe format the display path in the preferences?
+            // `Sandbox: Music` or `Music` instead of `/run/user/1000/_/Music` (for mounted paths)
+            // This would require storing the display string in gschema however
+            //
+            // Check whether it's a sandbox path or not by matching the path
+            // against the xattr host path, if it doesn't match, it's sandbox
+            //
+            // Flatpak metadata is available from `/.flatpak-info`, which contains info
+            // about host filesystem paths being available to the app, and much more.
+
+            // Path provided is host path if the app has been granted host access to it via
+            // --filesystem. Otherwise, it's a mounted path.
+            //
+            // Now, there's an issue with the vscode-flatpak extension where while running
+            // the app through it, the path given by FileChooser is always a mounted path.
+            // Leaving this note here so as to not base our logic on this wrong behaviour.
+            let folder_path = file.path().unwrap();
+
+            let display_path = strip_user_home_prefix(&folder_path);
+
+            tracing::debug!(
+                ?folder_path,
+                ?display_path,
+                "Selected custom downloads folder"
+            );
+
+            imp.download_folder_row
+                .set_subtitle(&display_path.to_string_lossy());
+
+            imp.settings
+                .set_string("download-folder", folder_path.to_str().unwrap())
+                .unwrap();
+            self.send_service_command(ServiceCommand::SetDownloadPath(folder_path));
+
+            return true;
+        }
+
+        false
+    }
+
+    fn setup_ui(&self) {
+        self.setup_bottom_bar();
+
+        self.setup_status_pages();
+        self.setup_main_page();
+        self.setup_manage_files_page();
+        self.setup_recipient_page();
+    }
+
+    pub fn present_plugin_success_dialog(&self) {
+        let dialog = adw::AlertDialog::builder()
+            .heading(&gettext("Plugin Installed"))
+            .default_response("done")
+            .build();
+
+        dialog.add_response("done", &gettext("Done"));
+        dialog.set_response_appearance("done", adw::ResponseAppearance::Suggested);
 
         let info_box = gtk::Box::builder()
             .orientation(gtk::Orientation::Vertical)
@@ -1106,7 +1637,7 @@ impl PacketApplicationWindow {
         dialog.present(self.root().as_ref());
     }
 
-    fn present_plugin_error_dialog(&self, extensions_display_dir: &str) {
+    pub fn present_plugin_error_dialog(&self, extensions_display_dir: &str) {
         let dialog = adw::AlertDialog::builder()
             .heading(&gettext("Installation Failed"))
             .default_response("close")
@@ -1144,6 +1675,15 @@ impl PacketApplicationWindow {
         dialog.present(self.root().as_ref());
     }
 
+    pub async fn spawn_send_to_device_receiver(
+        &self,
+        rx: async_channel::Receiver<(String, Vec<String>)>,
+    ) {
+        while let Ok((endpoint_id, files)) = rx.recv().await {
+            widgets::send_files_to_device(self, &endpoint_id, files);
+        }
+    }
+
     pub async fn spawn_send_files_receiver(&self, rx: async_channel::Receiver<Vec<String>>) {
         let imp = self.imp();
 
@@ -1172,7 +1712,9 @@ impl PacketApplicationWindow {
 
         // Clear previous recipients
         imp.send_transfers_id_cache.blocking_lock().clear();
+        imp.send_queue.clear();
         imp.recipient_model.remove_all();
+        imp.recipients_selection_button.set_active(false);
 
         imp.obj().start_mdns_discovery(None);
 
@@ -1180,7 +1722,7 @@ impl PacketApplicationWindow {
         imp.is_recipients_dialog_opened.set(true);
     }
 
-    fn close_recipients_dialog(&self) {
+    pub fn close_recipients_dialog(&self) {
         let imp = self.imp();
 
         if !imp.is_recipients_dialog_opened.get() {
@@ -1214,6 +1756,45 @@ impl PacketApplicationWindow {
                 )));
             }
         ));
+        imp.rqs_error_action_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                let imp = this.imp();
+
+                let Some(action) = imp
+                    .rqs_error
+                    .borrow()
+                    .as_ref()
+                    .and_then(|err| StartupErrorKind::classify(err).action())
+                else {
+                    return;
+                };
+
+                match action {
+                    StartupErrorAction::ChangePort => {
+                        imp.preferences_dialog.present(Some(&this));
+                        imp.static_port_expander.set_enable_expansion(true);
+                        imp.static_port_expander.set_expanded(true);
+                        imp.static_port_entry.grab_focus();
+                    }
+                    StartupErrorAction::PickFolder => {
+                        glib::spawn_future_local(clone!(
+                            #[weak]
+                            this,
+                            async move {
+                                if this.select_download_folder().await {
+                                    this.restart_rqs_service();
+                                }
+                            }
+                        ));
+                    }
+                    StartupErrorAction::OpenDiagnostics => {
+                        widgets::present_diagnostics_dialog(&this);
+                    }
+                }
+            }
+        ));
         imp.rqs_error_retry_button.connect_clicked(clone!(
             #[weak(rename_to = this)]
             self,
@@ -1223,6 +1804,33 @@ impl PacketApplicationWindow {
         ));
     }
 
+    fn present_rqs_error(&self, err: anyhow::Error) {
+        let imp = self.imp();
+
+        let kind = StartupErrorKind::classify(&err);
+        tracing::error!(?kind, "{err:#}");
+
+        imp.rqs_error_status.set_title(&kind.title());
+        imp.rqs_error_status
+            .set_description(Some(&kind.description()));
+
+        let action = kind.action();
+        imp.rqs_error_action_button.set_visible(action.is_some());
+        if let Some(action) = action {
+            imp.rqs_error_action_button.set_label(&action.label());
+            // Only one of the buttons should stand out
+            imp.rqs_error_retry_button.remove_css_class("accent");
+        } else {
+            imp.rqs_error_retry_button.add_css_class("accent");
+        }
+
+        imp.rqs_error.borrow_mut().replace(err);
+
+        imp.root_stack
+            .get()
+            .set_visible_child_name("rqs_error_status_page");
+    }
+
     fn setup_main_page(&self) {
         let imp = self.imp();
 
@@ -1321,7 +1929,10 @@ impl PacketApplicationWindow {
             imp,
             move |_| {
                 imp.is_recipients_dialog_opened.set(false);
-                imp.obj().stop_mdns_discovery();
+                // Deferred sends need to know when their device shows up
+                if !widgets::has_deferred_sends(&imp.obj()) {
+                    imp.obj().stop_mdns_discovery();
+                }
             }
         ));
     }
@@ -1371,6 +1982,68 @@ impl PacketApplicationWindow {
             }
         ));
 
+        imp.recipients_selection_button.connect_toggled(clone!(
+            #[weak]
+            imp,
+            move |button| {
+                if !button.is_active() {
+                    for model_item in imp
+                        .recipient_model
+                        .iter::<SendRequestState>()
+                        .filter_map(|it| it.ok())
+                    {
+                        model_item.set_selected(false);
+                    }
+                }
+            }
+        ));
+        imp.send_to_selected_button.connect_clicked(clone!(
+            #[weak]
+            imp,
+            move |_| {
+                let selected = imp
+                    .recipient_model
+                    .iter::<SendRequestState>()
+                    .filter_map(|it| it.ok())
+                    .filter(|it| it.selected())
+                    .filter(|it| {
+                        !imp.send_queue.is_active(it)
+                            && !imp.send_queue.is_pending(it)
+                            && it.transfer_state() != TransferState::Retrying
+                    })
+                    .collect::<Vec<_>>();
+                imp.recipients_selection_button.set_active(false);
+                if selected.is_empty() {
+                    return;
+                }
+
+                widgets::enqueue_batch_send(&imp.obj(), selected);
+            }
+        ));
+
+        imp.send_later_button.connect_clicked(clone!(
+            #[weak]
+            imp,
+            move |_| {
+                widgets::present_deferred_send_dialog(&imp.obj());
+            }
+        ));
+
+        imp.send_queue_button.connect_clicked(clone!(
+            #[weak]
+            imp,
+            move |_| {
+                widgets::present_send_queue_dialog(&imp.obj());
+            }
+        ));
+        imp.send_queue.model.connect_items_changed(clone!(
+            #[weak]
+            imp,
+            move |model, _, _, _| {
+                imp.send_queue_button.set_visible(model.n_items() > 0);
+            }
+        ));
+
         imp.recipients_help_button
             .action_set_enabled("menu.popup", false);
         imp.recipients_help_button
@@ -1405,6 +2078,7 @@ impl PacketApplicationWindow {
                         .filter_map(|(pos, it)| it.ok().and_then(|it| Some((pos, it))))
                         .filter(|(_, it)| match it.transfer_state() {
                             TransferState::Queued
+                            | TransferState::Retrying
                             | TransferState::RequestedForConsent
                             | TransferState::OngoingTransfer => false,
                             TransferState::AwaitingConsentOrIdle
@@ -1420,12 +2094,13 @@ impl PacketApplicationWindow {
                         let actual_pos = pos - items_removed;
 
                         imp.recipient_model.remove(actual_pos as u32);
+                        imp.send_queue.forget(&obj);
                         let removed_model_item = guard.remove(&obj.endpoint_info().id);
                         items_removed += 1;
 
                         tracing::debug!(
                             endpoint_info = %obj.endpoint_info(),
-                            last_state = ?(obj.transfer_state(), &obj.event().unwrap().msg.as_client().unwrap().state),
+                            last_state = ?(obj.transfer_state(), obj.event().and_then(|it| it.msg.as_client().and_then(|it| it.state.clone()))),
                             model_item_pos = actual_pos,
                             was_model_item_cached = removed_model_item.is_some(),
                             "Removed recipient card"
@@ -1486,6 +2161,137 @@ impl PacketApplicationWindow {
                     .set_label(&gettext("Turn on Bluetooth"));
             }
         }
+
+        self.update_background_status();
+    }
+
+    /// What Packet is up to, for the desktop to show while the window isn't
+    /// around.
+    fn background_status(&self) -> String {
+        let imp = self.imp();
+
+        imp.transfer_inhibitor
+            .description()
+            .unwrap_or_else(|| imp.bottom_bar_caption.label().to_string())
+    }
+
+    /// Updates the status message of the Background portal and the tray icon.
+    fn update_background_status(&self) {
+        let imp = self.imp();
+
+        let status = self.background_status();
+        if let Some(tray) = imp.tray.borrow().clone() {
+            let status = status.clone();
+            let is_visible = imp.device_visibility_switch.is_active();
+            glib::spawn_future_local(async move {
+                if let Err(err) = tray.update(&status, is_visible).await {
+                    tracing::warn!("Failed to update tray icon: {:#}", err);
+                }
+            });
+        }
+
+        let is_in_background =
+            imp.is_background_allowed.get() && imp.settings.boolean("run-in-background");
+        if !is_in_background || *imp.background_status.borrow() == status {
+            return;
+        }
+        imp.background_status.replace(status.clone());
+
+        glib::spawn_future_local(async move {
+            // The portal doesn't take messages longer than this
+            let status = status.chars().take(96).collect::<String>();
+            let result = match BackgroundProxy::new().await {
+                Ok(proxy) => proxy.set_status(&status).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                // Older portals don't support this
+                tracing::debug!("Failed to set background status: {:#}", err);
+            }
+        });
+    }
+
+    fn setup_tray(&self) {
+        let imp = self.imp();
+
+        if let Some(tray) = imp.tray.take() {
+            glib::spawn_future_local(tray.remove());
+        }
+        if !imp.settings.boolean("show-tray-icon") {
+            return;
+        }
+
+        let (action_tx, action_rx) = async_channel::unbounded();
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                let imp = this.imp();
+
+                let tray = match Tray::new(
+                    action_tx,
+                    this.background_status(),
+                    imp.device_visibility_switch.is_active(),
+                )
+                .await
+                {
+                    Ok(tray) => tray,
+                    Err(err) => {
+                        tracing::warn!("Tray icon isn't available: {:#}", err);
+                        this.add_toast(&gettext("Tray icons aren't supported on this desktop"));
+                        return;
+                    }
+                };
+
+                // Turned off while it was being set up
+                if !imp.settings.boolean("show-tray-icon") {
+                    tray.remove().await;
+                    return;
+                }
+                imp.tray.replace(Some(tray));
+                this.update_background_status();
+            }
+        ));
+
+        let this = self.downgrade();
+        glib::spawn_future_local(async move {
+            // Ends once the tray is gone, along with the senders
+            while let Ok(action) = action_rx.recv().await {
+                let Some(this) = this.upgrade() else {
+                    break;
+                };
+                this.activate_tray_action(action);
+            }
+        });
+    }
+
+    fn activate_tray_action(&self, action: TrayAction) {
+        let imp = self.imp();
+
+        match action {
+            TrayAction::ToggleWindow => {
+                let can_hide =
+                    imp.is_background_allowed.get() && imp.settings.boolean("run-in-background");
+                if self.is_visible() && can_hide {
+                    self.set_visible(false);
+                } else {
+                    self.present();
+                }
+            }
+            TrayAction::ToggleVisibility => {
+                imp.device_visibility_switch
+                    .set_active(!imp.device_visibility_switch.is_active());
+            }
+            TrayAction::SendFiles => {
+                self.present();
+                self.add_files_via_dialog();
+            }
+            TrayAction::Quit => {
+                if let Some(app) = self.application() {
+                    app.activate_action("quit", None);
+                }
+            }
+        }
     }
 
     fn setup_bottom_bar(&self) {
@@ -1531,14 +2337,8 @@ impl PacketApplicationWindow {
                     rqs_lib::Visibility::Invisible
                 };
 
-                glib::spawn_future_local(async move {
-                    imp.rqs
-                        .lock()
-                        .await
-                        .as_mut()
-                        .unwrap()
-                        .change_visibility(visibility);
-                });
+                imp.obj()
+                    .send_service_command(ServiceCommand::SetVisibility(visibility));
             }
         ));
     }
@@ -1659,41 +2459,14 @@ impl PacketApplicationWindow {
         (filtered_files, is_already_in_model)
     }
 
-    fn start_mdns_discovery(&self, force: Option<bool>) {
+    pub fn start_mdns_discovery(&self, force: Option<bool>) {
         let imp = self.imp();
 
         if (force.is_some() && force.unwrap_or_default())
             || (force.is_none() && !imp.is_mdns_discovery_on.get())
         {
             tracing::info!(?force, "Starting mDNS discovery task");
-
-            tokio_runtime().spawn(clone!(
-                #[weak(rename_to = mdns_discovery_broadcast_tx)]
-                imp.mdns_discovery_broadcast_tx,
-                #[weak(rename_to = rqs)]
-                imp.rqs,
-                async move {
-                    _ = rqs
-                        .lock()
-                        .await
-                        .as_mut()
-                        .unwrap()
-                        .discovery(
-                            mdns_discovery_broadcast_tx
-                                .lock()
-                                .await
-                                .as_ref()
-                                .unwrap()
-                                .clone(),
-                        )
-                        .inspect_err(|err| {
-                            tracing::error!(
-                                err = format!("{err:#}"),
-                                "Failed to start mDNS discovery task"
-                            )
-                        });
-                }
-            ));
+            self.send_service_command(ServiceCommand::StartDiscovery);
 
             imp.is_mdns_discovery_on.replace(true);
         }
@@ -1703,30 +2476,64 @@ impl PacketApplicationWindow {
         let imp = self.imp();
 
         if imp.is_mdns_discovery_on.get() {
-            tokio_runtime().spawn(clone!(
-                #[weak(rename_to = rqs)]
-                imp.rqs,
-                async move {
-                    rqs.lock().await.as_mut().unwrap().stop_discovery();
-                }
-            ));
+            self.send_service_command(ServiceCommand::StopDiscovery);
 
             imp.is_mdns_discovery_on.replace(false);
         }
     }
 
-    fn is_no_file_being_send(&self) -> bool {
+    pub fn update_send_to_selected_button(&self) {
+        let imp = self.imp();
+
+        let is_any_selected = imp
+            .recipient_model
+            .iter::<SendRequestState>()
+            .filter_map(|it| it.ok())
+            .any(|it| it.selected());
+        imp.send_to_selected_button.set_sensitive(is_any_selected);
+    }
+
+    /// Sends a command to the service task, if the service is around.
+    pub fn send_service_command(&self, command: ServiceCommand) {
+        match self.imp().service.borrow().as_ref() {
+            Some(service) => service.send(command),
+            None => tracing::warn!(?command, "Service isn't running, dropping command"),
+        }
+    }
+
+    pub fn service_port(&self) -> Option<u32> {
+        self.imp()
+            .service
+            .borrow()
+            .as_ref()
+            .and_then(|it| it.port_number())
+    }
+
+    pub fn is_no_file_being_send(&self) -> bool {
         let imp = self.imp();
 
+        if widgets::is_deferred_send_active(self) {
+            return false;
+        }
+
         for model_item in imp
             .recipient_model
             .iter::<SendRequestState>()
             .filter_map(|it| it.ok())
         {
+            // Waiting in the send queue, or to be retried
+            if matches!(
+                model_item.transfer_state(),
+                objects::TransferState::Queued | objects::TransferState::Retrying
+            ) {
+                return false;
+            }
+            let Some(event) = model_item.event() else {
+                continue;
+            };
+
             use rqs_lib::TransferState;
-            match model_item
-                .event()
-                .unwrap()
+            match event
                 .msg
                 .as_client()
                 .unwrap()
@@ -1777,21 +2584,21 @@ impl PacketApplicationWindow {
             }
         }
 
-        let handle = tokio_runtime().spawn(clone!(
-            #[weak(rename_to = rqs)]
-            imp.rqs,
-            async move {
-                {
-                    let mut rqs_guard = rqs.lock().await;
-                    if let Some(rqs) = rqs_guard.as_mut() {
-                        rqs.stop().await;
-                        tracing::info!("Stopped RQS service");
-                    }
-                }
-            }
-        ));
+        // Whatever was being sent is gone along with the service
+        imp.active_deferred_send.replace(None);
+        if let Some(app) = self.application() {
+            imp.transfer_inhibitor.release(&app);
+        }
+        self.update_background_status();
+        imp.nearby_endpoints.borrow_mut().clear();
+        widgets::publish_devices(self);
 
-        handle
+        let service = imp.service.take();
+        tokio_runtime().spawn(async move {
+            if let Some(service) = service {
+                service.stop().await;
+            }
+        })
     }
 
     fn setup_connection_monitors(&self) {
@@ -1911,6 +2718,17 @@ impl PacketApplicationWindow {
                         let action = action_stream.next().await.context("Stream exhausted")?;
                         tracing::info!(action_name = ?action.name(), id = action.id(), params = ?action.parameter(), "Notification action received");
 
+                        if action.name() == "deferred-device-confirm" {
+                            if let Some(param) = action.parameter().first().and_then(|it| {
+                                it.downcast_ref::<String>()
+                                    .inspect_err(|err| tracing::warn!("{err:#}"))
+                                    .ok()
+                            }) {
+                                widgets::confirm_deferred_device(&imp.obj(), &param);
+                            }
+                            continue;
+                        }
+
                         if let Some(cached_transfer) = imp.receive_transfer_cache.lock().await.as_mut() {
                             match action.name() {
                                 "consent-accept" => {
@@ -1964,9 +2782,158 @@ impl PacketApplicationWindow {
         ));
     }
 
+    fn spawn_service_events_receiver(&self, rx: async_channel::Receiver<ServiceEvent>) {
+        let handle = glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                while let Ok(event) = rx.recv().await {
+                    match event {
+                        // Already logged by the service task
+                        ServiceEvent::CommandFailed { command, .. } => {
+                            this.add_toast(&match command {
+                                "send" => gettext("Couldn't send files"),
+                                "start-discovery" | "restart-discovery" => {
+                                    gettext("Couldn't look for nearby devices")
+                                }
+                                _ => gettext("Something went wrong, see the logs for details"),
+                            });
+                        }
+                    }
+                }
+            }
+        ));
+        self.imp()
+            .looping_async_tasks
+            .borrow_mut()
+            .push(LoopingTaskHandle::Glib(handle));
+    }
+
+    fn setup_service_supervisor(&self) {
+        let imp = self.imp();
+
+        let (tx, rx) = async_channel::unbounded();
+        tokio_runtime().spawn(imp.supervisor.clone().run(tx));
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                while let Ok(recovery) = rx.recv().await {
+                    let imp = this.imp();
+
+                    match recovery {
+                        Recovery::Resync { task } => {
+                            // The transfers that were going are ended by the
+                            // transfer events task itself. Discovered endpoints
+                            // are only announced once however.
+                            if task == DISCOVERY_EVENTS_TASK && imp.is_mdns_discovery_on.get() {
+                                this.send_service_command(ServiceCommand::RestartDiscovery);
+                            }
+
+                            imp.supervisor.set_health(ServiceHealth::Healthy);
+                        }
+                        Recovery::Restart => {
+                            tracing::warn!("Restarting the service after a task failure");
+                            this.restart_rqs_service();
+                        }
+                        Recovery::GiveUp { fault } => {
+                            _ = this.stop_rqs_service().await;
+                            this.present_rqs_error(anyhow!(
+                                "The service kept failing, last failure: {fault:?}"
+                            ));
+                        }
+                    }
+                }
+            }
+        ));
+    }
+
+    async fn run_post_transfer_hooks(
+        &self,
+        channel_message: &rqs_lib::channel::ChannelMessage,
+        device_name: String,
+    ) {
+        let imp = self.imp();
+
+        let client_msg = channel_message.msg.as_client().unwrap();
+        let direction = event_stream::Direction::from_kind(&client_msg.kind);
+        let event_msg = objects::ChannelMessage(channel_message.clone());
+
+        let payload_kind = event_stream::PayloadKind::from_metadata(client_msg.metadata.as_ref());
+        let files = match direction {
+            event_stream::Direction::Inbound
+                if payload_kind != event_stream::PayloadKind::Files =>
+            {
+                vec![]
+            }
+            event_stream::Direction::Inbound => {
+                // Not necessarily saved under their own names
+                let received_files = imp.received_files.borrow_mut().remove(&channel_message.id);
+                match received_files {
+                    Some(received_files) => async {
+                        let received_files = received_files.await?;
+                        tokio_runtime()
+                            .spawn_blocking(move || {
+                                received_files
+                                    .destinations()
+                                    .into_iter()
+                                    .filter_map(|(_, path)| path)
+                                    .collect()
+                            })
+                            .await
+                    }
+                    .await
+                    .unwrap_or_default(),
+                    None => {
+                        tracing::warn!(
+                            id = channel_message.id,
+                            "Don't know where the received files were saved"
+                        );
+                        vec![]
+                    }
+                }
+            }
+            event_stream::Direction::Outbound => {
+                let model_item_files = imp
+                    .send_transfers_id_cache
+                    .lock()
+                    .await
+                    .get(&channel_message.id)
+                    .map(|it| it.imp().files.borrow().clone());
+                model_item_files
+                    .or_else(|| event_msg.files().cloned())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect()
+            }
+        };
+
+        hooks::run_post_transfer_hooks(hooks::FinishedTransfer {
+            id: channel_message.id.clone(),
+            direction,
+            device_name,
+            payload_kind,
+            files,
+            text: event_msg.transferred_text_data().map(|it| it.0),
+        });
+    }
+
+    fn setup_event_stream(&self) {
+        let event_stream = self.imp().event_stream.clone();
+        tokio_runtime().spawn(async move {
+            if let Err(err) = event_stream.serve().await {
+                tracing::warn!("Couldn't serve transfer events: {err:#}");
+            }
+        });
+    }
+
     fn setup_rqs_service(&self) -> glib::JoinHandle<()> {
         let imp = self.imp();
 
+        imp.supervisor.begin_generation();
+
         let (tx, rx) = async_channel::bounded(1);
 
         let is_device_visible = imp.settings.boolean("device-visibility");
@@ -1989,6 +2956,8 @@ impl PacketApplicationWindow {
                 "Starting RQS service"
             );
 
+            let download_folder_check = errors::ensure_download_folder_writable(&download_path);
+
             let mut rqs = rqs_lib::RQS::new(
                 if is_device_visible {
                     rqs_lib::Visibility::Visible
@@ -2000,7 +2969,10 @@ impl PacketApplicationWindow {
                 Some(device_name.to_string()),
             );
 
-            let rqs_run_result = rqs.run().await;
+            let rqs_run_result = match download_folder_check {
+                Ok(_) => QuickShareBackend::run(&mut rqs).await,
+                Err(err) => Err(err.into()),
+            };
             tx.send((rqs, rqs_run_result)).await.unwrap();
         });
         let rqs_init_handle = glib::spawn_future_local(clone!(
@@ -2010,59 +2982,109 @@ impl PacketApplicationWindow {
                 let (rqs, rqs_run_result) = rx.recv().await.unwrap();
 
                 tracing::debug!("Fetched RQS instance after run()");
-                *imp.rqs.lock().await = Some(rqs);
                 let (mdns_discovery_broadcast_tx, _) =
                     tokio::sync::broadcast::channel::<rqs_lib::EndpointInfo>(10);
-                *imp.mdns_discovery_broadcast_tx.lock().await = Some(mdns_discovery_broadcast_tx);
+                *imp.mdns_discovery_broadcast_tx.lock().await =
+                    Some(mdns_discovery_broadcast_tx.clone());
+
+                let (file_sender, rqs_run_result) = match rqs_run_result {
+                    Ok(BackendChannels {
+                        file_sender,
+                        ble_receiver,
+                    }) => (Some(file_sender), Ok(ble_receiver)),
+                    Err(err) => (None, Err(err)),
+                };
+
+                // The service is handed over to its own task even if it failed
+                // to start, so that it can still be stopped
+                let (events_tx, events_rx) = async_channel::unbounded();
+                imp.service.replace(Some(ServiceHandle::spawn(
+                    rqs,
+                    file_sender,
+                    mdns_discovery_broadcast_tx,
+                    events_tx,
+                )));
+                imp.obj().spawn_service_events_receiver(events_rx);
 
                 match rqs_run_result {
-                    Ok((file_sender, ble_receiver)) => {
-                        *imp.file_sender.lock().await = Some(file_sender);
+                    Ok(ble_receiver) => {
                         *imp.ble_receiver.lock().await = Some(ble_receiver);
 
                         imp.root_stack.get().set_visible_child_name("main_page");
 
                         spawn_rqs_receiver_tasks(&imp);
+                        imp.supervisor.set_health(ServiceHealth::Healthy);
+
+                        widgets::resume_deferred_sends(&imp.obj());
                     }
                     Err(err) => {
-                        let err = err.context("Failed to setup Packet");
-                        tracing::error!("{err:#}");
-                        imp.rqs_error.borrow_mut().replace(err);
-
-                        imp.root_stack
-                            .get()
-                            .set_visible_child_name("rqs_error_status_page");
+                        imp.supervisor.set_health(ServiceHealth::Failed);
+                        imp.obj()
+                            .present_rqs_error(err.context("Failed to setup Packet"));
                     }
                 };
             }
         ));
 
         fn spawn_rqs_receiver_tasks(imp: &imp::PacketApplicationWindow) {
-            let (tx, rx) = async_channel::bounded(1);
-            let handle = tokio_runtime().spawn(clone!(
-                #[weak(rename_to = rqs)]
-                imp.rqs,
-                async move {
-                    let mut rx = rqs
-                        .lock()
-                        .await
-                        .as_ref()
-                        .expect("State must be set")
-                        .message_sender
-                        .subscribe();
+            use tokio::sync::broadcast::error::RecvError;
 
+            let service = imp.service.borrow().clone().expect("State must be set");
+
+            let (tx, rx) = async_channel::bounded(1);
+            let mut messages_rx = service.subscribe_messages();
+            let event_stream = imp.event_stream.clone();
+            let handle = imp.supervisor.spawn(TRANSFER_EVENTS_TASK, {
+                let service = service.clone();
+                move |monitor| async move {
+                    let mut active_transfers = ActiveTransfers::default();
                     loop {
-                        match rx.recv().await {
+                        let deadline = active_transfers.next_deadline();
+                        let received = tokio::select! {
+                            received = messages_rx.recv() => received,
+                            _ = tokio::time::sleep_until(
+                                deadline.unwrap_or_else(Instant::now).into()
+                            ), if deadline.is_some() => {
+                                for channel_message in active_transfers.expired(Instant::now()) {
+                                    tracing::warn!(
+                                        id = channel_message.id,
+                                        "Lost track of transfer, taking it as disconnected"
+                                    );
+                                    let _busy = monitor.busy();
+                                    event_stream.publish_message(&channel_message);
+                                    tx.send(channel_message)
+                                        .await
+                                        .map_err(|_| TaskError::Closed)?;
+                                }
+                                continue;
+                            }
+                        };
+
+                        match received {
                             Ok(channel_message) => {
-                                tx.send(channel_message).await.unwrap();
+                                let _busy = monitor.busy();
+                                active_transfers.update(&channel_message);
+                                event_stream.publish_message(&channel_message);
+                                tx.send(channel_message)
+                                    .await
+                                    .map_err(|_| TaskError::Closed)?;
                             }
-                            Err(err) => {
-                                tracing::error!("{err:#}")
+                            Err(RecvError::Lagged(skipped)) => {
+                                monitor.lagged(skipped);
+                                // The events that ended them might be among
+                                // the lost ones
+                                for id in active_transfers.events_lost(Instant::now()) {
+                                    service.send(ServiceCommand::TransferAction {
+                                        id,
+                                        action: rqs_lib::channel::TransferAction::TransferCancel,
+                                    });
+                                }
                             }
+                            Err(RecvError::Closed) => return Err(TaskError::Closed),
                         };
                     }
                 }
-            ));
+            });
             imp.looping_async_tasks
                 .borrow_mut()
                 .push(LoopingTaskHandle::Tokio(handle));
@@ -2071,9 +3093,9 @@ impl PacketApplicationWindow {
                 #[weak]
                 imp,
                 async move {
-                    loop {
-                        let channel_message = rx.recv().await.unwrap();
-
+                    // Stops once the forwarding task is gone, the supervisor
+                    // takes care of the rest
+                    while let Ok(channel_message) = rx.recv().await {
                         if channel_message.msg.as_client().is_none() {
                             // Ignore library messages
                             continue;
@@ -2084,6 +3106,57 @@ impl PacketApplicationWindow {
                         let id = &channel_message.id;
                         let client_msg = channel_message.msg.as_client().unwrap();
 
+                        let device_name = match client_msg.kind {
+                            rqs_lib::channel::TransferKind::Inbound => {
+                                objects::ChannelMessage(channel_message.clone()).device_name()
+                            }
+                            rqs_lib::channel::TransferKind::Outbound => {
+                                let model_item_name = imp
+                                    .send_transfers_id_cache
+                                    .lock()
+                                    .await
+                                    .get(id)
+                                    .map(|it| it.device_name());
+                                model_item_name
+                                    .or_else(|| {
+                                        imp.nearby_endpoints
+                                            .borrow()
+                                            .get(id)
+                                            .and_then(|it| it.name.clone())
+                                    })
+                                    .unwrap_or_else(|| gettext("Unknown device"))
+                            }
+                        };
+
+                        // Keep the session from suspending while files are moving
+                        if let Some(app) = imp.obj().application()
+                            && imp.transfer_inhibitor.handle_event(
+                                &app,
+                                imp.obj().upcast_ref(),
+                                &channel_message,
+                                device_name.clone(),
+                            )
+                        {
+                            imp.obj().update_background_status();
+                        }
+
+                        widgets::handle_webhook_event(&imp.obj(), &channel_message, &device_name);
+
+                        if matches!(client_msg.state, Some(rqs_lib::TransferState::Finished)) {
+                            glib::spawn_future_local(clone!(
+                                #[weak(rename_to = this)]
+                                imp.obj(),
+                                #[strong]
+                                channel_message,
+                                #[strong]
+                                device_name,
+                                async move {
+                                    this.run_post_transfer_hooks(&channel_message, device_name)
+                                        .await;
+                                }
+                            ));
+                        }
+
                         use rqs_lib::TransferState;
                         match client_msg
                             .state
@@ -2150,6 +3223,11 @@ impl PacketApplicationWindow {
                                     }
                                     rqs_lib::channel::TransferKind::Outbound => {
                                         // Send
+                                        widgets::handle_deferred_send_event(
+                                            &imp.obj(),
+                                            &channel_message,
+                                        );
+
                                         let send_transfers_id_cache =
                                             imp.send_transfers_id_cache.lock().await;
 
@@ -2173,10 +3251,11 @@ impl PacketApplicationWindow {
             // Discover the devices to send file transfer requests to
             // The Sender used in RQS::discovery()
             let (tx, rx) = async_channel::bounded(1);
-            let handle = tokio_runtime().spawn(clone!(
-                #[weak(rename_to = mdns_discovery_broadcast_tx)]
-                imp.mdns_discovery_broadcast_tx,
-                async move {
+            let mdns_discovery_broadcast_tx = imp.mdns_discovery_broadcast_tx.clone();
+            let event_stream = imp.event_stream.clone();
+            let handle = imp
+                .supervisor
+                .spawn(DISCOVERY_EVENTS_TASK, move |monitor| async move {
                     let mdns_discovery_broadcast_tx = mdns_discovery_broadcast_tx
                         .lock()
                         .await
@@ -2189,18 +3268,17 @@ impl PacketApplicationWindow {
                         match mdns_discovery_rx.recv().await {
                             Ok(endpoint_info) => {
                                 tracing::trace!(?endpoint_info, "Processing endpoint");
-                                tx.send(endpoint_info).await.unwrap();
-                            }
-                            Err(err) => {
-                                tracing::error!(
-                                    err = format!("{err:#}"),
-                                    "mDNS discovery receiver"
-                                );
+                                let _busy = monitor.busy();
+                                event_stream.publish(Event::from_endpoint(&endpoint_info));
+                                tx.send(endpoint_info)
+                                    .await
+                                    .map_err(|_| TaskError::Closed)?;
                             }
+                            Err(RecvError::Lagged(skipped)) => monitor.lagged(skipped),
+                            Err(RecvError::Closed) => return Err(TaskError::Closed),
                         }
                     }
-                }
-            ));
+                });
             imp.looping_async_tasks
                 .borrow_mut()
                 .push(LoopingTaskHandle::Tokio(handle));
@@ -2211,7 +3289,10 @@ impl PacketApplicationWindow {
                 async move {
                     loop {
                         {
-                            let endpoint_info = rx.recv().await.unwrap();
+                            let Ok(endpoint_info) = rx.recv().await else {
+                                break;
+                            };
+                            widgets::handle_deferred_endpoint(&imp.obj(), &endpoint_info);
 
                             let mut send_transfers_id_cache_guard =
                                 imp.send_transfers_id_cache.lock().await;
@@ -2240,38 +3321,21 @@ impl PacketApplicationWindow {
                 .borrow_mut()
                 .push(LoopingTaskHandle::Glib(handle));
 
-            let handle = tokio_runtime().spawn(clone!(
-                #[weak(rename_to = rqs)]
-                imp.rqs,
-                async move {
-                    let mut visibility_receiver = rqs
-                        .lock()
-                        .await
-                        .as_ref()
-                        .expect("State must be set")
-                        .visibility_sender
-                        .lock()
-                        .unwrap()
-                        .subscribe();
-
+            let mut visibility_receiver = service.subscribe_visibility();
+            let handle = imp
+                .supervisor
+                .spawn(VISIBILITY_EVENTS_TASK, move |_| async move {
                     loop {
-                        match visibility_receiver.changed().await {
-                            Ok(_) => {
-                                // FIXME: Update visibility in UI, not used for now
-                                // since visibility is not being set from outside
-                                let visibility = visibility_receiver.borrow_and_update();
-                                tracing::debug!(?visibility, "Visibility change");
-                            }
-                            Err(err) => {
-                                tracing::error!(
-                                    err = format!("{err:#}"),
-                                    "Visibility watcher receiver"
-                                );
-                            }
+                        if visibility_receiver.changed().await.is_err() {
+                            return Err(TaskError::Closed);
                         }
+
+                        // FIXME: Update visibility in UI, not used for now
+                        // since visibility is not being set from outside
+                        let visibility = visibility_receiver.borrow_and_update();
+                        tracing::debug!(?visibility, "Visibility change");
                     }
-                }
-            ));
+                });
             imp.looping_async_tasks
                 .borrow_mut()
                 .push(LoopingTaskHandle::Tokio(handle));
@@ -2282,34 +3346,38 @@ impl PacketApplicationWindow {
             // since that resets the ble receiver and other stuff, and here the
             // ble receiver is set to whichever one is in the Window state at the
             // time of setting up the task.
-            let handle = tokio_runtime().spawn(clone!(
-                #[weak(rename_to = ble_receiver)]
-                imp.ble_receiver,
-                async move {
-                    let mut ble_receiver =
-                        ble_receiver.lock().await.as_ref().unwrap().resubscribe();
+            let ble_receiver = imp.ble_receiver.clone();
+            let handle = imp.supervisor.spawn(BLE_EVENTS_TASK, move |_| async move {
+                let mut ble_receiver = ble_receiver
+                    .lock()
+                    .await
+                    .as_ref()
+                    .context("BLE receiver isn't set")?
+                    .resubscribe();
 
-                    // let mut last_sent = std::time::Instant::now() - std::time::Duration::from_secs(120);
-                    loop {
-                        match ble_receiver.recv().await {
-                            Ok(_) => {
-                                // let is_visible = device_visibility_switch.is_active();
+                // let mut last_sent = std::time::Instant::now() - std::time::Duration::from_secs(120);
+                loop {
+                    match ble_receiver.recv().await {
+                        Ok(_) => {
+                            // let is_visible = device_visibility_switch.is_active();
 
-                                // FIXME: The task is for the "A nearby device is sharing" feature
-                                // where you're given an option to make yourself temporarily visible
+                            // FIXME: The task is for the "A nearby device is sharing" feature
+                            // where you're given an option to make yourself temporarily visible
 
-                                // tracing::debug!("Received BLE event, show a \"A nearby device is sharing\" notification here")
-                            }
-                            Err(err) => {
-                                tracing::error!(
-                                    err = format!("{err:#}"),
-                                    "Couldn't receive BLE event"
-                                );
-                            }
+                            // tracing::debug!("Received BLE event, show a \"A nearby device is sharing\" notification here")
+                        }
+                        // Nothing to catch up on, the events only signal
+                        // that some device is sharing nearby
+                        Err(RecvError::Lagged(_)) => {}
+                        Err(RecvError::Closed) => {
+                            // BLE is optional, e.g. there might be no adapter,
+                            // so this isn't worth restarting the service over
+                            tracing::info!("BLE advertisement receiver closed");
+                            std::future::pending::<()>().await;
                         }
                     }
                 }
-            ));
+            });
             imp.looping_async_tasks
                 .borrow_mut()
                 .push(LoopingTaskHandle::Tokio(handle));